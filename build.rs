@@ -0,0 +1,34 @@
+//! Build provenance for `--version --verbose`: target triple, enabled cargo features, and git commit.
+
+use std::process::Command;
+
+fn main() {
+        let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+        let mut features: Vec<String> = std::env::vars().filter_map(|(key, _)| {
+                                                                key.strip_prefix("CARGO_FEATURE_")
+                                                                   .map(|name| name.to_lowercase().replace('_', "-"))
+                                                        })
+                                                        .collect();
+        features.sort();
+        let features = match features.is_empty() {
+                true => "none".to_string(),
+                false => features.join(","),
+        };
+        let commit = git(&["rev-parse", "--short=12", "HEAD"]).map_or_else(|| "unknown".to_string(), |commit| {
+                let is_dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty());
+                if is_dirty { format!("{}-dirty", commit) } else { commit }
+        });
+        println!("cargo:rustc-env=RENAME_FILES_BUILD_TARGET={}", target);
+        println!("cargo:rustc-env=RENAME_FILES_BUILD_FEATURES={}", features);
+        println!("cargo:rustc-env=RENAME_FILES_BUILD_COMMIT={}", commit);
+        println!("cargo:rustc-env=RENAME_FILES_BUILD_PROFILE={}", std::env::var("PROFILE").unwrap_or_default());
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/index");
+        println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// Trimmed stdout of a successful git command.  `None` outside a git checkout (e.g. a crates.io build).
+fn git(args: &[&str]) -> Option<String> {
+        let output = Command::new("git").args(args).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,45 @@
+//! Planner fuzz target: any plan without conflicts must carry out cleanly on its virtual tree, whichever filesystem
+//! flavor that tree has.
+//!
+//! `cargo +nightly fuzz run planner`
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rename_files::{fuzz::{FsFlavor, PlanOptions, VirtualFs, conflicts_on, plan_on, simulate_on},
+                   matcher::Matcher,
+                   transform::Transform,
+                   validate::NameRules};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+        names:                Vec<String>,
+        flavor:               u8,
+        pattern:              String,
+        rep:                  String,
+        is_rename_to:         bool,
+        allow_bare_ext:       bool,
+        allow_dotfile_change: bool,
+        auto_trim:            bool,
+}
+
+fuzz_target!(|input: Input| {
+        let Ok(matcher) = Matcher::new(&input.pattern) else { return };
+        let transform = match input.is_rename_to {
+                true => Transform::rename_to(&input.rep),
+                false => Transform::replace(&input.rep),
+        };
+        let rules = NameRules { allow_bare_ext:       input.allow_bare_ext,
+                                allow_dotfile_change: input.allow_dotfile_change,
+                                auto_trim:            input.auto_trim, };
+        let opts = PlanOptions { matcher, transform, rules };
+        let flavor = [FsFlavor::POSIX, FsFlavor::WINDOWS, FsFlavor::MACOS][usize::from(input.flavor % 3)];
+        let fs = VirtualFs::new(flavor, &input.names);
+        let planned = plan_on(&fs, &opts);
+        if conflicts_on(&fs, &planned).is_empty() {
+                if let Err(e) = simulate_on(fs, &planned) {
+                        panic!("conflict-free plan failed on {:?}: {}\n{:#?}", flavor, e, planned.entries);
+                }
+        }
+});
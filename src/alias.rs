@@ -0,0 +1,132 @@
+//! Command aliases from the config file, expanded as if typed.
+//!
+//! ```toml
+//! [alias]
+//! shots = "'^IMG_(\d+)' --rep 'shot_${1}' --recurse"
+//! ```
+//! `rename_files shots --preview` then runs `rename_files '^IMG_(\d+)' --rep 'shot_${1}' --recurse --preview`.
+//!
+//! Only the first argument (after a leading `--config PATH`, if any) is checked, and only against the user config
+//! (`--config`, `$RENAME_FILES_CONFIG`, or the default location); project-local files cannot define aliases.
+//! Expansion is not recursive.
+
+use std::{ffi::OsString, io::Write};
+
+use owo_colors::OwoColorize;
+
+use crate::{config::Config, error::Result, output::Output};
+
+/// Names that can't be aliases, as they are subcommands.
+pub const RESERVED: &[&str] = &["alias", "batch", "bench", "daemon", "features", "help", "integrate", "recipe"];
+
+/// Replaces an alias in first-argument position (after the program name and any `--config`) with its expansion.
+pub fn expand(raw: Vec<OsString>, config: &Config) -> Result<Vec<OsString>> {
+        let position = match raw.get(1).and_then(|arg| arg.to_str()) {
+                Some("--config") => 3,
+                Some(arg) if arg.starts_with("--config=") => 2,
+                _ => 1,
+        };
+        let Some(expansion) =
+                raw.get(position).and_then(|first| first.to_str()).and_then(|first| config.aliases.get(first))
+        else {
+                return Ok(raw);
+        };
+        tracing::debug!("Expanding alias {:?} to {:?}", raw[position], expansion);
+        let mut expanded = raw[..position].to_vec();
+        expanded.extend(split_words(expansion)?.into_iter().map(OsString::from));
+        expanded.extend(raw.into_iter().skip(position + 1));
+        Ok(expanded)
+}
+
+/// Config path given on the command line (`--config PATH` or `--config=PATH`), if any.
+pub fn explicit_config(raw: &[OsString]) -> Option<OsString> {
+        raw.iter().enumerate().find_map(|(i, arg)| match arg.to_str() {
+                                      Some("--config") => raw.get(i + 1).cloned(),
+                                      Some(arg) => arg.strip_prefix("--config=").map(OsString::from),
+                                      None => None,
+                              })
+}
+
+/// Splits an alias definition into arguments, shell style: whitespace separated, with `'...'` (literal) and
+/// `"..."` (`\` escapes `"` and `\`) quoting.
+pub fn split_words(text: &str) -> Result<Vec<String>> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+                match c {
+                        c if c.is_whitespace() => words.extend(word.take()),
+                        '\'' => {
+                                let word = word.get_or_insert_with(String::new);
+                                loop {
+                                        match chars.next() {
+                                                Some('\'') => break,
+                                                Some(c) => word.push(c),
+                                                None => return Err(format!("Unclosed ' in {:?}", text).into()),
+                                        }
+                                }
+                        }
+                        '"' => {
+                                let word = word.get_or_insert_with(String::new);
+                                loop {
+                                        match chars.next() {
+                                                Some('"') => break,
+                                                Some('\\') => match chars.next() {
+                                                        Some(c @ ('"' | '\\')) => word.push(c),
+                                                        Some(c) => word.extend(['\\', c]),
+                                                        None => {
+                                                                return Err(format!("Unclosed \" in {:?}", text).into());
+                                                        }
+                                                },
+                                                Some(c) => word.push(c),
+                                                None => return Err(format!("Unclosed \" in {:?}", text).into()),
+                                        }
+                                }
+                        }
+                        c => word.get_or_insert_with(String::new).push(c),
+                }
+        }
+        words.extend(word);
+        Ok(words)
+}
+
+/// `alias list`: every alias and its expansion.
+#[tracing::instrument]
+pub fn list(config: &Config) -> Result<()> {
+        let mut out = Output::stdout();
+        for (name, expansion) in &config.aliases {
+                writeln!(out, "{} = {}", name.cyan(), expansion)?;
+        }
+        out.flush()?;
+        Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_split_words() {
+                assert_eq!(split_words(r"'^IMG_(\d+)' --rep 'shot_${1}' --recurse").unwrap(), vec![r"^IMG_(\d+)",
+                                                                                                   "--rep",
+                                                                                                   "shot_${1}",
+                                                                                                   "--recurse"]);
+                assert_eq!(split_words(r#"a"b c"'d' "\"\\" ''"#).unwrap(), vec!["ab cd", r#""\"#, ""]);
+                assert!(split_words("'open").is_err());
+        }
+
+        #[test]
+        fn test_expand() {
+                let config = Config::parse("[alias]\nshots = \"'^IMG_' --rep shot_\"\n").unwrap();
+                let raw = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+                assert_eq!(expand(raw(&["rf", "shots", "-p"]), &config).unwrap(),
+                           raw(&["rf", "^IMG_", "--rep", "shot_", "-p"]));
+                assert_eq!(expand(raw(&["rf", "--config", "c.toml", "shots"]), &config).unwrap(),
+                           raw(&["rf", "--config", "c.toml", "^IMG_", "--rep", "shot_"]));
+                assert_eq!(expand(raw(&["rf", "-p", "shots"]), &config).unwrap(), raw(&["rf", "-p", "shots"]));
+                assert_eq!(explicit_config(&raw(&["rf", "--config=a.toml"])), Some("a.toml".into()));
+                assert_eq!(explicit_config(&raw(&["rf", "--config", "b.toml", "x"])), Some("b.toml".into()));
+        }
+}
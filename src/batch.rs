@@ -0,0 +1,106 @@
+//! `batch` subcommand: many operations, read from stdin as JSON lines, in one process.
+//!
+//! Saves orchestration tools from spawning the binary once per operation: the config is read once, and each
+//! operation then runs through the same planner and guards as a normal invocation.  Operations share a
+//! [`RunContext`]: project configs are merged once per set of roots, recipes discovered once, and a pattern compiled
+//! once however many operations use it.
+//!
+//! # Example:
+//! ```text
+//! {"pattern": "^IMG_(\\d+)", "replacement": "shot_${1}", "root": "photos", "recurse": true}
+//! {"pattern": "\\.jpeg$", "replacement": ".jpg"}
+//! ```
+//! `root` defaults to the current directory; an operation without `replacement` only lists matches.
+//! A failing operation is reported and the rest still run.
+
+use std::{io::BufRead, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{Args, DEFAULT_WALK_BUFFER, config::Config, context::RunContext, error::Result, run_in};
+
+/// Options applied to every operation of a batch.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct BatchArgs {
+        /// Preview every operation; rename nothing.
+        #[arg(short, long)]
+        pub(crate) preview: bool,
+
+        /// Confirm renames (required while safe mode is on, as for single runs).
+        #[arg(short, long)]
+        pub(crate) yes: bool,
+}
+
+/// One operation (one JSON line).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Operation {
+        pub pattern:     String,
+        #[serde(default)]
+        pub replacement: Option<String>,
+        #[serde(default)]
+        pub root:        Option<PathBuf>,
+        #[serde(default)]
+        pub recurse:     bool,
+        #[serde(default)]
+        pub preview:     bool,
+}
+
+impl Operation {
+        /// The equivalent command line arguments.
+        pub(crate) fn to_args(&self, batch_args: &BatchArgs, config_path: Option<&PathBuf>) -> Args {
+                Args { regex: self.pattern.clone(),
+                       paths: self.root.iter().cloned().collect(),
+                       replacement: self.replacement.clone(),
+                       recurse: self.recurse,
+                       preview: self.preview || batch_args.preview,
+                       yes: batch_args.yes,
+                       config: config_path.cloned(),
+                       walk_buffer: DEFAULT_WALK_BUFFER,
+                       ..Default::default() }
+        }
+}
+
+/// Runs every operation on stdin in order.  Errors if any operation failed (after running the rest).
+#[tracing::instrument]
+pub fn run_batch(batch_args: &BatchArgs, config_path: Option<&PathBuf>) -> Result<()> {
+        let mut context = RunContext::new(Config::load(config_path.map(PathBuf::as_path))?);
+        let (mut total, mut failed) = (0, 0);
+        for (i, line) in std::io::stdin().lock().lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                        continue;
+                }
+                total += 1;
+                let result = serde_json::from_str::<Operation>(&line)
+                        .map_err(|e| e.into())
+                        .and_then(|operation| run_in(&operation.to_args(batch_args, config_path), &mut context));
+                if let Err(e) = result {
+                        failed += 1;
+                        eprintln!("Error: operation on line {}: {}", i + 1, e);
+                }
+        }
+        match failed {
+                0 => Ok(()),
+                _ => Err(format!("{} of {} batch operations failed", failed, total).into()),
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_operation_to_args() {
+                let operation: Operation =
+                        serde_json::from_str(r#"{"pattern": "a", "replacement": "b", "root": "dir"}"#).unwrap();
+                let args = operation.to_args(&BatchArgs { preview: true, yes: false }, None);
+                assert_eq!(args.regex, "a");
+                assert_eq!(args.replacement.as_deref(), Some("b"));
+                assert_eq!(args.paths, vec![PathBuf::from("dir")]);
+                assert!(args.preview && !args.recurse);
+                assert!(serde_json::from_str::<Operation>(r#"{"pattern": "a", "replacment": "b"}"#).is_err());
+        }
+}
@@ -0,0 +1,123 @@
+//! `bench` subcommand: throughput of each stage on a synthetic tree.
+//!
+//! The tree is generated in a temporary directory (removed afterwards), so users can measure performance on their
+//! own hardware and filesystems without touching real files.
+
+use std::{fs::{self, File},
+          io::{self, Write},
+          path::{Path, PathBuf},
+          time::{Duration, Instant}};
+
+use owo_colors::OwoColorize;
+use tempfile::TempDir;
+
+use crate::{Args, DEFAULT_WALK_BUFFER, build_plan, error::Result, matcher::Matcher, output::Output,
+            walkdir_build_with_depths};
+
+/// Shape of the synthetic tree and the pattern to run over it.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+        /// Total number of files to generate.
+        #[arg(long, default_value_t = 10_000)]
+        files: usize,
+
+        /// Directory nesting depth.
+        #[arg(long, default_value_t = 3)]
+        depth: u32,
+
+        /// Subdirectories per directory.
+        #[arg(long, default_value_t = 4)]
+        fanout: usize,
+
+        /// Regex to search the generated filenames (`file_<n>.txt`) with.
+        #[arg(long, default_value = r"file_(\d+)")]
+        regex: String,
+
+        /// Replacement used for the plan and execute stages.
+        #[arg(long = "rep", default_value = "renamed_${1}")]
+        replacement: String,
+
+        /// Threads used to fetch entry metadata in the plan stage.  0: one per CPU, up to 8.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        metadata_threads: usize,
+}
+
+/// Generates the tree, then times walk, match, plan, and execute stages in turn.
+#[tracing::instrument]
+pub fn run(bench_args: &BenchArgs) -> Result<()> {
+        let matcher = Matcher::new(&bench_args.regex)?;
+        let re = matcher.regex();
+        let temp_dir = TempDir::new()?;
+        let mut out = Output::stdout();
+
+        let start = Instant::now();
+        let generated = generate_tree(temp_dir.path(), bench_args)?;
+        report(&mut out, "generate", generated, start.elapsed())?;
+
+        let start = Instant::now();
+        let walked = walkdir_build_with_depths(temp_dir.path(), true).into_iter().filter_map(|e| e.ok()).count();
+        report(&mut out, "walk", walked, start.elapsed())?;
+
+        let start = Instant::now();
+        let is_match = |entry: &walkdir::DirEntry| entry.file_name().to_str().is_some_and(|name| re.is_match(name));
+        let walk = walkdir_build_with_depths(temp_dir.path(), true).into_iter().filter_map(|e| e.ok());
+        let matched = walk.filter(is_match).count();
+        report(&mut out, "match", matched, start.elapsed())?;
+
+        let args = Args { regex: bench_args.regex.clone(),
+                          replacement: Some(bench_args.replacement.clone()),
+                          recurse: true,
+                          walk_buffer: DEFAULT_WALK_BUFFER,
+                          metadata_threads: bench_args.metadata_threads,
+                          ..Default::default() };
+        let start = Instant::now();
+        let plan = build_plan(vec![temp_dir.path().to_path_buf()], &matcher, &args);
+        let conflicts = plan.conflicts();
+        report(&mut out, "plan", plan.entries.len(), start.elapsed())?;
+        if !conflicts.is_empty() {
+                tracing::warn!("{} conflicts in the generated plan; execute stage skips them", conflicts.len());
+        }
+
+        let start = Instant::now();
+        let mut executed = 0;
+        for entry in &plan.entries {
+                let Some(destination) = entry.destination() else { continue };
+                if fs::rename(&entry.path, destination).is_ok() {
+                        executed += 1;
+                }
+        }
+        report(&mut out, "execute", executed, start.elapsed())?;
+
+        temp_dir.close()?;
+        Ok(())
+}
+
+/// Creates `depth` levels of `fanout` directories and spreads the files evenly across all of them (and the root).
+///
+/// Returns the number of entries (files and directories) created.
+fn generate_tree(root: &Path, bench_args: &BenchArgs) -> Result<usize> {
+        let mut dirs = vec![root.to_path_buf()];
+        let mut level: Vec<PathBuf> = vec![root.to_path_buf()];
+        for _ in 0..bench_args.depth {
+                let mut next = Vec::with_capacity(level.len() * bench_args.fanout);
+                for parent in &level {
+                        for i in 0..bench_args.fanout {
+                                let dir = parent.join(format!("dir_{}", i));
+                                fs::create_dir(&dir)?;
+                                next.push(dir);
+                        }
+                }
+                dirs.extend(next.iter().cloned());
+                level = next;
+        }
+        for i in 0..bench_args.files {
+                File::create(dirs[i % dirs.len()].join(format!("file_{}.txt", i)))?;
+        }
+        Ok(dirs.len() - 1 + bench_args.files)
+}
+
+/// Prints one stage's count, time, and throughput.
+fn report(out: &mut impl Write, stage: &str, entries: usize, elapsed: Duration) -> io::Result<()> {
+        let rate = entries as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        writeln!(out, "{:<10} {:>10} entries  {:>12.2?}  {:>12.0} entries/s", stage.cyan(), entries, elapsed, rate)
+}
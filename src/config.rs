@@ -0,0 +1,396 @@
+//! User configuration file.
+//!
+//! Looked up at (first found wins):
+//! - `--config <PATH>`
+//! - `$RENAME_FILES_CONFIG`
+//! - `$XDG_CONFIG_HOME/rename_files/config.toml`
+//! - `$HOME/.config/rename_files/config.toml`
+//!
+//! Additionally, a project-local `.rename_files.toml` is looked for in each root and its ancestors (nearest wins,
+//! like `.editorconfig`), so a repository can pin settings for anyone running the tool inside it.  A project file
+//! honors only these keys (any other, e.g. `[alias]`, is an error):
+//! - `safe_mode = true` turns safe mode on; `safe_mode = false` is ignored
+//! - `protected_paths` (relative to the file's directory) are added
+//! - `ignore`: gitignore-style patterns (relative to the file's directory); matching entries are never walked or
+//!   renamed
+//! - `[profile.<name>]` tables, available unless the user config defines a profile of the same name
+//! - `[recipe.<name>]` tables (as recipe files, see `recipe.rs` docs), available unless a built-in or user recipe of
+//!   the same name exists
+//!
+//! A missing file is not an error: defaults are used.  An invalid one is: unknown keys (e.g. a typo like
+//! `safe_mod`), wrong types, and invalid values are reported with the file, line, and column.
+//!
+//! # Example:
+//! ```toml
+//! # require `--yes` before renaming
+//! safe_mode = true
+//! # never use these as a root or rename them (in addition to the built-in list)
+//! protected_paths = ["~/photos", "/srv/data"]
+//!
+//! # named sets of defaults, selected with `--profile base,mine`
+//! [profile.base]
+//! recurse = true
+//! protected_paths = ["/srv/shared"]
+//!
+//! [profile.mine]
+//! inherits = "base"
+//! safe_mode = false
+//!
+//! # shorthands, expanded as if typed: `rename_files shots -p`  (see `alias` module)
+//! [alias]
+//! shots = "'^IMG_(\d+)' --rep 'shot_${1}' --recurse"
+//! ```
+//!
+//! # Profile precedence (lowest to highest):
+//! 1. top-level settings
+//! 2. each `--profile`, in the order given; a profile's `inherits` chain is applied just before it
+//! 3. command line flags
+//!
+//! Later layers override single values (`safe_mode`, `recurse`, ...) and extend lists (`protected_paths`, ...).
+//! Command line switches can only turn a setting on: a profile's `recurse = true` cannot be undone with a flag.
+
+use std::{collections::BTreeMap,
+          path::{Path, PathBuf}};
+
+use ignore::gitignore::Gitignore;
+use serde::Deserialize;
+
+use crate::{alias, error::Result, gitignore, recipe::Recipe};
+
+/// Environment variable naming a config file to use in place of the default location.
+pub const CONFIG_ENV_VAR: &str = "RENAME_FILES_CONFIG";
+
+/// File name of project-local config files.
+pub const PROJECT_CONFIG_FILE: &str = ".rename_files.toml";
+
+/// Settings read from the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+        /// Require `--yes` before any file is actually renamed.  (`--preview` runs are unaffected.)
+        pub safe_mode:       bool,
+        /// Extra paths to protect, on top of the built-in list.  A leading `~` is expanded.
+        pub protected_paths: Vec<String>,
+        /// Named profiles (`[profile.<name>]` tables).
+        #[serde(rename = "profile")]
+        pub profiles:        BTreeMap<String, Profile>,
+        /// Command aliases (`[alias]` table): name -> arguments.
+        #[serde(rename = "alias")]
+        pub aliases:         BTreeMap<String, String>,
+        /// From project files: `ignore` rules, one per file.
+        #[serde(skip)]
+        pub project_ignores: Vec<Gitignore>,
+        /// From project files: recipes, by name.
+        #[serde(skip)]
+        pub project_recipes: BTreeMap<String, Recipe>,
+}
+
+/// What a project-local config file can pin (see module docs).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProjectConfig {
+        safe_mode:       bool,
+        protected_paths: Vec<String>,
+        ignore:          Vec<String>,
+        #[serde(rename = "profile")]
+        profiles:        BTreeMap<String, Profile>,
+        #[serde(rename = "recipe")]
+        recipes:         BTreeMap<String, Recipe>,
+}
+
+impl ProjectConfig {
+        /// Reads and validates a project file.  Errors name the file.
+        fn load(path: &Path) -> Result<Self> {
+                let invalid = |e: &dyn std::fmt::Display| format!("Invalid project config {:?}: {}", path, e);
+                let text =
+                        std::fs::read_to_string(path).map_err(|e| format!("Could not read config {:?}: {}", path, e))?;
+                let project: Self = toml::from_str(&text).map_err(|e| invalid(&e))?;
+                let as_config = Config { protected_paths: project.protected_paths.clone(),
+                                         profiles: project.profiles.clone(),
+                                         ..Default::default() };
+                as_config.validate().map_err(|e| invalid(&e))?;
+                for (name, recipe) in &project.recipes {
+                        recipe.validate().map_err(|e| invalid(&format!("recipe {:?}: {}", name, e)))?;
+                }
+                Ok(project)
+        }
+}
+
+/// A named, layerable set of defaults.  Unset values leave the layer below unchanged.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+        /// Profile applied before this one.
+        pub inherits: Option<String>,
+        /// As the top-level setting.
+        pub safe_mode: Option<bool>,
+        /// Added to the top-level list.
+        pub protected_paths: Vec<String>,
+        /// As `--recurse`.
+        pub recurse: Option<bool>,
+        /// As `--preview`.
+        pub preview: Option<bool>,
+        /// As `--stats`.
+        pub stats: Option<bool>,
+        /// As `--update-refs`.
+        pub update_refs: Vec<String>,
+        /// As `--refuse-dir-rename-if-referenced`.
+        pub refuse_dir_rename_if_referenced: Vec<String>,
+}
+
+impl Profile {
+        /// Layers `other` on top of `self`.
+        fn merge(&mut self, other: &Profile) {
+                self.safe_mode = other.safe_mode.or(self.safe_mode);
+                self.recurse = other.recurse.or(self.recurse);
+                self.preview = other.preview.or(self.preview);
+                self.stats = other.stats.or(self.stats);
+                self.protected_paths.extend(other.protected_paths.iter().cloned());
+                self.update_refs.extend(other.update_refs.iter().cloned());
+                self.refuse_dir_rename_if_referenced.extend(other.refuse_dir_rename_if_referenced.iter().cloned());
+        }
+}
+
+impl Config {
+        /// Loads the config from an explicit path, or from the first default location that exists.
+        ///
+        /// An explicitly given path must exist; default locations are optional.
+        pub fn load(explicit: Option<&Path>) -> Result<Self> {
+                let path = match explicit {
+                        Some(path) => path.to_path_buf(),
+                        None => match default_path() {
+                                Some(path) if path.exists() => path,
+                                _ => {
+                                        tracing::debug!("No config file found, using defaults");
+                                        return Ok(Self::default());
+                                }
+                        },
+                };
+                tracing::debug!("Loading config from {:?}", path);
+                let text =
+                        std::fs::read_to_string(&path).map_err(|e| format!("Could not read config {:?}: {}", path, e))?;
+                Self::parse(&text).map_err(|e| format!("Invalid config {:?}: {}", path, e).into())
+        }
+
+        /// Layers the nearest project-local config of each root (if any) on top of this config.
+        pub fn merge_project_configs(&mut self, roots: &[PathBuf]) -> Result<()> {
+                let mut paths: Vec<PathBuf> = roots.iter().filter_map(|root| find_project_config(root)).collect();
+                paths.sort();
+                paths.dedup();
+                for path in paths {
+                        tracing::debug!("Loading project config from {:?}", path);
+                        let project = ProjectConfig::load(&path)?;
+                        let dir = path.parent().expect("config file path has a parent");
+                        self.safe_mode |= project.safe_mode;
+                        self.protected_paths.extend(project.protected_paths.iter().map(|p| {
+                                                                                          dir.join(expand_tilde(p))
+                                                                                             .to_string_lossy()
+                                                                                             .into_owned()
+                                                                                  }));
+                        for (name, profile) in project.profiles {
+                                self.profiles.entry(name).or_insert(profile);
+                        }
+                        if !project.ignore.is_empty() {
+                                let rules = gitignore::rules_from_patterns(dir, &project.ignore)
+                                        .map_err(|e| format!("Invalid project config {:?}: {}", path, e))?;
+                                self.project_ignores.push(rules);
+                        }
+                        for (name, mut recipe) in project.recipes {
+                                recipe.source = Some(path.clone());
+                                self.project_recipes.entry(name).or_insert(recipe);
+                        }
+                }
+                Ok(())
+        }
+
+        /// Parses and validates config text.
+        ///
+        /// Errors (from the toml parser) name the offending key with its line and column, and list the valid keys.
+        pub fn parse(text: &str) -> Result<Self> {
+                let config: Self = toml::from_str(text)?;
+                config.validate()?;
+                Ok(config)
+        }
+
+        /// Checks values that parse but cannot be meant.
+        fn validate(&self) -> Result<()> {
+                let all_protected_paths =
+                        self.protected_paths
+                            .iter()
+                            .chain(self.profiles.values().flat_map(|profile| &profile.protected_paths));
+                if all_protected_paths.into_iter().any(|path| path.trim().is_empty()) {
+                        return Err("`protected_paths` contains an empty path".into());
+                }
+                for name in self.profiles.keys() {
+                        self.inheritance_chain(name)?;
+                }
+                for (name, expansion) in &self.aliases {
+                        if alias::RESERVED.contains(&name.as_str()) {
+                                return Err(format!("Alias {:?} would shadow the subcommand of the same name",
+                                                   name).into());
+                        }
+                        alias::split_words(expansion).map_err(|e| format!("Alias {:?}: {}", name, e))?;
+                }
+                Ok(())
+        }
+
+        /// The named profiles, each with its `inherits` chain, merged in precedence order.  Also applies the merged
+        /// `safe_mode` and `protected_paths` to this config.
+        pub fn apply_profiles(&mut self, names: &[String]) -> Result<Profile> {
+                let mut merged = Profile::default();
+                for name in names {
+                        for profile in self.inheritance_chain(name)? {
+                                merged.merge(profile);
+                        }
+                }
+                self.safe_mode = merged.safe_mode.unwrap_or(self.safe_mode);
+                self.protected_paths.extend(merged.protected_paths.iter().cloned());
+                Ok(merged)
+        }
+
+        /// A profile and its ancestors, most distant ancestor first.
+        fn inheritance_chain(&self, name: &str) -> Result<Vec<&Profile>> {
+                let mut chain: Vec<(&str, &Profile)> = Vec::new();
+                let mut next = Some(name);
+                while let Some(name) = next {
+                        if chain.iter().any(|(seen, _)| *seen == name) {
+                                let cycle: Vec<&str> = chain.iter().map(|(seen, _)| *seen).chain([name]).collect();
+                                return Err(format!("Profile inheritance cycle: {}", cycle.join(" -> ")).into());
+                        }
+                        let profile = self.profiles.get(name).ok_or_else(|| match chain.last() {
+                                              Some((child, _)) => {
+                                                      format!("Profile {:?} inherits unknown profile {:?}", child, name)
+                                              }
+                                              None => format!("Unknown profile {:?}", name),
+                                      })?;
+                        chain.push((name, profile));
+                        next = profile.inherits.as_deref();
+                }
+                Ok(chain.into_iter().rev().map(|(_, profile)| profile).collect())
+        }
+}
+
+/// Current user's home directory, from `$HOME` (or `%USERPROFILE%`).
+pub fn home_dir() -> Option<PathBuf> {
+        std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from)
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+        match (path.strip_prefix('~'), home_dir()) {
+                (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+                        home.join(rest.trim_start_matches(['/', '\\']))
+                }
+                _ => PathBuf::from(path),
+        }
+}
+
+/// Nearest `.rename_files.toml` in `root` or its ancestors.
+fn find_project_config(root: &Path) -> Option<PathBuf> {
+        let root = root.canonicalize().ok()?;
+        root.ancestors().map(|dir| dir.join(PROJECT_CONFIG_FILE)).find(|path| path.is_file())
+}
+
+/// Config location from the environment, XDG config dir, or home directory.
+fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV_VAR) {
+                return Some(PathBuf::from(path));
+        }
+        config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// This tool's directory under the XDG config dir (or `~/.config`).
+pub fn config_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| {
+                                                                                  home_dir().map(|home| {
+                                                                                                    home.join(".config")
+                                                                                            })
+                                                                          })?;
+        Some(base.join("rename_files"))
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+                let config = Config::parse("safe_mode = true\nprotected_paths = [\"~/photos\"]\n").unwrap();
+                assert!(config.safe_mode);
+                assert_eq!(config.protected_paths, vec!["~/photos"]);
+                assert!(!Config::parse("").unwrap().safe_mode);
+
+                let typo = Config::parse("safe_mode = false\nsafe_mod = true\n").unwrap_err().to_string();
+                assert!(typo.contains("safe_mod") && typo.contains("line 2"), "{}", typo);
+                let wrong_type = Config::parse("safe_mode = \"no\"\n").unwrap_err().to_string();
+                assert!(wrong_type.contains("line 1"), "{}", wrong_type);
+                assert!(Config::parse("protected_paths = [\"\"]\n").is_err());
+        }
+
+        #[test]
+        fn test_profiles() {
+                let text = r#"
+                        protected_paths = ["/top"]
+                        [profile.base]
+                        recurse = true
+                        safe_mode = false
+                        protected_paths = ["/base"]
+                        [profile.mine]
+                        inherits = "base"
+                        safe_mode = true
+                        [profile.other]
+                        safe_mode = false
+                "#;
+                let mut config = Config::parse(text).unwrap();
+                let merged = config.apply_profiles(&["mine".to_string()]).unwrap();
+                assert_eq!(merged.recurse, Some(true));
+                assert!(config.safe_mode);
+                assert_eq!(config.protected_paths, vec!["/top", "/base"]);
+
+                let mut config = Config::parse(text).unwrap();
+                config.apply_profiles(&["mine".to_string(), "other".to_string()]).unwrap();
+                assert!(!config.safe_mode);
+
+                assert!(Config::parse(text).unwrap().apply_profiles(&["nope".to_string()]).is_err());
+                assert!(Config::parse("[profile.a]\ninherits = \"b\"\n[profile.b]\ninherits = \"a\"\n").is_err());
+                assert!(Config::parse("[profile.a]\ninherits = \"b\"\n").is_err());
+        }
+
+        /// Nearest project file applies; it can add protections, ignore rules, and recipes, and turn safe mode on,
+        /// never off.
+        #[test]
+        fn test_merge_project_configs() -> Result<()> {
+                let temp_dir = tempfile::tempdir()?;
+                let nested = temp_dir.path().join("a").join("b");
+                std::fs::create_dir_all(&nested)?;
+                std::fs::write(temp_dir.path().join(PROJECT_CONFIG_FILE),
+                               "safe_mode = true\nprotected_paths = [\"data\"]\nignore = [\"build/\"]\n[profile.p]\n\
+                                recurse = true\n[recipe.r]\ndescription = \"r\"\npattern = 'x'\n")?;
+
+                let mut config = Config { safe_mode: false, ..Default::default() };
+                config.merge_project_configs(std::slice::from_ref(&nested))?;
+                assert!(config.safe_mode);
+                let data = temp_dir.path().canonicalize()?.join("data");
+                assert_eq!(config.protected_paths, vec![data.to_string_lossy()]);
+                assert!(config.profiles.contains_key("p"));
+                let root = temp_dir.path().canonicalize()?;
+                assert!(gitignore::is_ignored_by(&config.project_ignores, &root.join("a/build/x.o"), false));
+                assert!(!gitignore::is_ignored_by(&config.project_ignores, &root.join("a/build"), false));
+                assert!(!gitignore::is_ignored_by(&config.project_ignores, Path::new("/elsewhere/build/x"), false));
+                let source = config.project_recipes.get("r").and_then(|recipe| recipe.source.clone());
+                assert_eq!(source, Some(root.join(PROJECT_CONFIG_FILE)));
+
+                std::fs::write(temp_dir.path().join(PROJECT_CONFIG_FILE), "safe_mode = false\n")?;
+                let mut config = Config { safe_mode: true, ..Default::default() };
+                config.merge_project_configs(std::slice::from_ref(&nested))?;
+                assert!(config.safe_mode);
+
+                // keys a project file does not honor are errors, not silently dropped
+                std::fs::write(temp_dir.path().join(PROJECT_CONFIG_FILE), "[alias]\nx = \"y\"\n")?;
+                let error = Config::default().merge_project_configs(&[nested]).unwrap_err().to_string();
+                assert!(error.contains("alias"), "{}", error);
+                Ok(())
+        }
+}
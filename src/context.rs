@@ -0,0 +1,96 @@
+//! What one process works out once and reuses across runs (the operations of a `batch`).
+//!
+//! Each run still applies its own options on top; only work that depends on nothing else is shared:
+//! - the user config with the project configs of a set of roots merged in, by those roots
+//! - the discovered (built-in and user) recipes
+//! - compiled matchers, by patterns and flags
+//!
+//! Nothing is invalidated: a project config edited while a batch runs is not seen by the rest of it.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{config::Config, error::Result, matcher::Matcher, recipe::Recipes};
+
+/// Shared state for the runs of one process.
+#[derive(Debug)]
+pub struct RunContext {
+        /// The user config, as loaded.
+        config:   Config,
+        /// [`Self::config`] with each root set's project configs merged in.
+        merged:   HashMap<Vec<PathBuf>, Config>,
+        recipes:  Option<Recipes>,
+        /// By patterns, ignore case, fancy.
+        matchers: HashMap<(Vec<String>, bool, bool), Matcher>,
+}
+
+impl RunContext {
+        /// A context with nothing cached yet, for the given user config.
+        pub fn new(config: Config) -> Self {
+                Self { config, merged: HashMap::new(), recipes: None, matchers: HashMap::new() }
+        }
+
+        /// The user config with the project configs of `roots` merged in.
+        pub fn config(&mut self, roots: &[PathBuf]) -> Result<Config> {
+                if let Some(config) = self.merged.get(roots) {
+                        return Ok(config.clone());
+                }
+                let mut config = self.config.clone();
+                config.merge_project_configs(roots)?;
+                self.merged.insert(roots.to_vec(), config.clone());
+                Ok(config)
+        }
+
+        /// The built-in and user recipes (without project recipes, which depend on the roots).
+        pub fn recipes(&mut self) -> Result<Recipes> {
+                if self.recipes.is_none() {
+                        self.recipes = Some(Recipes::discover()?);
+                }
+                Ok(self.recipes.clone().expect("just discovered"))
+        }
+
+        /// The compiled matcher, as [`Matcher::any_of`].
+        pub fn matcher(&mut self,
+                       patterns: &[impl AsRef<str>],
+                       ignore_case: bool,
+                       allow_fancy: bool)
+                       -> Result<Matcher> {
+                let key = (patterns.iter().map(|pattern| pattern.as_ref().to_string()).collect(),
+                           ignore_case,
+                           allow_fancy);
+                if let Some(matcher) = self.matchers.get(&key) {
+                        return Ok(matcher.clone());
+                }
+                let matcher = Matcher::any_of(patterns, ignore_case, allow_fancy)?;
+                self.matchers.insert(key, matcher.clone());
+                Ok(matcher)
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_run_context_caches() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let project = temp_dir.path().join(".rename_files.toml");
+                std::fs::write(&project, "safe_mode = true\n")?;
+                let roots = vec![temp_dir.path().to_path_buf()];
+                let mut context = RunContext::new(Config::default());
+                assert!(context.config(&roots)?.safe_mode);
+                // Merged once: later edits are not seen
+                std::fs::write(&project, "safe_mode = false\n")?;
+                assert!(context.config(&roots)?.safe_mode);
+                assert!(!context.config(&[])?.safe_mode);
+
+                let matcher = context.matcher(&["^a", "b$"], true, false)?;
+                assert_eq!(context.matchers.len(), 1);
+                assert_eq!(context.matcher(&["^a", "b$"], true, false)?.regex().as_str(), matcher.regex().as_str());
+                context.matcher(&["^a", "b$"], false, false)?;
+                assert_eq!(context.matchers.len(), 2);
+                Ok(())
+        }
+}
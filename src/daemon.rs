@@ -0,0 +1,250 @@
+//! `daemon` subcommand: plan/preview/execute over a local Unix socket, for editor and file-manager plugins.
+//!
+//! Opt-in and local only: the socket is created with owner-only permissions, inside a private directory, so it is
+//! never connectable by others, even briefly.  Requests are JSON-RPC 2.0, one per
+//! line; each response is one line.  Clients are served one at a time, so renames never interleave.
+//!
+//! # Methods (params are a batch [`Operation`]: `pattern`, `replacement`, `root`, `recurse`):
+//! - `plan`: the plan as JSON (entries, conflicts), renaming nothing
+//! - `preview`: the human-readable preview text, renaming nothing
+//! - `execute`: renames (the request is the confirmation, as `--yes`), then returns the plan JSON
+//!
+//! With `--metrics <ADDR>`, counters are also served in Prometheus format; see `metrics.rs` docs.
+//!
+//! # Example:
+//! ```text
+//! -> {"jsonrpc": "2.0", "id": 1, "method": "plan", "params": {"pattern": "^IMG_", "replacement": "shot_"}}
+//! <- {"jsonrpc": "2.0", "id": 1, "result": {"entries": [...], "conflicts": []}}
+//! ```
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{batch::{BatchArgs, Operation},
+            config::Config,
+            error::Result,
+            metrics::Metrics,
+            run_to};
+
+/// Where to listen.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct DaemonArgs {
+        /// Socket path.  Default: `$XDG_RUNTIME_DIR/rename_files.sock`, else in the temp dir.
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Also serve Prometheus-format metrics at `http://<ADDR>/metrics` (e.g. `127.0.0.1:9464`).
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<SocketAddr>,
+}
+
+/// A JSON-RPC request.
+#[derive(Debug, Deserialize)]
+struct Request {
+        #[serde(default)]
+        id:     Value,
+        method: String,
+        params: Option<Value>,
+}
+
+/// JSON-RPC error codes used.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Server-defined: the operation itself failed (e.g. conflicts, protected path).
+const OPERATION_FAILED: i64 = -32000;
+
+/// Serves requests until killed.
+#[cfg(unix)]
+#[tracing::instrument]
+pub fn serve(daemon_args: &DaemonArgs, config_path: Option<&PathBuf>) -> Result<()> {
+        use std::{io::{BufRead, BufReader, Write},
+                  os::unix::fs::FileTypeExt,
+                  sync::{Arc, mpsc}};
+
+        let config = Config::load(config_path.map(PathBuf::as_path))?;
+        let socket = daemon_args.socket.clone().unwrap_or_else(default_socket);
+        // Guard: only ever replace a stale socket, not some other file
+        if let Ok(metadata) = std::fs::symlink_metadata(&socket) {
+                if !metadata.file_type().is_socket() {
+                        return Err(format!("{:?} exists and is not a socket", socket).into());
+                }
+                std::fs::remove_file(&socket)?;
+        }
+        let listener = bind_private(&socket)?;
+        tracing::warn!("Listening on {:?}", socket);
+        let metrics = Arc::new(Metrics::default());
+        if let Some(addr) = daemon_args.metrics {
+                crate::metrics::spawn_endpoint(addr, Arc::clone(&metrics))?;
+        }
+        // Accept on a separate thread, so clients waiting their turn show up as queue depth
+        let (sender, receiver) = mpsc::channel();
+        let acceptor_metrics = Arc::clone(&metrics);
+        std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                        match stream {
+                                Ok(stream) => {
+                                        acceptor_metrics.enqueued();
+                                        if sender.send(stream).is_err() {
+                                                break;
+                                        }
+                                }
+                                Err(e) => tracing::error!("Connection failed: {}", e),
+                        }
+                }
+        });
+        for stream in receiver {
+                metrics.dequeued();
+                let mut writer = stream.try_clone()?;
+                for line in BufReader::new(stream).lines() {
+                        let Ok(line) = line else { break };
+                        if line.trim().is_empty() {
+                                continue;
+                        }
+                        let response = respond(&line, &config, config_path, &metrics);
+                        if writeln!(writer, "{}", response).is_err() {
+                                break;
+                        }
+                }
+        }
+        Ok(())
+}
+
+/// Serves requests until killed.  (Unix sockets only.)
+#[cfg(not(unix))]
+pub fn serve(_daemon_args: &DaemonArgs, _config_path: Option<&PathBuf>) -> Result<()> {
+        Err("The daemon needs Unix domain sockets, which this platform does not support".into())
+}
+
+/// Binds `socket` so no one else can ever connect: it is bound inside a fresh 0700 directory next to it, made
+/// owner-only, and only then moved into place.  (Setting permissions after binding in place leaves a window open.)
+#[cfg(unix)]
+fn bind_private(socket: &std::path::Path) -> Result<std::os::unix::net::UnixListener> {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        let file_name = socket.file_name().ok_or_else(|| format!("{:?} is not a socket file path", socket))?;
+        let parent =
+                socket.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+        let private_dir = parent.join(format!(".rename_files.{}.tmp", std::process::id()));
+        std::fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+        let staged = private_dir.join(file_name);
+        let bound = std::os::unix::net::UnixListener::bind(&staged).and_then(|listener| {
+                            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+                            std::fs::rename(&staged, socket)?;
+                            Ok(listener)
+                    });
+        if bound.is_err() {
+                let _ = std::fs::remove_file(&staged);
+        }
+        std::fs::remove_dir(&private_dir)?;
+        Ok(bound?)
+}
+
+/// Socket path used without `--socket`.
+fn default_socket() -> PathBuf {
+        std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+                                           .unwrap_or_else(std::env::temp_dir)
+                                           .join("rename_files.sock")
+}
+
+/// The response line for one request line, counted in `metrics`.
+fn respond(line: &str, config: &Config, config_path: Option<&PathBuf>, metrics: &Metrics) -> Value {
+        let request: Request = match serde_json::from_str(line) {
+                Ok(request) => request,
+                Err(e) => {
+                        let response = error(Value::Null, PARSE_ERROR, e.to_string());
+                        metrics.record("", &response);
+                        return response;
+                }
+        };
+        let method = request.method.clone();
+        let response = dispatch(request, config, config_path);
+        metrics.record(&method, &response);
+        response
+}
+
+/// Runs one parsed request.
+fn dispatch(request: Request, config: &Config, config_path: Option<&PathBuf>) -> Value {
+        let params = request.params.unwrap_or(Value::Null);
+        let operation: Operation = match serde_json::from_value(params) {
+                Ok(operation) => operation,
+                Err(e) => return error(request.id, INVALID_PARAMS, e.to_string()),
+        };
+        let batch_args = match request.method.as_str() {
+                "plan" | "preview" => BatchArgs { preview: true, yes: false },
+                "execute" => BatchArgs { preview: false, yes: true },
+                method => return error(request.id, METHOD_NOT_FOUND, format!("unknown method {:?}", method)),
+        };
+        let mut args = operation.to_args(&batch_args, config_path);
+        args.json = request.method != "preview";
+        let mut out = Vec::new();
+        let result = run_to(&mut out, &args, config.clone()).and_then(|()| {
+                                                                    let text = String::from_utf8(out)?;
+                                                                    Ok(match args.json {
+                                                                            true => serde_json::from_str(&text)?,
+                                                                            false => Value::String(text),
+                                                                    })
+                                                            });
+        match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+                Err(e) => error(request.id, OPERATION_FAILED, e.to_string()),
+        }
+}
+
+/// A JSON-RPC error response.
+fn error(id: Value, code: i64, message: String) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_respond() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                std::fs::File::create(temp_dir.path().join("file_1.txt"))?;
+                let root = serde_json::to_string(&temp_dir.path())?;
+                let config = Config::default();
+                let metrics = Metrics::default();
+
+                let plan = format!(r#"{{"jsonrpc": "2.0", "id": 7, "method": "plan",
+                                       "params": {{"pattern": "file_(\\d)", "replacement": "f$1", "root": {}}}}}"#,
+                                   root);
+                let response = respond(&plan.replace('\n', " "), &config, None, &metrics);
+                assert_eq!(response["id"], 7);
+                assert_eq!(response["result"]["entries"][0]["new_filename"], "f1.txt");
+                assert!(temp_dir.path().join("file_1.txt").exists());
+
+                let execute = plan.replace("\"plan\"", "\"execute\"").replace('\n', " ");
+                let response = respond(&execute, &config, None, &metrics);
+                assert!(response.get("result").is_some(), "{}", response);
+                assert!(temp_dir.path().join("f1.txt").exists());
+
+                assert_eq!(respond("{", &config, None, &metrics)["error"]["code"], PARSE_ERROR);
+                let unknown = r#"{"jsonrpc": "2.0", "id": 1, "method": "nope", "params": {"pattern": "x"}}"#;
+                assert_eq!(respond(unknown, &config, None, &metrics)["error"]["code"], METHOD_NOT_FOUND);
+                assert!(metrics.render().contains("\nrename_files_files_renamed_total 1\n"));
+                Ok(())
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_bind_private() -> Result<()> {
+                use std::os::unix::fs::PermissionsExt;
+
+                let temp_dir = TempDir::new()?;
+                let socket = temp_dir.path().join("daemon.sock");
+                let _listener = bind_private(&socket)?;
+                assert_eq!(std::fs::metadata(&socket)?.permissions().mode() & 0o777, 0o600);
+                // Only the socket is left behind, not the private staging directory
+                assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 1);
+                std::os::unix::net::UnixStream::connect(&socket)?;
+                Ok(())
+        }
+}
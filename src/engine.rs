@@ -0,0 +1,242 @@
+//! Regex engines a pattern can be compiled for: the regex crate, or with the `fancy` feature and `--fancy`,
+//! fancy-regex (lookaround and backreferences).
+//!
+//! Both give the same matching and capture interface, so [`crate::matcher`] is engine agnostic.  The standard engine
+//! is used whenever it can compile the pattern: it runs in linear time, and its patterns get the byte-level
+//! pre-checks.  fancy-regex backtracks; a search that hits its backtrack limit counts as no match, with a warning.
+
+use regex::{Regex, RegexBuilder};
+
+use crate::error::Result;
+
+/// A compiled pattern.
+#[derive(Debug, Clone)]
+pub enum Engine {
+        /// The regex crate.
+        Standard(Regex),
+        /// fancy-regex, for patterns the regex crate cannot compile.
+        #[cfg(feature = "fancy")]
+        Fancy(fancy_regex::Regex),
+}
+
+impl Engine {
+        /// Compiles `pattern` with the standard engine, or if it cannot and `allow_fancy`, with fancy-regex.
+        pub fn new(pattern: &str, ignore_case: bool, allow_fancy: bool) -> Result<Self> {
+                match RegexBuilder::new(pattern).case_insensitive(ignore_case).build() {
+                        Ok(re) => Ok(Self::Standard(re)),
+                        Err(e) if !allow_fancy => Err(e.into()),
+                        Err(e) => Self::fancy(pattern, ignore_case, e),
+                }
+        }
+
+        /// Compiles `pattern` with fancy-regex (which reports its own errors).
+        #[cfg(feature = "fancy")]
+        fn fancy(pattern: &str, ignore_case: bool, _standard_error: regex::Error) -> Result<Self> {
+                let re = fancy_regex::RegexBuilder::new(pattern).case_insensitive(ignore_case).build()?;
+                tracing::debug!("Pattern needs fancy-regex: {:?}", pattern);
+                Ok(Self::Fancy(re))
+        }
+
+        /// Without the `fancy` feature there is no fallback: the standard engine's error stands.
+        #[cfg(not(feature = "fancy"))]
+        fn fancy(_pattern: &str, _ignore_case: bool, standard_error: regex::Error) -> Result<Self> {
+                Err(standard_error.into())
+        }
+
+        /// The standard regex, unless the pattern needed fancy-regex.
+        pub fn standard(&self) -> Option<&Regex> {
+                match self {
+                        Self::Standard(re) => Some(re),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(_) => None,
+                }
+        }
+
+        /// The pattern, as given.
+        pub fn as_str(&self) -> &str {
+                match self {
+                        Self::Standard(re) => re.as_str(),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => re.as_str(),
+                }
+        }
+
+        pub fn is_match(&self, text: &str) -> bool {
+                match self {
+                        Self::Standard(re) => re.is_match(text),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => re.is_match(text).unwrap_or_else(|e| backtrack_failed(text, e)),
+                }
+        }
+
+        /// Capture groups of the first match in `text`, if any.
+        pub fn captures<'h>(&self, text: &'h str) -> Option<Captures<'h>> {
+                match self {
+                        Self::Standard(re) => re.captures(text).map(Captures::Standard),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => match re.captures(text) {
+                                Ok(caps) => caps.map(Captures::Fancy),
+                                Err(e) => {
+                                        backtrack_failed(text, e);
+                                        None
+                                }
+                        },
+                }
+        }
+
+        /// Capture groups of every successive non-overlapping match in `text`.
+        pub fn captures_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item=Captures<'a>>+'a> {
+                match self {
+                        Self::Standard(re) => Box::new(re.captures_iter(text).map(Captures::Standard)),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => {
+                                Box::new(re.captures_iter(text).map_while(move |caps| match caps {
+                                                                       Ok(caps) => Some(Captures::Fancy(caps)),
+                                                                       Err(e) => {
+                                                                               backtrack_failed(text, e);
+                                                                               None
+                                                                       }
+                                                               }))
+                        }
+                }
+        }
+
+        /// Number of groups, including group 0.
+        pub fn captures_len(&self) -> usize {
+                match self {
+                        Self::Standard(re) => re.captures_len(),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => re.captures_len(),
+                }
+        }
+
+        /// Each group's name (`None` for unnamed groups), including group 0.
+        pub fn capture_names(&self) -> Box<dyn Iterator<Item=Option<&str>>+'_> {
+                match self {
+                        Self::Standard(re) => Box::new(re.capture_names()),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(re) => Box::new(re.capture_names()),
+                }
+        }
+}
+
+/// Reports a fancy-regex search that gave up (e.g. at the backtrack limit); it counts as no match.
+#[cfg(feature = "fancy")]
+fn backtrack_failed(text: &str, e: fancy_regex::Error) -> bool {
+        tracing::warn!("Pattern search gave up on {:?}, treated as no match: {}", text, e);
+        false
+}
+
+/// Capture groups of one match, from either engine.
+#[derive(Debug)]
+pub enum Captures<'h> {
+        Standard(regex::Captures<'h>),
+        #[cfg(feature = "fancy")]
+        Fancy(fancy_regex::Captures<'h>),
+}
+
+impl<'h> Captures<'h> {
+        /// Group `index`, if it participated in the match.  Group 0, the whole match, always does.
+        pub fn get(&self, index: usize) -> Option<Group<'h>> {
+                match self {
+                        Self::Standard(caps) => caps.get(index).map(|m| Group::new(m.as_str(), m.start(), m.end())),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(caps) => caps.get(index).map(|m| Group::new(m.as_str(), m.start(), m.end())),
+                }
+        }
+
+        /// The whole match.
+        pub fn whole(&self) -> Group<'h> {
+                self.get(0).expect("group 0 always participates")
+        }
+
+        /// Every group in order, group 0 first; `None` for those that did not participate.
+        pub fn iter(&self) -> impl Iterator<Item=Option<Group<'h>>>+'_ {
+                let count = match self {
+                        Self::Standard(caps) => caps.len(),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(caps) => caps.len(),
+                };
+                (0..count).map(|index| self.get(index))
+        }
+
+        /// Appends `rep` to `dst` with capture references (`$1`, `${name}`, `$$`) replaced, as the regex crate does.
+        pub fn expand(&self, rep: &str, dst: &mut String) {
+                match self {
+                        Self::Standard(caps) => caps.expand(rep, dst),
+                        #[cfg(feature = "fancy")]
+                        Self::Fancy(caps) => caps.expand(rep, dst),
+                }
+        }
+}
+
+/// One group's part of a match: its text and utf8 byte offsets into the searched text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group<'h> {
+        text:  &'h str,
+        start: usize,
+        end:   usize,
+}
+
+impl<'h> Group<'h> {
+        fn new(text: &'h str, start: usize, end: usize) -> Self {
+                Self { text, start, end }
+        }
+
+        pub fn as_str(&self) -> &'h str {
+                self.text
+        }
+
+        pub fn start(&self) -> usize {
+                self.start
+        }
+
+        pub fn end(&self) -> usize {
+                self.end
+        }
+
+        pub fn len(&self) -> usize {
+                self.end - self.start
+        }
+
+        pub fn is_empty(&self) -> bool {
+                self.start == self.end
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_engine() {
+                let engine = Engine::new(r"(\d+)_(?<name>\w+)?", false, true).unwrap();
+                assert!(engine.standard().is_some());
+                let caps = engine.captures("a12_b").unwrap();
+                assert_eq!(caps.whole().as_str(), "12_b");
+                assert_eq!(caps.iter().map(|group| group.map(|group| group.start())).collect::<Vec<_>>(),
+                           vec![Some(1), Some(1), Some(4)]);
+                assert_eq!(engine.capture_names().collect::<Vec<_>>(), vec![None, None, Some("name")]);
+                assert_eq!(engine.captures_iter("1_ 2_").count(), 2);
+
+                // Lookaround and backreferences are beyond the standard engine
+                assert!(Engine::new(r"(\w)\1", false, false).is_err());
+                assert_eq!(Engine::new(r"(?<=x)y", false, true).is_ok(), cfg!(feature = "fancy"));
+        }
+
+        #[cfg(feature = "fancy")]
+        #[test]
+        fn test_fancy_engine() {
+                let engine = Engine::new(r"(\w)\1", true, true).unwrap();
+                assert!(engine.standard().is_none());
+                assert!(engine.is_match("aA"));
+                assert!(!engine.is_match("ab"));
+                let mut dst = String::new();
+                engine.captures("xaay").unwrap().expand("[$1]", &mut dst);
+                assert_eq!(dst, "[a]");
+                let engine = Engine::new(r"\d+(?=\.jpg$)", false, true).unwrap();
+                assert_eq!(engine.captures("img_2024.jpg").unwrap().whole().as_str(), "2024");
+        }
+}
@@ -3,3 +3,15 @@
 // Early dev phase error type that can flow into standard error handling with type coercions.
 pub type Result<T> = core::result::Result<T, Error>;
 pub type Error = Box<dyn std::error::Error>;
+
+/// Nothing matched.  Reported only through the exit code (1, as `grep` does), not as an error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoMatches;
+
+impl std::fmt::Display for NoMatches {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "no matches")
+        }
+}
+
+impl std::error::Error for NoMatches {}
@@ -0,0 +1,61 @@
+//! `features` subcommand: which optional capabilities this binary was compiled with.
+//!
+//! Distro packages may be built with a different feature set than a `cargo install`; this lets users check what
+//! their binary supports before reaching for a token or flag that depends on an optional capability.
+
+use std::io::{self, Write};
+
+use owo_colors::OwoColorize;
+
+use crate::{error::Result, output::Output, version};
+
+/// An optional, cargo-feature gated capability.
+#[derive(Debug, Clone, Copy)]
+pub struct Capability {
+        /// Cargo feature that enables it.
+        pub feature: &'static str,
+        /// What it does.
+        pub summary: &'static str,
+        /// Replacement tokens and flags that depend on it.
+        pub unlocks: &'static str,
+}
+
+impl Capability {
+        /// Whether the capability was compiled into this binary.
+        pub fn is_enabled(&self) -> bool {
+                version::FEATURES.split(',').any(|feature| feature == self.feature)
+        }
+}
+
+/// Every optional capability: each cargo feature in `Cargo.toml`, compiled in or not.
+pub const CAPABILITIES: &[Capability] = &[FANCY];
+
+const FANCY: Capability = Capability { feature: "fancy",
+                                       summary: "fancy-regex engine",
+                                       unlocks: "`--fancy`: lookaround and backreferences in patterns", };
+
+/// Prints each capability with whether it is compiled in.
+#[tracing::instrument]
+pub fn run() -> Result<()> {
+        let mut out = Output::stdout();
+        print(&mut out)?;
+        out.flush()?;
+        Ok(())
+}
+
+/// One line per capability: status, feature name, summary, and what it unlocks.
+fn print(out: &mut impl Write) -> io::Result<()> {
+        for capability in CAPABILITIES {
+                let status = match capability.is_enabled() {
+                        true => "yes".green().to_string(),
+                        false => "no ".red().to_string(),
+                };
+                writeln!(out,
+                         "{} {:<8} {} ({})",
+                         status,
+                         capability.feature.cyan(),
+                         capability.summary,
+                         capability.unlocks.dimmed())?;
+        }
+        writeln!(out, "target: {}, enabled features: {}", version::TARGET, version::FEATURES)
+}
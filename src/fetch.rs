@@ -0,0 +1,161 @@
+//! Per-entry filesystem work (stat, canonicalize, ...), a batch of entries at a time, on a few threads.
+//!
+//! Such work is IO bound; on network filesystems issuing the calls one entry after another dominates the run.  A
+//! batch is split across up to `threads` threads, and results come back in batch order.
+//!
+//! With `--entry-timeout`, each part of a batch runs on a detached worker thread, and an entry whose work takes longer
+//! is given up on.  This covers every per-entry call made while planning: the stat for the metadata filters,
+//! `--changed-only`, and `{mtime:...}`; the `--skip-marked` xattr read; and canonicalizing and counting the contents
+//! of a renamed directory.  It does not cover the walk's own directory reads (or its stats of followed symlinks), the
+//! conflict checks on the finished plan, or the renames themselves.
+
+use std::{ops::Range,
+          sync::{Arc,
+                 atomic::{AtomicU8, AtomicUsize, Ordering},
+                 mpsc::{self, RecvTimeoutError}},
+          thread,
+          time::Duration};
+
+/// Workers given up on whose calls have yet to return, across all batches.
+static STUCK_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Stuck workers beyond which entries are given up on without trying.  On a dead mount every call hangs, and each
+/// would strand another thread.
+const MAX_STUCK_WORKERS: usize = 64;
+
+/// How per-entry work is run: threads to spread a batch over (`--metadata-threads`), and how long to wait on any one
+/// entry (`--entry-timeout`).
+#[derive(Debug, Clone, Copy)]
+pub struct Fetcher {
+        pub threads: usize,
+        pub timeout: Option<Duration>,
+}
+
+impl Fetcher {
+        /// Runs `work` on each of `items`, returning the results in order.
+        ///
+        /// With a `timeout`, an item whose work takes longer (e.g. a stat on a dead network mount) is given up on: its
+        /// result is `None`.  The stuck call cannot be interrupted; its worker is left behind, and the rest of its part
+        /// of the batch goes to a fresh one.  While [`MAX_STUCK_WORKERS`] are stuck, items are given up on untried.
+        pub fn map<T, R, F>(&self, items: Arc<Vec<T>>, work: F) -> Vec<Option<R>>
+                where T: Send+Sync+'static,
+                      R: Send+'static,
+                      F: Fn(&T) -> R+Send+Sync+'static
+        {
+                let work = Arc::new(work);
+                let run = |range: Range<usize>| -> Vec<Option<R>> {
+                        match self.timeout {
+                                Some(timeout) => within(&items, range, &work, timeout),
+                                None => range.map(|index| Some(work(&items[index]))).collect(),
+                        }
+                };
+                if self.threads <= 1 || items.len() < 2 {
+                        return run(0..items.len());
+                }
+                let chunk_size = items.len().div_ceil(self.threads);
+                thread::scope(|scope| {
+                        let handles: Vec<_> = (0..items.len()).step_by(chunk_size)
+                                                              .map(|start| {
+                                                                      let range = start..items.len()
+                                                                                              .min(start + chunk_size);
+                                                                      scope.spawn(|| run(range))
+                                                              })
+                                                              .collect();
+                        handles.into_iter()
+                               .flat_map(|handle| handle.join().expect("per-entry work does not panic"))
+                               .collect()
+                })
+        }
+}
+
+/// `work` on each of `items[range]` on a detached worker, giving up (`None`) on an item after `timeout`.
+fn within<T, R, F>(items: &Arc<Vec<T>>, range: Range<usize>, work: &Arc<F>, timeout: Duration) -> Vec<Option<R>>
+        where T: Send+Sync+'static,
+              R: Send+'static,
+              F: Fn(&T) -> R+Send+Sync+'static
+{
+        const RUNNING: u8 = 0;
+        const DONE: u8 = 1;
+        const GIVEN_UP: u8 = 2;
+
+        let mut results = Vec::with_capacity(range.len());
+        let mut next = range.start;
+        while next < range.end {
+                if STUCK_WORKERS.load(Ordering::SeqCst) >= MAX_STUCK_WORKERS {
+                        tracing::debug!("{} workers stuck; giving up on {} entries untried",
+                                        MAX_STUCK_WORKERS,
+                                        range.end - next);
+                        results.resize_with(range.len(), || None);
+                        break;
+                }
+                let (sender, receiver) = mpsc::channel();
+                let state = Arc::new(AtomicU8::new(RUNNING));
+                let (worker_items, worker_work, worker_state) =
+                        (Arc::clone(items), Arc::clone(work), Arc::clone(&state));
+                let worker_range = next..range.end;
+                // Detached, not scoped: a hung call must not hold up the run
+                thread::spawn(move || {
+                        for item in &worker_items[worker_range] {
+                                // The receiver is gone once the worker is given up on
+                                if sender.send(worker_work(item)).is_err() {
+                                        break;
+                                }
+                        }
+                        if worker_state.swap(DONE, Ordering::SeqCst) == GIVEN_UP {
+                                STUCK_WORKERS.fetch_sub(1, Ordering::SeqCst);
+                        }
+                });
+                while next < range.end {
+                        next += 1;
+                        match receiver.recv_timeout(timeout) {
+                                Ok(result) => results.push(Some(result)),
+                                Err(RecvTimeoutError::Timeout) => {
+                                        results.push(None);
+                                        // Counted before it is marked, so a worker that returns meanwhile cannot take
+                                        // the count below zero
+                                        STUCK_WORKERS.fetch_add(1, Ordering::SeqCst);
+                                        if state.compare_exchange(RUNNING, GIVEN_UP, Ordering::SeqCst, Ordering::SeqCst)
+                                                .is_err()
+                                        {
+                                                STUCK_WORKERS.fetch_sub(1, Ordering::SeqCst);
+                                        }
+                                        break;
+                                }
+                                Err(RecvTimeoutError::Disconnected) => {
+                                        results.push(None);
+                                        break;
+                                }
+                        }
+                }
+        }
+        results
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        /// Results come back in order, whatever the threads; work that overruns the timeout is given up on.
+        #[test]
+        fn test_map() {
+                let items = Arc::new((0..10).collect::<Vec<u64>>());
+                let expected: Vec<_> = (0..10).map(|n| Some(n * 2)).collect();
+                for threads in [1, 3, 16] {
+                        for timeout in [None, Some(Duration::from_secs(60))] {
+                                let fetcher = Fetcher { threads, timeout };
+                                assert_eq!(fetcher.map(Arc::clone(&items), |n| n * 2), expected);
+                        }
+                }
+                let fetcher = Fetcher { threads: 2, timeout: Some(Duration::from_millis(50)) };
+                let slow_odd = |n: &u64| {
+                        if n % 2 == 1 {
+                                thread::sleep(Duration::from_secs(1));
+                        }
+                        *n
+                };
+                let items = Arc::new(vec![0, 1, 2, 3, 4]);
+                assert_eq!(fetcher.map(items, slow_odd), vec![Some(0), None, Some(2), None, Some(4)]);
+        }
+}
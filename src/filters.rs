@@ -0,0 +1,229 @@
+//! Candidate filters beyond the pattern: by extension (`--ext`), and on entry metadata: `--larger-than`,
+//! `--smaller-than`, and modification time (`--newer-than`, `--older-than`, and their `-file` forms).
+//!
+//! Metadata filters are checked only when given, as they cost a `stat` per matched entry (fetched once per entry, with
+//! the rest of its batch; see [`crate::fetch`]).  The extension filter only looks at name bytes.
+
+use std::{ffi::OsStr,
+          fmt,
+          fs::Metadata,
+          path::Path,
+          str::FromStr,
+          time::{Duration, SystemTime}};
+
+use serde::{Deserialize, Deserializer, de};
+
+/// Extensions for `--ext`, compared case-insensitively (ASCII) against a name's final extension.
+#[derive(Debug, Clone, Default)]
+pub struct Extensions(Vec<Vec<u8>>);
+
+impl Extensions {
+        /// From the extensions as given, with or without a leading dot (`jpg`, `.JPG`).
+        pub fn new(extensions: &[String]) -> Self {
+                Self(extensions.iter().map(|ext| ext.trim_start_matches('.').as_bytes().to_vec()).collect())
+        }
+
+        /// Whether `name`'s final extension is one of these (always, if there are none).  A leading dot marks a
+        /// hidden file, not an extension.
+        pub fn contains(&self, name: &OsStr) -> bool {
+                if self.0.is_empty() {
+                        return true;
+                }
+                let name = name.as_encoded_bytes();
+                let Some(dot) = name.iter().rposition(|&byte| byte == b'.').filter(|&dot| dot > 0) else {
+                        return false;
+                };
+                self.0.iter().any(|ext| ext.eq_ignore_ascii_case(&name[dot + 1..]))
+        }
+}
+
+/// A size in bytes, as given on the command line: `N` with an optional binary unit, `k`, `M`, `G`, or `T`
+/// (case-insensitive, optionally followed by `B` or `iB`).  `4k` is 4096 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Size(pub u64);
+
+impl FromStr for Size {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+                let (number, unit) = s.split_at(split);
+                let unit = unit.trim_end_matches("iB").trim_end_matches(['B', 'b']);
+                let exponent = match unit.to_ascii_lowercase().as_str() {
+                        "" => 0,
+                        "k" => 1,
+                        "m" => 2,
+                        "g" => 3,
+                        "t" => 4,
+                        _ => return Err(format!("unknown size unit in {:?} (expected k, M, G, or T)", s)),
+                };
+                match number.parse::<f64>() {
+                        Ok(number) if number >= 0.0 => Ok(Self((number * 1024_f64.powi(exponent)) as u64)),
+                        _ => Err(format!("expected a size, e.g. 10M or 4k; got {:?}", s)),
+                }
+        }
+}
+
+/// As given on the command line, e.g. `larger_than = "10M"` in a recipe file.
+impl<'de> Deserialize<'de> for Size {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+        }
+}
+
+impl fmt::Display for Size {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} bytes", self.0)
+        }
+}
+
+/// Size bounds (exclusive) for candidates.  Only files have a size here: with any bound, other entries are
+/// filtered out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeRange {
+        pub larger_than:  Option<Size>,
+        pub smaller_than: Option<Size>,
+}
+
+impl SizeRange {
+        pub fn is_unbounded(&self) -> bool {
+                self.larger_than.is_none() && self.smaller_than.is_none()
+        }
+
+        /// Whether an entry with `metadata` is a file within the bounds.  Entries whose metadata could not be read
+        /// (`None`) are not.
+        pub fn contains(&self, metadata: Option<&Metadata>) -> bool {
+                if self.is_unbounded() {
+                        return true;
+                }
+                let Some(size) = metadata.filter(|metadata| metadata.is_file()).map(Metadata::len) else {
+                        return false;
+                };
+                self.larger_than.is_none_or(|min| size > min.0) && self.smaller_than.is_none_or(|max| size < max.0)
+        }
+}
+
+/// A time span, as given on the command line: `N` with a unit, `s`, `m`, `h`, `d`, or `w` (e.g. `2d`, `90m`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Age(pub Duration);
+
+impl FromStr for Age {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+                let (number, unit) = s.split_at(split);
+                let unit_secs = match unit {
+                        "s" => 1,
+                        "m" => 60,
+                        "h" => 60 * 60,
+                        "d" => 24 * 60 * 60,
+                        "w" => 7 * 24 * 60 * 60,
+                        _ => return Err(format!("unknown time unit in {:?} (expected s, m, h, d, or w)", s)),
+                };
+                match number.parse::<f64>() {
+                        Ok(number) if number >= 0.0 && number.is_finite() => {
+                                Ok(Self(Duration::from_secs_f64(number * f64::from(unit_secs))))
+                        }
+                        _ => Err(format!("expected a time span, e.g. 2d or 90m; got {:?}", s)),
+                }
+        }
+}
+
+/// Modification time of a reference file, for `--newer-than-file`/`--older-than-file`.
+pub fn reference_mtime(path: &str) -> Result<SystemTime, String> {
+        Path::new(path).metadata()
+                       .and_then(|metadata| metadata.modified())
+                       .map_err(|e| format!("could not read the modification time of {:?}: {}", path, e))
+}
+
+/// Modification time bounds (exclusive) for candidates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MtimeRange {
+        pub newer_than: Option<SystemTime>,
+        pub older_than: Option<SystemTime>,
+}
+
+impl MtimeRange {
+        pub fn is_unbounded(&self) -> bool {
+                self.newer_than.is_none() && self.older_than.is_none()
+        }
+
+        /// Whether an entry with `metadata` was last modified within the bounds.  Entries whose mtime could not be
+        /// read are not.
+        pub fn contains(&self, metadata: Option<&Metadata>) -> bool {
+                if self.is_unbounded() {
+                        return true;
+                }
+                let Some(modified) = metadata.and_then(|metadata| metadata.modified().ok()) else {
+                        return false;
+                };
+                self.newer_than.is_none_or(|min| modified > min) && self.older_than.is_none_or(|max| modified < max)
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use std::fs;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_extensions() {
+                let extensions = Extensions::new(&["jpg".to_string(), ".PNG".to_string()]);
+                assert!(extensions.contains(OsStr::new("a.JPG")));
+                assert!(extensions.contains(OsStr::new("a.tar.png")));
+                assert!(!extensions.contains(OsStr::new("a.jpg.gz")));
+                assert!(!extensions.contains(OsStr::new("jpg")));
+                assert!(!extensions.contains(OsStr::new(".jpg")));
+                assert!(Extensions::default().contains(OsStr::new("README")));
+        }
+
+        #[test]
+        fn test_size_range() -> Result<(), Box<dyn std::error::Error>> {
+                assert_eq!("4k".parse::<Size>(), Ok(Size(4096)));
+                assert_eq!("1.5MiB".parse::<Size>(), Ok(Size(1_572_864)));
+                assert_eq!("10".parse::<Size>(), Ok(Size(10)));
+                assert!("10 parsecs".parse::<Size>().is_err());
+
+                let temp_dir = TempDir::new()?;
+                fs::write(temp_dir.path().join("small"), [0; 10])?;
+                fs::write(temp_dir.path().join("large"), [0; 5000])?;
+                let metadata = |name| temp_dir.path().join(name).metadata().ok();
+                let range = SizeRange { larger_than: Some(Size(10)), smaller_than: Some("4k".parse()?) };
+                assert!(!range.contains(metadata("small").as_ref()));
+                assert!(!range.contains(metadata("large").as_ref()));
+                let range = SizeRange { larger_than: Some(Size(9)), ..range };
+                assert!(range.contains(metadata("small").as_ref()));
+                assert!(!range.contains(temp_dir.path().metadata().ok().as_ref()));
+                assert!(!range.contains(None));
+                assert!(SizeRange::default().contains(metadata("large").as_ref()));
+                Ok(())
+        }
+
+        #[test]
+        fn test_mtime_range() -> Result<(), Box<dyn std::error::Error>> {
+                assert_eq!("2d".parse::<Age>(), Ok(Age(Duration::from_secs(2 * 24 * 60 * 60))));
+                assert_eq!("1.5m".parse::<Age>(), Ok(Age(Duration::from_secs(90))));
+                assert!("2 fortnights".parse::<Age>().is_err());
+
+                let temp_dir = TempDir::new()?;
+                let (old, new) = (temp_dir.path().join("old"), temp_dir.path().join("new"));
+                fs::write(&old, "")?;
+                fs::write(&new, "")?;
+                let an_hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+                fs::File::options().write(true).open(&old)?.set_modified(an_hour_ago - Duration::from_secs(60))?;
+                let metadata = |path: &Path| path.metadata().ok();
+                let recent = MtimeRange { newer_than: Some(an_hour_ago), older_than: None };
+                assert!(recent.contains(metadata(&new).as_ref()));
+                assert!(!recent.contains(metadata(&old).as_ref()));
+                let before_new =
+                        MtimeRange { newer_than: None, older_than: Some(reference_mtime(new.to_str().unwrap())?) };
+                assert!(before_new.contains(metadata(&old).as_ref()));
+                assert!(reference_mtime("no/such/file").is_err());
+                Ok(())
+        }
+}
@@ -0,0 +1,142 @@
+//! `--emit-test-fixture <PATH>`: a JSON reproduction of a run, for bug reports and regression tests.
+//!
+//! Records the tree shape under each root before the run (names only, no contents), the shape the plan would leave
+//! behind, and what produced the plan.  Directories end with `/`.  Written before anything is renamed, so a
+//! preview is enough.
+//!
+//! # Example:
+//! ```text
+//! {"origin": {"pattern": "^IMG_", ...}, "command_line": ["^IMG_", "--rep", "shot_", "--preview"],
+//!  "roots": [{"root": "photos", "before": ["IMG_1.jpg", "old/"], "after": ["old/", "shot_1.jpg"]}]}
+//! ```
+
+use std::{collections::{BTreeSet, HashMap},
+          fs,
+          path::{Path, PathBuf}};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{error::Result,
+            plan::{Plan, PlanOrigin}};
+
+/// A run's reproduction.
+#[derive(Debug, Serialize)]
+pub struct Fixture<'a> {
+        pub origin:       &'a PlanOrigin,
+        /// Arguments the tool was run with.
+        pub command_line: Vec<String>,
+        pub roots:        Vec<RootShape>,
+}
+
+/// Tree shape under one root, as paths relative to it (`/`-separated, sorted).
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RootShape {
+        pub root:   PathBuf,
+        pub before: Vec<String>,
+        /// As `before`, with the plan's renames applied.
+        pub after:  Vec<String>,
+}
+
+impl<'a> Fixture<'a> {
+        /// Captures the shape of every root with planned entries, down to `max_depth`.
+        pub fn capture(plan: &Plan, origin: &'a PlanOrigin, max_depth: usize) -> Self {
+                let mut roots: Vec<&Path> = Vec::new();
+                for entry in &plan.entries {
+                        if !roots.contains(&entry.root.as_path()) {
+                                roots.push(&entry.root);
+                        }
+                }
+                let command_line = std::env::args_os().skip(1).map(|arg| arg.to_string_lossy().into_owned()).collect();
+                Self { origin,
+                       command_line,
+                       roots: roots.into_iter().map(|root| RootShape::capture(plan, root, max_depth)).collect() }
+        }
+
+        /// Writes the fixture as pretty-printed JSON.
+        pub fn write(&self, path: &Path) -> Result<()> {
+                fs::write(path, serde_json::to_string_pretty(self)?).map_err(|e| {
+                                                                            format!("Could not write fixture {:?}: {}",
+                                                                                    path, e)
+                                                                    })?;
+                tracing::warn!("Test fixture written to {:?}", path);
+                Ok(())
+        }
+}
+
+impl RootShape {
+        fn capture(plan: &Plan, root: &Path, max_depth: usize) -> Self {
+                // New names by (original) path relative to the root
+                let renames: HashMap<PathBuf, &str> =
+                        plan.entries
+                            .iter()
+                            .filter(|entry| entry.root == root)
+                            .filter_map(|entry| Some((relative(root, &entry.path), entry.new_filename.as_deref()?)))
+                            .collect();
+                let mut before = BTreeSet::new();
+                let mut after = BTreeSet::new();
+                let walk = match root.is_dir() {
+                        true => WalkDir::new(root).min_depth(1).max_depth(max_depth),
+                        false => WalkDir::new(root).max_depth(0),
+                };
+                for entry in walk.into_iter().filter_map(|entry| entry.ok()) {
+                        let path = relative(root, entry.path());
+                        let suffix = if entry.file_type().is_dir() { "/" } else { "" };
+                        // Each component is renamed if the path up to it is
+                        let mut original = PathBuf::new();
+                        let mut renamed = Vec::new();
+                        for component in path.iter() {
+                                original.push(component);
+                                renamed.push(renames.get(&original).map_or_else(|| {
+                                                                                        component.to_string_lossy()
+                                                                                                 .into_owned()
+                                                                                },
+                                                                                |name| name.to_string()));
+                        }
+                        before.insert(format!("{}{}", slashed(&path), suffix));
+                        after.insert(format!("{}{}", renamed.join("/"), suffix));
+                }
+                Self { root:   root.to_path_buf(),
+                       before: before.into_iter().collect(),
+                       after:  after.into_iter().collect(), }
+        }
+}
+
+/// `path` relative to `root`; a root itself (e.g. a file root) is its name.
+fn relative(root: &Path, path: &Path) -> PathBuf {
+        match path.strip_prefix(root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+                _ => path.file_name().map(PathBuf::from).unwrap_or_default(),
+        }
+}
+
+/// `path` with `/` separators on every platform.
+fn slashed(path: &Path) -> String {
+        path.iter().map(|component| component.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+        use crate::plan::PlanEntry;
+
+        #[test]
+        fn test_root_shape() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let root = temp_dir.path();
+                fs::create_dir_all(root.join("old/inner"))?;
+                fs::write(root.join("old/inner/IMG_1.jpg"), "")?;
+                fs::write(root.join("notes.txt"), "")?;
+                let mut plan = Plan::default();
+                plan.push(PlanEntry::new(root, &root.join("old/inner/IMG_1.jpg"), false, Some("shot_1.jpg".into())));
+                plan.push(PlanEntry::new(root, &root.join("old"), true, Some("new".into())));
+
+                let shape = RootShape::capture(&plan, root, usize::MAX);
+                assert_eq!(shape.before, ["notes.txt", "old/", "old/inner/", "old/inner/IMG_1.jpg"]);
+                assert_eq!(shape.after, ["new/", "new/inner/", "new/inner/shot_1.jpg", "notes.txt"]);
+                Ok(())
+        }
+}
@@ -0,0 +1,248 @@
+//! Filesystem-free planning, for fuzzers and property tests.
+//!
+//! [`plan`] builds a [`Plan`] for a virtual tree given as `/`-separated relative paths, with the same matching,
+//! name building, validation, ordering, and conflict rules as a real run.  [`simulate`] then carries the plan out on
+//! that virtual tree.  The property to hunt for: a plan without [`conflicts`] must simulate cleanly, i.e. every
+//! entry still exists when its turn comes and nothing is overwritten.
+//!
+//! The virtual tree is a [`VirtualFs`], which can behave like a case-insensitive and/or normalization-insensitive
+//! filesystem ([`FsFlavor`]), so case-only renames and NFC/NFD collisions can be tested on any host.  The `_on`
+//! functions take one; the others use a case-sensitive, byte-exact tree ([`FsFlavor::POSIX`]).
+//!
+//! The fuzz target is in `fuzz/` (run with `cargo +nightly fuzz run planner`).
+
+use std::{collections::{BTreeMap, BTreeSet},
+          path::{Component, Path, PathBuf}};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{matcher::Matcher,
+            plan::{Conflict, Plan, PlanEntry},
+            transform::Transform,
+            validate::NameRules};
+
+/// What to plan: the pattern, how new names are built, and which otherwise rejected names to allow.
+#[derive(Debug)]
+pub struct PlanOptions {
+        pub matcher:   Matcher,
+        pub transform: Transform,
+        pub rules:     NameRules,
+}
+
+/// How a [`VirtualFs`] compares names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsFlavor {
+        /// `A` and `a` name the same entry (as on default Windows and macOS volumes).
+        pub case_insensitive:          bool,
+        /// Composed (NFC) and decomposed (NFD) spellings of a name are the same entry (as on macOS volumes).
+        pub normalization_insensitive: bool,
+}
+
+impl FsFlavor {
+        /// Case- and normalization-insensitive names, as on default APFS volumes.
+        pub const MACOS: Self = Self { case_insensitive: true, normalization_insensitive: true };
+        /// Byte-exact names, as on most Linux filesystems.
+        pub const POSIX: Self = Self { case_insensitive: false, normalization_insensitive: false };
+        /// Case-insensitive names, as on default NTFS volumes.
+        pub const WINDOWS: Self = Self { case_insensitive: true, normalization_insensitive: false };
+
+        /// Form of a path that is equal for every spelling naming the same entry.
+        fn key(&self, path: &Path) -> PathBuf {
+                path.iter()
+                    .map(|component| {
+                            let component = component.to_string_lossy();
+                            let component = match self.normalization_insensitive {
+                                    true => component.nfc().collect(),
+                                    false => component.into_owned(),
+                            };
+                            match self.case_insensitive {
+                                    true => component.to_lowercase(),
+                                    false => component,
+                            }
+                    })
+                    .collect()
+        }
+}
+
+/// An in-memory tree of entries (names only), compared as its [`FsFlavor`] dictates.
+#[derive(Debug, Clone)]
+pub struct VirtualFs {
+        flavor:  FsFlavor,
+        /// Path as spelled when created, by [`FsFlavor::key`].
+        entries: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl VirtualFs {
+        /// A tree of every path given and its ancestors (which are directories).  Paths that are empty, absolute, or
+        /// contain `..` stop at the last usable component.  Of several spellings of one entry, the first is kept.
+        pub fn new(flavor: FsFlavor, names: &[String]) -> Self {
+                let mut fs = Self { flavor, entries: BTreeMap::new() };
+                for name in names {
+                        let mut path = PathBuf::new();
+                        for component in Path::new(name).components() {
+                                match component {
+                                        Component::Normal(component) => path.push(component),
+                                        Component::CurDir => continue,
+                                        _ => break,
+                                }
+                                // Keep an existing spelling, so descendants share their parent's
+                                path = fs.entries.entry(flavor.key(&path)).or_insert(path).clone();
+                        }
+                }
+                fs
+        }
+
+        /// Every entry, as spelled, in sorted order.
+        pub fn paths(&self) -> BTreeSet<PathBuf> {
+                self.entries.values().cloned().collect()
+        }
+
+        /// Whether `path`, in any spelling the flavor considers the same, exists.
+        pub fn contains(&self, path: &Path) -> bool {
+                self.entries.contains_key(&self.flavor.key(path))
+        }
+
+        /// Whether two spellings name the same entry.
+        pub fn is_same(&self, a: &Path, b: &Path) -> bool {
+                self.flavor.key(a) == self.flavor.key(b)
+        }
+
+        /// Renames `from` (and everything beneath it) to `to`.  Fails if `from` is missing, or `to` exists and is not
+        /// `from` itself (a case-only rename on a case-insensitive flavor is fine).
+        pub fn rename(&mut self, from: &Path, to: &Path) -> Result<(), String> {
+                let from_key = self.flavor.key(from);
+                let Some(from) = self.entries.get(&from_key).cloned() else {
+                        return Err(format!("{:?} is gone by the time it is renamed", from));
+                };
+                if self.contains(to) && !self.is_same(&from, to) {
+                        return Err(format!("{:?} would overwrite {:?}", from, to));
+                }
+                let moved: Vec<PathBuf> =
+                        self.entries.keys().filter(|key| key.starts_with(&from_key)).cloned().collect();
+                for key in moved {
+                        let path = self.entries.remove(&key).expect("moved keys are in the tree");
+                        let relative = path.strip_prefix(&from).expect("moved paths are under the entry");
+                        let path = to.join(relative);
+                        self.entries.insert(self.flavor.key(&path), path);
+                }
+                Ok(())
+        }
+}
+
+/// Plans renames over a virtual tree of `names` (see [`VirtualFs::new`]).  Every entry is a candidate (hidden ones
+/// included).
+pub fn plan(names: &[String], opts: &PlanOptions) -> Plan {
+        plan_on(&VirtualFs::new(FsFlavor::POSIX, names), opts)
+}
+
+/// As [`plan`], over a given tree.
+pub fn plan_on(fs: &VirtualFs, opts: &PlanOptions) -> Plan {
+        let tree: Vec<PathBuf> = fs.paths().into_iter().collect();
+        let mut plan = Plan::default();
+        let mut scratch = String::new();
+        // Sorted paths are in the order of a sorted depth-first walk, which `{n}` numbering depends on
+        for (i, path) in tree.iter().enumerate() {
+                let name = path.file_name().and_then(|name| name.to_str()).expect("tree paths are utf8 names");
+                if !opts.matcher.regex().is_match(name) {
+                        continue;
+                }
+                let is_dir = tree.get(i + 1).is_some_and(|next| next.starts_with(path));
+                let new_filename = opts.transform.apply(&opts.matcher, name, &mut scratch);
+                plan.push(PlanEntry::new_unresolved(Path::new(""), path, is_dir, new_filename));
+        }
+        opts.rules.reject_invalid(&mut plan);
+        plan.sort();
+        plan
+}
+
+/// Conflicts of a [`plan`] over the same tree: every parent is usable, and a destination is taken if it is in the
+/// tree.
+pub fn conflicts(names: &[String], plan: &Plan) -> Vec<Conflict> {
+        conflicts_on(&VirtualFs::new(FsFlavor::POSIX, names), plan)
+}
+
+/// As [`conflicts`], over a given tree.  A destination that is the entry itself (e.g. a case-only rename on a
+/// case-insensitive flavor) is not taken.
+pub fn conflicts_on(fs: &VirtualFs, plan: &Plan) -> Vec<Conflict> {
+        plan.conflicts_with(|_| None,
+                            |entry, destination| fs.contains(destination) && !fs.is_same(&entry.path, destination))
+}
+
+/// Carries out a [`plan`] on the virtual tree, in plan order.  Returns the tree afterwards, or what went wrong first:
+/// an entry that is no longer where it was planned, or a rename onto an existing path.
+pub fn simulate(names: &[String], plan: &Plan) -> Result<BTreeSet<PathBuf>, String> {
+        simulate_on(VirtualFs::new(FsFlavor::POSIX, names), plan).map(|fs| fs.paths())
+}
+
+/// As [`simulate`], on a given tree.
+pub fn simulate_on(mut fs: VirtualFs, plan: &Plan) -> Result<VirtualFs, String> {
+        for entry in &plan.entries {
+                let Some(destination) = entry.destination() else { continue };
+                if destination != entry.path {
+                        fs.rename(&entry.path, &destination)?;
+                }
+        }
+        Ok(fs)
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+        use crate::error::Result;
+
+        #[test]
+        fn test_plan_and_simulate() -> Result<()> {
+                let names: Vec<String> = ["a/b.txt", "a/c.txt", "d.txt", "../up.txt"].map(String::from).to_vec();
+                let opts = PlanOptions { matcher:   Matcher::new("^[ab]")?,
+                                         transform: Transform::replace("x"),
+                                         rules:     NameRules::default(), };
+                let planned = plan(&names, &opts);
+                // Children before their parents
+                let renames: Vec<_> = planned.entries.iter().map(|entry| (entry.path.clone(), entry.is_dir)).collect();
+                assert_eq!(renames, [(PathBuf::from("a/b.txt"), false), (PathBuf::from("a"), true)]);
+                assert!(conflicts(&names, &planned).is_empty());
+                let after = simulate(&names, &planned)?;
+                assert_eq!(after, ["d.txt", "x", "x/c.txt", "x/x.txt"].map(PathBuf::from).into());
+
+                // Renaming onto an existing entry is caught as a conflict, and would overwrite if carried out
+                let names: Vec<String> = ["a", "b"].map(String::from).to_vec();
+                let opts = PlanOptions { matcher: Matcher::new("^a$")?, transform: Transform::replace("b"), ..opts };
+                let planned = plan(&names, &opts);
+                assert!(matches!(conflicts(&names, &planned)[..], [Conflict::DestinationExists { .. }]));
+                assert!(simulate(&names, &planned).is_err());
+                Ok(())
+        }
+
+        /// Case-only renames and NFC/NFD spellings, per flavor.
+        #[test]
+        fn test_fs_flavors() -> Result<()> {
+                let opts = PlanOptions { matcher:   Matcher::new("^readme")?,
+                                         transform: Transform::replace("README"),
+                                         rules:     NameRules::default(), };
+                let names = vec!["readme.md".to_string()];
+                for flavor in [FsFlavor::POSIX, FsFlavor::WINDOWS, FsFlavor::MACOS] {
+                        let fs = VirtualFs::new(flavor, &names);
+                        let planned = plan_on(&fs, &opts);
+                        assert!(conflicts_on(&fs, &planned).is_empty(), "{:?}", flavor);
+                        let after = simulate_on(fs, &planned).map_err(|e| format!("{:?}: {}", flavor, e))?;
+                        assert_eq!(after.paths(), [PathBuf::from("README.md")].into(), "{:?}", flavor);
+                }
+
+                // `café` composed exists; renaming `cafe` to the decomposed spelling collides only on macOS
+                let names = vec!["caf\u{e9}".to_string(), "cafe".to_string()];
+                let opts = PlanOptions { matcher: Matcher::new("^cafe$")?,
+                                         transform: Transform::replace("cafe\u{301}"),
+                                         ..opts };
+                for (flavor, collides) in
+                        [(FsFlavor::POSIX, false), (FsFlavor::WINDOWS, false), (FsFlavor::MACOS, true)]
+                {
+                        let fs = VirtualFs::new(flavor, &names);
+                        let planned = plan_on(&fs, &opts);
+                        assert_eq!(!conflicts_on(&fs, &planned).is_empty(), collides, "{:?}", flavor);
+                        assert_eq!(simulate_on(fs, &planned).is_err(), collides, "{:?}", flavor);
+                }
+                Ok(())
+        }
+}
@@ -0,0 +1,151 @@
+//! `--gitignore`: skip entries ignored by `.gitignore` and `.ignore` files, so build artifacts and vendored trees
+//! are never touched.
+//!
+//! Rules are read with the `ignore` crate's gitignore matcher, per directory, as the walk reaches it:
+//! - `.gitignore` and `.ignore` in every directory from the walk root down (`.ignore` takes precedence), and in the
+//!   root's parents up to the enclosing git repository, if any
+//! - the repository's `.git/info/exclude`, then the user's global git excludes file
+//!
+//! Deeper files take precedence over shallower ones, and a `!pattern` re-includes, as with git.
+//! Ignored directories (and `.git` itself) are pruned from the walk entirely.
+
+use std::{collections::HashMap,
+          path::{Path, PathBuf}};
+
+use ignore::{Match,
+             gitignore::{Gitignore, GitignoreBuilder}};
+
+use crate::error::Result;
+
+/// Names of per-directory ignore files, lowest precedence first.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Ignore rules for entries under one walk root, loaded lazily per directory.
+#[derive(Debug)]
+pub struct IgnoreRules {
+        /// Walk root, as given (walked paths start with it).
+        root:      PathBuf,
+        /// Canonical walk root.
+        canonical: PathBuf,
+        /// Topmost directory whose ignore files apply: the repository root, else the walk root.
+        top:       PathBuf,
+        /// Rules of each directory seen so far; `None` if it has no ignore files.
+        by_dir:    HashMap<PathBuf, Option<Gitignore>>,
+        /// Repository and global excludes, lowest precedence first.
+        fallback:  Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+        pub fn new(root: &Path) -> Self {
+                let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+                let repository = canonical.ancestors().find(|dir| dir.join(".git").exists()).map(Path::to_path_buf);
+                let mut fallback = Vec::new();
+                let (global, error) = Gitignore::global();
+                if let Some(error) = error {
+                        tracing::warn!("Could not read global git excludes: {}", error);
+                }
+                fallback.push(global);
+                if let Some(repository) = &repository {
+                        fallback.push(read_rules(repository, &[".git/info/exclude"]).unwrap_or_else(Gitignore::empty));
+                }
+                let top = repository.unwrap_or_else(|| canonical.clone());
+                Self { root: root.to_path_buf(), canonical, top, by_dir: HashMap::new(), fallback }
+        }
+
+        /// Whether a walked entry is ignored.
+        pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+                let path = match path.strip_prefix(&self.root) {
+                        Ok(relative) => self.canonical.join(relative),
+                        Err(_) => path.to_path_buf(),
+                };
+                // Like git, never look inside a repository's own database
+                if path.file_name().is_some_and(|name| name == ".git") {
+                        return true;
+                }
+                for dir in path.ancestors().skip(1).take_while(|dir| dir.starts_with(&self.top)) {
+                        let rules =
+                                self.by_dir.entry(dir.to_path_buf()).or_insert_with(|| read_rules(dir, IGNORE_FILES));
+                        if let Some(decision) = rules.as_ref().and_then(|rules| decide(rules.matched(&path, is_dir))) {
+                                return decision;
+                        }
+                }
+                self.fallback.iter().rev().find_map(|rules| decide(rules.matched(&path, is_dir))).unwrap_or(false)
+        }
+}
+
+/// Rules from gitignore-style `patterns`, relative to `dir` (e.g. a project config's `ignore`).
+pub fn rules_from_patterns(dir: &Path, patterns: &[String]) -> Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(dir);
+        for pattern in patterns {
+                builder.add_line(None, pattern).map_err(|e| format!("Invalid ignore pattern {:?}: {}", pattern, e))?;
+        }
+        Ok(builder.build()?)
+}
+
+/// Whether any of `rules` ignores the entry at (canonical) `path`, or one of its parents.  Rules for other
+/// directories do not apply.
+pub fn is_ignored_by(rules: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+        rules.iter().filter(|rules| path.starts_with(rules.path())).any(|rules| {
+                                                                           rules.matched_path_or_any_parents(path,
+                                                                                                             is_dir)
+                                                                                .is_ignore()
+                                                                   })
+}
+
+/// `Some(is_ignored)` if the rules decide the entry, `None` to ask the next (shallower) rules.
+fn decide<T>(matched: Match<T>) -> Option<bool> {
+        match matched {
+                Match::None => None,
+                Match::Ignore(_) => Some(true),
+                Match::Whitelist(_) => Some(false),
+        }
+}
+
+/// Rules from whichever of `files` (relative to `dir`) exist, later ones taking precedence.  `None` if none do.
+fn read_rules(dir: &Path, files: &[&str]) -> Option<Gitignore> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut is_any = false;
+        for file in files.iter().map(|file| dir.join(file)).filter(|file| file.is_file()) {
+                is_any = true;
+                if let Some(error) = builder.add(&file) {
+                        tracing::warn!("Problem reading {:?}: {}", file, error);
+                }
+        }
+        if !is_any {
+                return None;
+        }
+        builder.build().map_err(|e| tracing::warn!("Invalid ignore rules in {:?}: {}", dir, e)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+        use std::fs;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_ignore_rules() {
+                let temp_dir = TempDir::new().unwrap();
+                let root = temp_dir.path();
+                fs::create_dir_all(root.join(".git")).unwrap();
+                fs::create_dir_all(root.join("src/generated")).unwrap();
+                fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+                fs::write(root.join("src/.gitignore"), "!keep.log\ngenerated/\n").unwrap();
+                fs::write(root.join("src/.ignore"), "scratch.txt\n").unwrap();
+
+                // walking from a subdirectory still sees the repository's rules
+                let src = root.join("src");
+                let mut rules = IgnoreRules::new(&src);
+                assert!(rules.is_ignored(&src.join("debug.log"), false));
+                assert!(!rules.is_ignored(&src.join("keep.log"), false));
+                assert!(rules.is_ignored(&src.join("generated"), true));
+                assert!(rules.is_ignored(&src.join("scratch.txt"), false));
+                assert!(!rules.is_ignored(&src.join("main.rs"), false));
+                assert!(rules.is_ignored(&src.join("target"), true));
+                assert!(!rules.is_ignored(&src.join("target"), false));
+                assert!(IgnoreRules::new(root).is_ignored(&root.join(".git"), true));
+        }
+}
@@ -0,0 +1,196 @@
+//! Localization of user-facing messages (prompts, summaries, warnings).
+//!
+//! Messages are looked up by id in a per-language catalog, with `{name}` placeholders filled from arguments.
+//! English is the reference catalog; any id missing from another catalog falls back to English.
+//!
+//! Language is taken from the first set of `$RENAME_FILES_LANG`, `$LC_ALL`, `$LC_MESSAGES`, `$LANG`
+//! (e.g. `de_DE.UTF-8` -> `de`).
+//!
+//! # Adding a translation:
+//! Add a `Lang` variant, a catalog of `(id, template)` pairs, and its language code in [`Lang::from_code`].
+//! The catalog test checks that every id exists in English and uses the same placeholders.
+
+use std::{fmt::Display, sync::OnceLock};
+
+/// Supported message languages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+        En,
+        De,
+}
+
+impl Lang {
+        /// Language for a locale code like `de`, `de_DE`, or `de_DE.UTF-8`.  `None` if unsupported.
+        pub fn from_code(code: &str) -> Option<Self> {
+                match code.split(['_', '.', '-']).next()?.to_ascii_lowercase().as_str() {
+                        "en" | "c" | "posix" => Some(Lang::En),
+                        "de" => Some(Lang::De),
+                        _ => None,
+                }
+        }
+
+        /// Language from the environment, defaulting to English.
+        fn from_env() -> Self {
+                ["RENAME_FILES_LANG", "LC_ALL", "LC_MESSAGES", "LANG"].iter()
+                                                                      .filter_map(|var| std::env::var(var).ok())
+                                                                      .find(|value| !value.is_empty())
+                                                                      .and_then(|value| Lang::from_code(&value))
+                                                                      .unwrap_or(Lang::En)
+        }
+
+        fn catalog(self) -> &'static [(&'static str, &'static str)] {
+                match self {
+                        Lang::En => EN,
+                        Lang::De => DE,
+                }
+        }
+}
+
+/// Process-wide message language, read from the environment once.
+pub fn lang() -> Lang {
+        static LANG: OnceLock<Lang> = OnceLock::new();
+        *LANG.get_or_init(Lang::from_env)
+}
+
+/// Localized message `id`, with `{name}` placeholders replaced by the matching arguments.
+pub fn tr(id: &str, args: &[(&str, &dyn Display)]) -> String {
+        tr_in(lang(), id, args)
+}
+
+/// As [`tr`], in a given language.
+pub fn tr_in(lang: Lang, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        let template = lookup(lang.catalog(), id).or_else(|| lookup(EN, id)).unwrap_or(id);
+        // Single pass, so argument values that happen to contain `{...}` are left alone
+        let mut message = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+                message.push_str(&rest[..open]);
+                let after = &rest[open + 1..];
+                let value = after.find('}').and_then(|close| {
+                                                   args.iter()
+                                                       .find(|(name, _)| *name == &after[..close])
+                                                       .map(|(_, value)| (close, value))
+                                           });
+                match value {
+                        Some((close, value)) => {
+                                message.push_str(&value.to_string());
+                                rest = &after[close + 1..];
+                        }
+                        None => {
+                                message.push('{');
+                                rest = after;
+                        }
+                }
+        }
+        message.push_str(rest);
+        message
+}
+
+fn lookup(catalog: &'static [(&'static str, &'static str)], id: &str) -> Option<&'static str> {
+        catalog.iter().find(|(key, _)| *key == id).map(|(_, template)| *template)
+}
+
+/// English (reference) catalog.
+const EN: &[(&str, &str)] =
+        &[("match-found", "Match found: {parent}/{name}"),
+          ("preview-mapping", "--test-run mapping: {parent}/{name} ~~> {new}{note}"),
+          ("renaming", "Renaming: {parent}/{name} ~~> {new}{note}"),
+          ("rejected-mapping", "Not renaming: {parent}/{name} ~~> {new} ({reason})"),
+          ("dir-note", " (directory; {count} descendants relocated)"),
+          ("matches-under-root", "Matches under {root}: {count}"),
+          ("total-matches", "Total matches: {count}"),
+          ("fast-preview-unchecked", "Fast preview: conflicts, protected paths, and new names were NOT checked"),
+          ("reference-preview", "--test-run references: {file} ({count} replacements)"),
+          ("reference-update", "Updating references: {file} ({count} replacements)"),
+          ("confirm-prompt", "{question} Type '{answer}' to continue: "),
+          ("confirm-answer", "yes"),
+          ("confirm-broad-question", "Rename everything matching {pattern} under {root}?"),
+          ("warn-broad-recursive", "Recursively renaming from {root} with the broad pattern {pattern}"),
+          ("err-broad-aborted", "Aborted: broad recursive rename from a filesystem root or home"),
+          ("err-not-terminal", "{question} -- confirmation required, but stdin is not a terminal"),
+          ("err-missing-root", "Root {path} does not exist"),
+          ("err-protected-root", "Refusing to use protected path {path} as a root (see `--allow-protected`)"),
+          ("err-protected-rename", "Refusing to rename protected path {path} (see `--allow-protected`)"),
+          ("warn-safe-mode",
+           "Safe mode: showing a preview only.  Re-run with `--yes` to rename, or set \
+                                `safe_mode = false` in the config file."),
+          ("err-safe-mode", "Safe mode: no files were renamed (pass `--yes` to confirm)"),
+          ("err-conflicts", "{count} conflicting rename(s) found; no files were renamed"),
+          ("warn-skip-referenced", "Skipping rename of directory {path}: referenced in {file}"),
+          ("warn-duplicate-root", "Root {root} is the same directory as {other}; ignoring it."),
+          ("warn-nested-root", "Root {root} is inside {other}; entries reached from both are renamed once.")];
+
+/// German catalog.
+const DE: &[(&str, &str)] =
+        &[("match-found", "Treffer: {parent}/{name}"),
+          ("preview-mapping", "--test-run Zuordnung: {parent}/{name} ~~> {new}{note}"),
+          ("renaming", "Umbenennen: {parent}/{name} ~~> {new}{note}"),
+          ("rejected-mapping", "Nicht umbenannt: {parent}/{name} ~~> {new} ({reason})"),
+          ("dir-note", " (Verzeichnis; {count} Unterpfade verschoben)"),
+          ("matches-under-root", "Treffer unter {root}: {count}"),
+          ("total-matches", "Treffer insgesamt: {count}"),
+          ("fast-preview-unchecked",
+           "Schnellvorschau: Konflikte, geschützte Pfade und neue Namen wurden NICHT geprüft"),
+          ("reference-preview", "--test-run Verweise: {file} ({count} Ersetzungen)"),
+          ("reference-update", "Verweise werden aktualisiert: {file} ({count} Ersetzungen)"),
+          ("confirm-prompt", "{question} Zum Fortfahren '{answer}' eingeben: "),
+          ("confirm-answer", "ja"),
+          ("confirm-broad-question", "Alles unter {root} umbenennen, das auf {pattern} passt?"),
+          ("warn-broad-recursive", "Rekursives Umbenennen ab {root} mit dem sehr allgemeinen Muster {pattern}"),
+          ("err-broad-aborted", "Abgebrochen: rekursives Umbenennen ab Dateisystemwurzel oder Home-Verzeichnis"),
+          ("err-not-terminal", "{question} -- Bestätigung erforderlich, aber stdin ist kein Terminal"),
+          ("err-missing-root", "Wurzel {path} existiert nicht"),
+          ("err-protected-root",
+           "Geschützter Pfad {path} wird nicht als Wurzel verwendet (siehe `--allow-protected`)"),
+          ("err-protected-rename", "Geschützter Pfad {path} wird nicht umbenannt (siehe `--allow-protected`)"),
+          ("warn-safe-mode",
+           "Sicherer Modus: nur Vorschau.  Mit `--yes` erneut ausführen, um umzubenennen, oder \
+                                `safe_mode = false` in der Konfigurationsdatei setzen."),
+          ("err-safe-mode", "Sicherer Modus: keine Dateien umbenannt (zum Bestätigen `--yes` angeben)"),
+          ("err-conflicts", "{count} widersprüchliche Umbenennung(en) gefunden; keine Dateien umbenannt"),
+          ("warn-skip-referenced", "Verzeichnis {path} wird nicht umbenannt: referenziert in {file}"),
+          ("warn-duplicate-root", "Wurzel {root} ist dasselbe Verzeichnis wie {other}; wird ignoriert."),
+          ("warn-nested-root",
+           "Wurzel {root} liegt in {other}; von beiden erreichte Einträge werden einmal \
+                                umbenannt.")];
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        /// Placeholders (`{name}`) used in a template, sorted.
+        fn placeholders(template: &str) -> Vec<&str> {
+                let mut names: Vec<&str> = template.split('{')
+                                                   .skip(1)
+                                                   .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+                                                   .collect();
+                names.sort();
+                names
+        }
+
+        /// Every translated id exists in English, with the same placeholders.
+        #[test]
+        fn test_catalogs_match_english() {
+                for lang in [Lang::De] {
+                        for (id, template) in lang.catalog() {
+                                let english = lookup(EN, id).unwrap_or_else(|| {
+                                                                    panic!("{:?} id not in English: {}", lang, id)
+                                                            });
+                                assert_eq!(placeholders(template), placeholders(english), "{:?} id: {}", lang, id);
+                        }
+                }
+        }
+
+        #[test]
+        fn test_tr() {
+                assert_eq!(tr_in(Lang::En, "total-matches", &[("count", &3)]), "Total matches: 3");
+                assert_eq!(tr_in(Lang::De, "total-matches", &[("count", &3)]), "Treffer insgesamt: 3");
+                assert_eq!(tr_in(Lang::En, "match-found", &[("parent", &"."), ("name", &"{name}")]),
+                           "Match found: ./{name}");
+                assert_eq!(tr_in(Lang::De, "no-such-id", &[]), "no-such-id");
+                assert_eq!(Lang::from_code("de_DE.UTF-8"), Some(Lang::De));
+                assert_eq!(Lang::from_code("fr_FR"), None);
+        }
+}
@@ -0,0 +1,286 @@
+//! `integrate` subcommand: installs file-manager context-menu entries that run this tool on the selected files.
+//!
+//! Each entry asks for a pattern and replacement, shows the preview, and renames only after confirmation.
+//! The selection is passed with `--files-from -`, so only the selected entries are considered.
+//!
+//! # Targets (installed files; dialogs):
+//! - `nautilus`: `~/.local/share/nautilus/scripts/Rename with rename_files`; `zenity`
+//! - `dolphin`: `~/.local/share/kio/servicemenus/rename_files.desktop` (+ script); `kdialog`
+//! - `finder`: `~/Library/Scripts/Applications/Finder/Rename with rename_files.applescript` (Script menu); built-in
+//! - `explorer`: `%APPDATA%\Microsoft\Windows\SendTo\rename_files.cmd`
+//!   (+ `%LOCALAPPDATA%\rename_files\explorer.ps1`); WinForms
+//!
+//! The Windows folders are read from `%APPDATA%`/`%LOCALAPPDATA%` (the defaults under the home directory are only a
+//! fallback), and the `.cmd` runs the script by its full path.
+//!
+//! On Windows the selection is handed over through a temporary UTF-8 list file rather than a pipe, so paths with
+//! spaces or non-ASCII characters survive the console code page.
+
+use std::path::{Path, PathBuf};
+
+use crate::{config, error::Result};
+
+/// File manager to integrate with.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+        /// GNOME Files (Scripts menu).
+        #[default]
+        Nautilus,
+        /// KDE Dolphin (service menu).
+        Dolphin,
+        /// macOS Finder (Script menu).
+        Finder,
+        /// Windows Explorer ("Send to" menu).
+        Explorer,
+}
+
+/// What to install.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct IntegrateArgs {
+        /// File manager.
+        #[arg(value_enum)]
+        target: Target,
+
+        /// Print the files that would be installed instead of writing them.
+        #[arg(long)]
+        dry_run: bool,
+}
+
+/// A file to install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallFile {
+        pub path:          PathBuf,
+        pub contents:      String,
+        pub is_executable: bool,
+}
+
+/// Installs (or, with `--dry-run`, prints) the integration files.
+#[tracing::instrument]
+pub fn run(integrate_args: &IntegrateArgs) -> Result<()> {
+        let home = config::home_dir().ok_or("Could not determine the home directory")?;
+        let exe = std::env::current_exe()?;
+        for file in files(integrate_args.target, &home, &exe) {
+                if integrate_args.dry_run {
+                        println!("# {}\n{}", file.path.to_string_lossy(), file.contents);
+                        continue;
+                }
+                if let Some(dir) = file.path.parent() {
+                        std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&file.path, &file.contents)?;
+                if file.is_executable {
+                        make_executable(&file.path)?;
+                }
+                println!("Installed {}", file.path.to_string_lossy());
+        }
+        Ok(())
+}
+
+/// The files for a target, invoking the binary at `exe`.
+pub fn files(target: Target, home: &Path, exe: &Path) -> Vec<InstallFile> {
+        let exe_path = exe.to_string_lossy();
+        let exe = shell_quote(&exe_path);
+        match target {
+                Target::Nautilus => {
+                        let selection = r#"printf '%s' "$NAUTILUS_SCRIPT_SELECTED_FILE_PATHS""#;
+                        vec![InstallFile { path:
+                                                   home.join(".local/share/nautilus/scripts/Rename with rename_files"),
+                                           contents:      dialog_script(&exe, selection, &ZENITY),
+                                           is_executable: true, }]
+                }
+                Target::Dolphin => {
+                        let script = home.join(".local/share/kio/servicemenus/rename_files.sh");
+                        let desktop = format!("[Desktop Entry]\n\
+                                               Type=Service\n\
+                                               MimeType=all/all;\n\
+                                               Actions=rename_files\n\
+                                               X-KDE-Priority=TopLevel\n\
+                                               \n\
+                                               [Desktop Action rename_files]\n\
+                                               Name=Rename with rename_files\n\
+                                               Icon=edit-rename\n\
+                                               Exec={} %F\n",
+                                              shell_quote(&script.to_string_lossy()));
+                        vec![InstallFile { path:          script,
+                                           contents:      dialog_script(&exe, r#"printf '%s\n' "$@""#, &KDIALOG),
+                                           is_executable: true, },
+                             InstallFile { path:
+                                                   home.join(".local/share/kio/servicemenus/rename_files.desktop"),
+                                           contents:      desktop,
+                                           is_executable: true, }]
+                }
+                Target::Explorer => {
+                        let script =
+                                windows_folder("LOCALAPPDATA", home, "AppData/Local").join("rename_files/explorer.ps1");
+                        let send_to =
+                                windows_folder("APPDATA", home, "AppData/Roaming").join("Microsoft/Windows/SendTo");
+                        // `%%`: a literal `%` in a batch file
+                        let cmd = format!("@echo off\r\n\
+                                           rem Installed by `rename_files integrate explorer`.\r\n\
+                                           powershell.exe -NoProfile -ExecutionPolicy Bypass -WindowStyle Hidden \
+                                           -File \"{}\" %*\r\n",
+                                          script.to_string_lossy().replace('%', "%%"));
+                        vec![InstallFile { path:          script,
+                                           contents:      powershell_script(&exe_path),
+                                           is_executable: false, },
+                             InstallFile { path:          send_to.join("rename_files.cmd"),
+                                           contents:      cmd,
+                                           is_executable: false, }]
+                }
+                Target::Finder => {
+                        let scripts = home.join("Library/Scripts/Applications/Finder");
+                        vec![InstallFile { path:          scripts.join("Rename with rename_files.applescript"),
+                                           contents:      applescript(&exe),
+                                           is_executable: false, }]
+                }
+        }
+}
+
+/// A Windows known folder from its environment variable (e.g. `%LOCALAPPDATA%`), else its default location under
+/// `home`.
+fn windows_folder(var: &str, home: &Path, default: &str) -> PathBuf {
+        std::env::var_os(var).filter(|dir| !dir.is_empty()).map(PathBuf::from).unwrap_or_else(|| home.join(default))
+}
+
+/// Dialog commands of a toolkit (each reads its prompt/text from `$1`).
+struct Dialogs {
+        ask:     &'static str,
+        show:    &'static str,
+        confirm: &'static str,
+}
+
+const ZENITY: Dialogs = Dialogs { ask:     r#"zenity --entry --title=rename_files --text="$1""#,
+                                  show:    r#"zenity --text-info --title=rename_files --width=800 --height=500"#,
+                                  confirm: r#"zenity --question --title=rename_files --text="$1""#, };
+
+const KDIALOG: Dialogs = Dialogs { ask:     r#"kdialog --title rename_files --inputbox "$1""#,
+                                   show:    r#"kdialog --title rename_files --textbox /dev/stdin 800 500"#,
+                                   confirm: r#"kdialog --title rename_files --yesno "$1""#, };
+
+/// POSIX shell script: ask, preview, confirm, rename.  `selection` prints the selected paths, one per line.
+fn dialog_script(exe: &str, selection: &str, dialogs: &Dialogs) -> String {
+        format!(
+                r#"#!/bin/sh
+# Installed by `rename_files integrate`.
+ask() {{ {ask}; }}
+show() {{ {show}; }}
+confirm() {{ {confirm}; }}
+selection() {{ {selection}; }}
+strip_colors() {{ sed 's/\x1b\[[0-9;]*m//g'; }}
+
+pattern=$(ask "Regex to match") || exit 0
+replacement=$(ask "Replacement") || exit 0
+selection "$@" | {exe} "$pattern" --rep "$replacement" --files-from - --preview 2>&1 | strip_colors | show
+confirm "Rename these files?" || exit 0
+selection "$@" | {exe} "$pattern" --rep "$replacement" --files-from - --yes 2>&1 | strip_colors | show
+"#,
+                ask = dialogs.ask,
+                show = dialogs.show,
+                confirm = dialogs.confirm
+        )
+}
+
+/// AppleScript for Finder's Script menu: same flow as [`dialog_script`], with the Finder selection.
+fn applescript(exe: &str) -> String {
+        format!(
+                r#"-- Installed by `rename_files integrate finder`.
+tell application "Finder" to set selectedItems to selection as alias list
+set fileList to ""
+repeat with selectedItem in selectedItems
+	set fileList to fileList & POSIX path of selectedItem & linefeed
+end repeat
+set pattern to text returned of (display dialog "Regex to match" default answer "")
+set replacement to text returned of (display dialog "Replacement" default answer "")
+set command to "printf %s " & quoted form of fileList & " | " & {exe_literal} & " " & quoted form of pattern & " --rep " & quoted form of replacement & " --files-from - "
+set preview to do shell script command & "--preview 2>&1 | perl -pe 's/\\e\\[[0-9;]*m//g'"
+display dialog preview buttons {{"Cancel", "Rename"}} default button "Cancel"
+do shell script command & "--yes"
+"#,
+                exe_literal = applescript_string(exe)
+        )
+}
+
+/// PowerShell script for the Explorer "Send to" entry: same flow as [`dialog_script`], with WinForms dialogs.
+///
+/// Starts with a BOM: Windows PowerShell reads BOM-less scripts in the ANSI code page, which would garble a
+/// non-ASCII binary path.
+fn powershell_script(exe: &str) -> String {
+        format!(
+                "\u{feff}# Installed by `rename_files integrate explorer`.
+param([Parameter(ValueFromRemainingArguments = $true)] [string[]] $Paths)
+Add-Type -AssemblyName Microsoft.VisualBasic, System.Windows.Forms
+[Console]::OutputEncoding = [System.Text.Encoding]::UTF8
+$exe = '{exe}'
+$pattern = [Microsoft.VisualBasic.Interaction]::InputBox('Regex to match', 'rename_files')
+if (-not $pattern) {{ exit }}
+$replacement = [Microsoft.VisualBasic.Interaction]::InputBox('Replacement', 'rename_files')
+$list = [System.IO.Path]::GetTempFileName()
+[System.IO.File]::WriteAllLines($list, $Paths, (New-Object System.Text.UTF8Encoding $false))
+$colors = \"$([char]27)\\[[0-9;]*m\"
+$preview = (& $exe $pattern --rep $replacement --files-from $list --preview 2>&1 | Out-String) -replace $colors, ''
+$answer = [System.Windows.Forms.MessageBox]::Show(\"$preview`nRename these files?\", 'rename_files', 'YesNo')
+if ($answer -eq 'Yes') {{
+    $result = (& $exe $pattern --rep $replacement --files-from $list --yes 2>&1 | Out-String) -replace $colors, ''
+    [System.Windows.Forms.MessageBox]::Show($result, 'rename_files') | Out-Null
+}}
+Remove-Item $list
+",
+                exe = exe.replace('\'', "''")
+        )
+}
+
+/// Single-quotes a string for POSIX shells.
+pub fn shell_quote(s: &str) -> String {
+        format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// An AppleScript string literal.
+fn applescript_string(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+        Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_files() {
+                let exe = Path::new("/opt/my tools/rename_files");
+                let nautilus = files(Target::Nautilus, Path::new("/home/u"), exe);
+                assert_eq!(nautilus.len(), 1);
+                assert!(nautilus[0].contents.contains("'/opt/my tools/rename_files' \"$pattern\""));
+                assert!(nautilus[0].contents.contains("--files-from -"));
+
+                let dolphin = files(Target::Dolphin, Path::new("/home/u"), exe);
+                assert!(dolphin[1].contents
+                                  .contains("Exec='/home/u/.local/share/kio/servicemenus/rename_files.sh' %F"));
+
+                let finder = files(Target::Finder, Path::new("/Users/u"), exe);
+                assert!(finder[0].contents.contains("\"'/opt/my tools/rename_files'\""));
+                assert_eq!(shell_quote("it's"), r"'it'\''s'");
+
+                let explorer = files(Target::Explorer,
+                                     Path::new("C:/Users/Zoë"),
+                                     Path::new("C:/Tools/O'Neil/rename_files.exe"));
+                assert!(explorer[0].contents.starts_with('\u{feff}'));
+                assert!(explorer[0].contents.contains("$exe = 'C:/Tools/O''Neil/rename_files.exe'"));
+                assert!(explorer[0].path.ends_with("rename_files/explorer.ps1"));
+                assert!(explorer[1].path.ends_with("Microsoft/Windows/SendTo/rename_files.cmd"));
+                let script = explorer[0].path.to_string_lossy();
+                assert!(explorer[1].contents.contains(&format!("-File \"{}\" %*", script.replace('%', "%%"))));
+        }
+}
@@ -1,50 +1,1125 @@
 //! CLI interface to allow regex based file searching and renaming
 //! This is just designed for my personal needs and functionality and ergonomics only added as needed.
 
+pub mod alias;
+pub mod batch;
+pub mod bench;
+pub mod config;
+pub mod context;
+pub mod daemon;
+pub mod engine;
 pub mod error;
+pub mod features;
+pub mod fetch;
+pub mod filters;
+pub mod fixture;
+pub mod fuzz;
+pub mod gitignore;
+pub mod i18n;
+pub mod integrate;
 pub mod logging;
+pub mod mapping;
+pub mod marker;
+pub mod matcher;
+pub mod metrics;
+pub mod output;
+pub mod plan;
+pub mod priority;
+pub mod protected;
+pub mod rate;
+pub mod recipe;
+pub mod references;
+pub mod report;
+pub mod roots;
+pub mod shard;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod transform;
+pub mod validate;
+pub mod version;
 
-use clap::Parser;
-use error::Result;
+use std::{borrow::Cow,
+          cell::Cell,
+          ffi::OsString,
+          fmt::Display,
+          fs::Metadata,
+          io::{IsTerminal, Write},
+          path::{Path, PathBuf},
+          sync::Arc,
+          time::SystemTime};
+
+use chrono::Local;
+use clap::{Parser, Subcommand};
+use config::{Config, Profile};
+use context::RunContext;
+use error::{NoMatches, Result};
+use fetch::Fetcher;
+use filters::{Age, Extensions, MtimeRange, Size, SizeRange, reference_mtime};
+use fixture::Fixture;
+use gitignore::IgnoreRules;
+use i18n::tr;
+use ignore::gitignore::Gitignore;
+use mapping::Mapping;
+use matcher::{Matcher, Occurrence, TransformCache};
+use output::Output;
 use owo_colors::OwoColorize;
+use plan::{MatchSubject, PathSep, Plan, PlanEntry, PlanOrigin};
+use protected::ProtectedPaths;
+use rate::{Rate, RateLimiter};
+use recipe::Recipe;
+use references::{ReferenceFiles, ReferenceUpdate};
 use regex::Regex;
-use walkdir::WalkDir;
+use report::{OutputStyle, Reporter};
+use shard::Shard;
+use snapshot::Snapshot;
+use state::RunState;
+use transform::{Counter, InsertAt, Transform, slugify, transliterate};
+use validate::{NameRules, sanitize, truncate};
+use walkdir::{DirEntry, WalkDir};
+
+/// Default for `--max-matches-per-file`.
+pub const DEFAULT_MAX_MATCHES_PER_FILE: usize = 64;
+
+/// Default number of matched entries buffered per metadata batch.
+pub const DEFAULT_WALK_BUFFER: usize = 256;
 
 /// Filename Find and (optionally) Replace using Rust Regex Syntax.  
 ///
 /// Files are *only* renamed if a `--rep(lace)` argument is provided AND `-p/--preview` is *not* provided.  
-#[derive(Parser, Debug)]
-#[command(version, about, long_about)]
+/// In safe mode (off by default, see config) `-y/--yes` is also required.  
+#[derive(Parser, Debug, Default, Clone)]
+#[command(about,
+          long_about,
+          disable_version_flag = true,
+          subcommand_negates_reqs = true,
+          args_conflicts_with_subcommands = true)]
 pub struct Args {
+        #[command(subcommand)]
+        command: Option<Command>,
+
         /// (Rust flavor) regex to search filenames with.
+        #[arg(required_unless_present_any = ["version", "recipe", "regexps"],
+              default_value = "",
+              hide_default_value = true)]
         regex: String,
 
+        /// Regex to search filenames with; repeatable, to match names matching any of several in one pass.  New
+        /// names are built from the first pattern (in the order given) that matches, with its own capture groups.
+        ///
+        /// With `-e`, the first positional argument is a path, not a regex.
+        /// e.g. `rename_files -e '^IMG_(\d+)' -e '^DSC(\d+)' --rep 'photo_$1' ~/Pictures`
+        #[arg(short = 'e', long = "regexp", value_name = "REGEX", conflicts_with = "recipe")]
+        regexps: Vec<String>,
+
+        /// Search for the pattern as a literal string: `(`, `+`, `.`, etc. need no escaping.
+        #[arg(short = 'F', long)]
+        fixed_string: bool,
+
+        /// The pattern must match the whole name (as if written `^(?:...)$`), not just part of it, so `--rep` gives
+        /// the full new name.  e.g. `--exact 'IMG_(\d+)\.jpg' --rep 'photo_$1.jpg'` leaves `old_IMG_1.jpg` alone.
+        #[arg(short = 'x', long, conflicts_with = "full_path")]
+        exact: bool,
+
+        /// Root directories to search, or files to consider on their own (only their names are matched; nothing is
+        /// walked), e.g. `rename_files IMG --rep photo ./IMG_001.jpg`.  Defaults to the current directory.
+        ///
+        /// Matches from all roots are collected into a single plan before anything is renamed,
+        /// so collisions are detected across roots.  With a single root directory, printed paths are relative to it
+        /// (`./sub/a.txt`); with several, they are shown as found.
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+
+        /// Root to search, as a flag (repeatable; added to any positional roots).  Handy in scripts, where the regex
+        /// may come from a variable.  e.g. `rename_files '^IMG_' --path ~/Pictures`
+        #[arg(long = "path", value_name = "PATH", conflicts_with = "files_from")]
+        path_flags: Vec<PathBuf>,
+
+        /// Consider exactly the entries listed (one path per line) in this file instead of walking roots.
+        /// `-` reads the list from stdin.  e.g. `fd -e jpg | rename_files '^' --rep 'x_' --files-from -`
+        #[arg(long, value_name = "FILE", conflicts_with = "paths")]
+        files_from: Option<PathBuf>,
+
+        /// Read the entries to consider from stdin (as `--files-from -`), bypassing the walk.
+        /// e.g. `fd -e jpg | rename_files '^' --rep 'x_' --stdin`
+        #[arg(long, conflicts_with_all = ["paths", "path_flags", "files_from"])]
+        stdin: bool,
+
+        /// Entries in the `--files-from`/`--stdin` list are separated by NUL instead of newlines
+        /// (as from `find -print0` or `fd -0`), so names may contain newlines.
+        #[arg(short = '0', long = "null")]
+        null_separated: bool,
+
         /// Replacement string for regex matches. Use `$1` or `${1}`, etc. to reference capture groups.
+        ///
+        /// Also: `$0`, `${0}`, or `{keep}` for the whole match, `{before}`/`{after}` for the text before/after it,
+        /// `{name}`, `{stem}`, `{ext}` (with its dot) for the whole original name, `{n}`/`{n:03}` for a counter
+        /// (see `--counter-start`), `{now:FORMAT}`/`{mtime:FORMAT}` for the current/modification time (strftime
+        /// format, e.g. `%Y-%m-%d`), and `{parent}`, `{parent.parent}`, ... for the containing directories' names.
+        /// e.g. `'\.jpg$' --rep '_small{keep}'`, `'draft' --rep 'old_${0}'`,
+        /// `'^IMG_\d+' --rep 'photo_{n:03}'`
+        ///
+        /// Case escapes: `\U`/`\L` upper/lowercase what follows, up to `\E`; `\u`/`\l` the next character only.
+        /// e.g. `'^(\w+)_(\w+)' --rep '\U$1\E_$2'`
         #[arg(long = "rep")]
         replacement: Option<String>,
 
+        /// Allow a new name that is only the old extension (`photo.txt` -> `.txt`), which is otherwise rejected.
+        #[arg(long)]
+        allow_bare_ext: bool,
+
+        /// Trim whitespace (and trailing dots) that a replacement leaves at the ends of a new name, instead of
+        /// rejecting the rename.
+        #[arg(long)]
+        auto_trim: bool,
+
+        /// Allow renames that add or remove a leading `.` (hiding or unhiding the entry), which are otherwise rejected.
+        #[arg(long)]
+        allow_dotfile_change: bool,
+
+        /// Match the pattern against each entry's path relative to its root (`dir_1/dir_11/file.txt`, always
+        /// `/`-separated) instead of its name.  Replacements apply to that path too, but may only change the last
+        /// component; entries whose parent components would change are not renamed.  (`--insert` positions still
+        /// count within the name.)
+        #[arg(long)]
+        full_path: bool,
+
+        /// With `--full-path`: the separator between path components, in the matched path and so in replacements
+        /// (`backslash`: `photos\2023\$1`; a component starting with `U`, `L`, `E`, `u`, or `l` then reads as a
+        /// case escape).
+        #[arg(long, value_enum, value_name = "SEP", default_value_t, requires = "full_path")]
+        path_sep: PathSep,
+
+        /// With `--full-path`: let each separator in the pattern (`/`, or an escaped `\\`) match either one, so a
+        /// pattern written for one platform works on the other.
+        #[arg(long, requires = "full_path")]
+        any_sep: bool,
+
+        /// Match the pattern against each name without its (final) extension, and re-append the extension to the
+        /// new name, so a greedy pattern cannot mangle it (`foo.txt` -> `foo_new.txt`, not `foo_new`).
+        #[arg(long, conflicts_with = "full_path")]
+        stem_only: bool,
+
+        /// Match the pattern case-insensitively (as a leading `(?i)`).  Only matching is affected: `--rep` text is
+        /// inserted as written, and captured text (`$1`, `{keep}`, ...) keeps the name's original case.
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Allow lookaround (`(?<=...)`, `(?!...)`) and backreferences (`\1`) in patterns, via fancy-regex.  Only
+        /// patterns the standard engine rejects use it: it backtracks, so it is slower, and a search that hits its
+        /// backtrack limit counts as no match.  Needs the `fancy` feature (see `rename_files features`).
+        #[arg(long)]
+        fancy: bool,
+
+        /// With `--rep`: replace every match in a name, not just the first.  Empty matches are never replaced.
+        #[arg(long, requires = "replacement")]
+        all: bool,
+
+        /// With `--rep`: replace only the Nth match in a name (counting from 1), or the `last`.  e.g. `-e '\d{4}' --rep
+        /// 2022 --occurrence 2` renames `2021_backup_2021.tar` to `2021_backup_2022.tar`.  Empty matches are not
+        /// counted; names with fewer matches are left alone.
+        #[arg(long, value_name = "N|last", requires = "replacement", conflicts_with = "all")]
+        occurrence: Option<Occurrence>,
+
+        /// With `--all`: leave names with more matches than this alone (with a warning), as a guard against
+        /// pathological patterns.
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_MATCHES_PER_FILE, requires = "all")]
+        max_matches_per_file: usize,
+
+        /// Insert this text into matching names instead of regex replacing (the regex only selects entries).
+        /// e.g. `'\.jpg$' --insert '_small' --at before-ext`
+        #[arg(long, value_name = "TEXT", conflicts_with = "replacement")]
+        insert: Option<String>,
+
+        /// Build the entire new name from a template; the regex only selects entries.
+        /// Tokens: `{name}`, `{stem}`, `{ext}` (with its dot), `{n}` (match number; `{n:03}` zero-pads to 3 digits),
+        /// captures (`$1`), `{keep}`, ...
+        /// e.g. `'\.jpg$' --rename-to 'holiday_{n}{ext}'`
+        #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["replacement", "insert"])]
+        rename_to: Option<String>,
+
+        /// First value of the `{n}` counter in `--rep` and `--rename-to` templates (default: 1).
+        #[arg(long, value_name = "N", allow_negative_numbers = true)]
+        counter_start: Option<i64>,
+
+        /// Amount the `{n}` counter advances by per renamed entry; negative counts down (default: 1).
+        #[arg(long, value_name = "N", allow_negative_numbers = true)]
+        counter_step: Option<i64>,
+
+        /// Replace the captured text (the first group that matched, or the whole match) with its value in a
+        /// two-column table: `.csv`, or `.tsv` for tab separated, with no header row.  e.g. with rows like
+        /// `jdoe,Jane Doe`, `-e '^(\w+)_report' --map people.csv` renames `jdoe_report.pdf` to `Jane Doe_report.pdf`.
+        #[arg(long,
+              value_name = "FILE",
+              value_parser = mapping::load,
+              conflicts_with_all = ["replacement", "insert", "rename_to"])]
+        map: Option<Mapping>,
+
+        /// With `--map`: use this for captured text the table has no row for, instead of leaving those names alone.
+        #[arg(long, value_name = "TEXT", requires = "map")]
+        map_default: Option<String>,
+
+        /// Transliterate new names to ASCII: `Über Café.txt` -> `Uber Cafe.txt`.  For tools that choke on non-ASCII
+        /// names.  Applies after `--rep`/`--insert`/`--rename-to`; on its own, transliterates matches' names.
+        #[arg(long)]
+        ascii: bool,
+
+        /// Make new names web-safe: lowercase ASCII letters, digits, and `_`, with accents dropped and any other
+        /// run of characters turned into `-` (the extension is kept).  Applies after `--rep`/`--insert`/`--rename-to`;
+        /// on its own, renames matches to the slugs of their names.  e.g. `My Photo (1).JPG` -> `my-photo-1.jpg`
+        #[arg(long)]
+        slug: bool,
+
+        /// Make new names valid on every platform: replace `<>:"/\|?*` and control characters with `_`, trim
+        /// trailing dots and spaces, and suffix reserved names (`CON`, `nul.txt`, ...) with `_`.  Applies after the
+        /// other transforms; on its own, sanitizes matches' names.  Altered names are listed at the end of the run.
+        #[arg(long)]
+        sanitize: bool,
+
+        /// Cut new names longer than N bytes down to N, keeping the extension and whole characters.  Avoids "file
+        /// name too long" failures partway through a run (e.g. on encrypted home directories, which allow ~143).
+        #[arg(long, value_name = "N")]
+        max_len: Option<usize>,
+
+        /// Where `--insert` puts its text: start, end, before-ext, or offset:N (characters; negative from the end).
+        #[arg(long, value_name = "POSITION", default_value = "before-ext", requires = "insert")]
+        at: InsertAt,
+
+        /// Select the entries that do NOT match the pattern, e.g. to find stragglers after a bulk rename.
+        ///
+        /// Such entries can still be renamed with `--insert` or a `--rename-to` template without capture references
+        /// (there is no match to capture from): `{name}`, `{stem}`, `{ext}`, and `{n}` work as usual.
+        #[arg(long = "not", conflicts_with = "replacement")]
+        invert: bool,
+
         /// Recurse into child directories.
         #[arg(short, long)]
         recurse: bool,
 
+        /// Only consider entries at least this deep (1: a root's direct children).  Implies recursion.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+        min_depth: Option<u16>,
+
+        /// Only consider entries at most this deep (1: a root's direct children, as without `--recurse`).
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+        max_depth: Option<u16>,
+
+        /// Only consider files.  Type filters combine: `--files-only --symlinks-only` considers both.
+        #[arg(long)]
+        files_only: bool,
+
+        /// Only consider directories (they are still descended into either way).
+        #[arg(long)]
+        dirs_only: bool,
+
+        /// Only consider symlinks.
+        #[arg(long)]
+        symlinks_only: bool,
+
+        /// Only consider entries with one of these (final) extensions, compared case-insensitively, e.g.
+        /// `--ext jpg,jpeg,png`.  Checked before the pattern, which still has to match.
+        #[arg(long, value_name = "EXT", value_delimiter = ',')]
+        ext: Vec<String>,
+
+        /// Only consider files larger than this, e.g. `10M` (binary units: k, M, G, T).
+        #[arg(long, value_name = "SIZE")]
+        larger_than: Option<Size>,
+
+        /// Only consider files smaller than this, e.g. `4k`.
+        #[arg(long, value_name = "SIZE")]
+        smaller_than: Option<Size>,
+
+        /// Only consider entries modified less than this long ago, e.g. `2d` (units: s, m, h, d, w).
+        #[arg(long, value_name = "AGE")]
+        newer_than: Option<Age>,
+
+        /// Only consider entries modified more than this long ago, e.g. `4w`.
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<Age>,
+
+        /// Only consider entries modified after this file was.
+        #[arg(long, value_name = "FILE", value_parser = reference_mtime, conflicts_with = "newer_than")]
+        newer_than_file: Option<SystemTime>,
+
+        /// Only consider entries modified before this file was.
+        #[arg(long, value_name = "FILE", value_parser = reference_mtime, conflicts_with = "older_than")]
+        older_than_file: Option<SystemTime>,
+
+        /// Descend into symlinked directories (and match links by their target's type).  Symlink loops are reported
+        /// and skipped.
+        #[arg(long)]
+        follow_symlinks: bool,
+
+        /// Also consider hidden entries (names starting with `.`).  By default they are skipped, and hidden directories
+        /// are not descended into.
+        #[arg(long)]
+        hidden: bool,
+
+        /// Skip entries whose path (relative to its root) matches this regex; matching directories are not descended
+        /// into at all.  Repeatable.  e.g. `--exclude '(^|/)node_modules$' --exclude '(^|/)\.git$'`
+        #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+        exclude: Vec<Regex>,
+
+        /// Skip entries ignored by `.gitignore`/`.ignore` files (and git's excludes), pruning ignored directories.
+        /// See `gitignore.rs` docs.
+        #[arg(long)]
+        gitignore: bool,
+
+        /// Project config files' `ignore` rules (see `config.rs` docs).
+        #[arg(skip)]
+        project_ignores: Vec<Gitignore>,
+
+        /// Fail, renaming nothing, if any part of the tree could not be walked (e.g. permission denied).  By default
+        /// such subtrees are skipped and listed after the run.
+        #[arg(long)]
+        strict_walk: bool,
+
         /// Show replacements that would occur, but don't rename files.
         #[arg(short, long)]
         preview: bool,
+
+        /// Instant, approximate preview: skips all metadata lookups and validation (conflicts, protected renames,
+        /// descendant counts), and says so after the summary (`"validated": false` in `--json`).  Implies `--preview`.
+        /// Options that need metadata (size and time filters, `--changed-only`, `--skip-marked`, `{mtime:...}`) are
+        /// refused with it.
+        #[arg(long,
+              conflicts_with_all = ["larger_than", "smaller_than", "newer_than", "older_than", "newer_than_file",
+                                    "older_than_file", "changed_only", "skip_marked"])]
+        fast_preview: bool,
+
+        /// Matched entries buffered while walking before their metadata is fetched as a batch.
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_WALK_BUFFER)]
+        walk_buffer: usize,
+
+        /// Threads used to fetch entry metadata (stat, canonicalize) for each batch.  0: one per CPU, up to 8.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        metadata_threads: usize,
+
+        /// Give up on an entry whose metadata takes longer than this to read (e.g. on a dead network mount), e.g.
+        /// `30s`.  Such entries are skipped and reported; the run goes on.  Covers each entry's stat, `--skip-marked`
+        /// check, and (for a renamed directory) canonicalizing and counting its contents; not the walk's directory
+        /// reads, the conflict checks, or the renames.  See `fetch.rs` docs.
+        #[arg(long, value_name = "SPAN")]
+        entry_timeout: Option<Age>,
+
+        /// Refuse to run unless every root is on a mounted filesystem below `/`, not an unmounted (empty) mountpoint.
+        /// Give a span (e.g. `2m`) to keep checking that long for the mount to come up.
+        #[arg(long, value_name = "SPAN", num_args = 0..=1, default_missing_value = "0s")]
+        wait_for_mount: Option<Age>,
+
+        /// Don't memoize filename -> replacement transforms.  (Only needed if a replacement depends on more than
+        /// the filename.)
+        #[arg(long)]
+        no_memo: bool,
+
+        /// Skip entries unchanged (same path and modification time) since the last completed run with the same
+        /// root, pattern, and replacement.  For repeated cleanup jobs over large trees.  See `state.rs` docs.
+        #[arg(long, conflicts_with_all = ["files_from", "stdin"])]
+        changed_only: bool,
+
+        /// Tag each renamed entry with an extended attribute (`user.rename_files.run`, set to when the run started),
+        /// for `--skip-marked`.  Linux and macOS; an entry that cannot be tagged is warned about.
+        #[arg(long)]
+        mark: bool,
+
+        /// Skip entries tagged by an earlier `--mark` run, so repeated (e.g. watch or cron) runs never reprocess a
+        /// file, even if its new name matches again.
+        #[arg(long)]
+        skip_marked: bool,
+
+        /// Only rename shard I of N (e.g. `2/4`): a deterministic partition by path relative to the root, so several
+        /// processes or machines can split one huge tree.  Warns about renames that interact across shards.
+        #[arg(long, value_name = "I/N")]
+        shard: Option<Shard>,
+
+        /// Rename at most this often, e.g. `50/s` or `600/m`, so bulk renames on shared network storage don't starve
+        /// other clients.
+        #[arg(long, value_name = "N/UNIT")]
+        rate: Option<Rate>,
+
+        /// Keep going past failed renames, aborting once more than this many have failed (default: abort on the
+        /// first).  The run still fails if any did.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        max_errors: u64,
+
+        /// Print per-stage counters (entries walked, rejected early, matched, replacements built, ...) at the end.
+        #[arg(long)]
+        stats: bool,
+
+        /// List matches with type, size, and modification time (like `ls -l`) instead of `Match found: ...` lines.
+        #[arg(short, long, conflicts_with_all = ["replacement", "insert", "rename_to"])]
+        long: bool,
+
+        /// Only print the number of matches (nothing is renamed).  Exits with 1 if there are none.
+        #[arg(long)]
+        count: bool,
+
+        /// With `--count`: one count per directory (`<count> <dir>`) instead of a single total.
+        #[arg(long, requires = "count")]
+        per_dir: bool,
+
+        /// Print the plan (entries and conflicts) as JSON instead of per-entry lines.
+        ///
+        /// Each entry includes `matched`: the match's byte offsets and every capture group's name, value, and offsets.
+        #[arg(long)]
+        json: bool,
+
+        /// Print nothing but what is explicitly asked for (`--count`, `--stats`); errors still go to stderr and set
+        /// the exit code.
+        #[arg(short, long, conflicts_with_all = ["json", "long"])]
+        quiet: bool,
+
+        /// Show one progress line, redrawn in place for every entry, instead of a line per entry.  For terminals.
+        #[arg(long, conflicts_with_all = ["json", "long", "quiet"])]
+        tui: bool,
+
+        /// Write a JSON test fixture to PATH: the tree shape under each root, the shape after the planned renames,
+        /// and the pattern and arguments that produced them.  Written before renaming; combine with `--preview`.
+        #[arg(long, value_name = "PATH")]
+        emit_test_fixture: Option<PathBuf>,
+
+        /// Write canonical plan and summary JSON (`plan.json`, `summary.json`) into DIR, for golden-file tests.
+        ///
+        /// Written before renaming.  Compare against a checked-in snapshot with `rename_files::snapshot::diff`.
+        #[arg(long, value_name = "DIR")]
+        snapshot: Option<PathBuf>,
+
+        /// For directory renames, list every descendant path that will be relocated (not just a count), with the path
+        /// it ends up at once every planned rename is done.
+        #[arg(long)]
+        list_descendants: bool,
+
+        /// Skip (with a warning) directory renames whose path is mentioned in any file matching these globs.
+        /// e.g. `--refuse-dir-rename-if-referenced '**/Makefile,**/*.toml'`
+        #[arg(long, value_name = "GLOB", value_delimiter = ',')]
+        refuse_dir_rename_if_referenced: Vec<String>,
+
+        /// Rewrite mentions of renamed paths inside files matching these globs.
+        /// e.g. `--update-refs '**/*.md,**/*.toml'`  (Shown, not written, with `--preview`.)
+        #[arg(long, value_name = "GLOB", value_delimiter = ',')]
+        update_refs: Vec<String>,
+
+        /// Confirm renames.  Required to rename files while safe mode is on (see config).
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Config file to use instead of the default location.
+        #[arg(long, value_name = "PATH", global = true)]
+        config: Option<PathBuf>,
+
+        /// Use a named recipe's pattern (and replacement, unless `--rep` is given), and its filters.  With a recipe,
+        /// positional arguments are all paths.  See `recipe.rs` docs for built-ins and the user recipe directory.
+        #[arg(long, value_name = "NAME")]
+        recipe: Option<String>,
+
+        /// Config profiles to apply, in order (later ones take precedence; flags override all of them).
+        /// e.g. `--profile team,mine`
+        #[arg(long, value_name = "NAME", value_delimiter = ',')]
+        profile: Vec<String>,
+
+        /// Allow protected paths (home, system dirs, mount points, config `protected_paths`) as roots or renames.
+        #[arg(long)]
+        allow_protected: bool,
+
+        /// Run at low CPU and IO priority, for background bulk renames.  See `priority.rs` docs.
+        #[arg(long, global = true)]
+        nice: bool,
+
+        /// Print version.
+        #[arg(short = 'V', long)]
+        version: bool,
+
+        /// With `--version`: also print target triple, enabled cargo features, and git commit (for bug reports).
+        #[arg(long, requires = "version")]
+        verbose: bool,
+}
+
+/// Auxiliary commands.  (The main find/rename behavior needs no subcommand.)
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+        /// Measure walk/match/plan/execute throughput on a generated tree in a temporary directory.
+        Bench(bench::BenchArgs),
+        /// Config aliases.
+        #[command(subcommand)]
+        Alias(AliasCommand),
+        /// Run many operations, read from stdin as JSON lines, in one process.  See `batch.rs` docs.
+        Batch(batch::BatchArgs),
+        /// Serve plan/preview/execute requests (JSON-RPC) on a local Unix socket.  See `daemon.rs` docs.
+        Daemon(daemon::DaemonArgs),
+        /// Install a file-manager context-menu entry (nautilus, dolphin, finder, explorer) for the selected files.
+        Integrate(integrate::IntegrateArgs),
+        /// Inspect recipes.
+        #[command(subcommand)]
+        Recipe(RecipeCommand),
+        /// List optional capabilities (and the tokens/flags they unlock), and whether this binary includes them.
+        Features,
+}
+
+/// `alias` subcommands.
+#[derive(Subcommand, Debug, Clone)]
+pub enum AliasCommand {
+        /// List aliases defined in the config file and what they expand to.
+        List,
+}
+
+/// `recipe` subcommands.
+#[derive(Subcommand, Debug, Clone)]
+pub enum RecipeCommand {
+        /// List built-in and user recipes.
+        List,
+        /// Print a recipe's exact pattern, replacement, options, and an example transformation.
+        Show {
+                /// Recipe name.
+                name: String,
+        },
+}
+
+impl Args {
+        /// Parses the command line, first expanding a config alias in first-argument position.
+        pub fn parse_with_aliases() -> Result<Self> {
+                let raw: Vec<std::ffi::OsString> = std::env::args_os().collect();
+                let config = Config::load(alias::explicit_config(&raw).as_deref().map(Path::new))?;
+                Ok(Self::parse_from(alias::expand(raw, &config)?))
+        }
+
+        /// Whether this is a preview (of either kind) rather than a real run.
+        fn is_preview(&self) -> bool {
+                self.preview || self.fast_preview || self.count
+        }
+
+        /// Threads to fetch metadata with, resolving `0` to the CPU count (capped, as stat calls are IO bound).
+        fn metadata_threads(&self) -> usize {
+                match self.metadata_threads {
+                        0 => std::thread::available_parallelism().map_or(1, |n| n.get().min(8)),
+                        n => n,
+                }
+        }
+
+        /// How per-entry metadata is fetched: `--metadata-threads` and `--entry-timeout`.
+        fn fetcher(&self) -> Fetcher {
+                Fetcher { threads: self.metadata_threads(), timeout: self.entry_timeout.map(|timeout| timeout.0) }
+        }
+
+        /// How new names are built, if at all.
+        fn transform(&self) -> Option<Transform> {
+                let counter = || Counter::new(self.counter_start.unwrap_or(1), self.counter_step.unwrap_or(1));
+                let now = Local::now();
+                if let Some(mapping) = &self.map {
+                        return Some(Transform::Map { mapping: mapping.clone(), default: self.map_default.clone() });
+                }
+                if let Some(template) = &self.rename_to {
+                        return Some(Transform::RenameTo { template: template.clone(), counter: counter(), now });
+                }
+                match (&self.replacement, &self.insert) {
+                        (Some(rep), _) if self.all => Some(Transform::ReplaceAll { rep: rep.clone(),
+                                                                                   max_matches:
+                                                                                           self.max_matches_per_file,
+                                                                                   counter: counter(),
+                                                                                   now }),
+                        (Some(rep), _) => match self.occurrence {
+                                Some(occurrence) => Some(Transform::ReplaceNth { rep: rep.clone(),
+                                                                                 occurrence,
+                                                                                 counter: counter(),
+                                                                                 now }),
+                                None => Some(Transform::Replace { rep: rep.clone(), counter: counter(), now }),
+                        },
+                        (None, Some(text)) => Some(Transform::Insert { text: text.clone(), at: self.at }),
+                        // On their own, --ascii, --slug, and --sanitize rework entries' current names
+                        (None, None) if self.ascii || self.slug || self.sanitize => {
+                                Some(Transform::rename_to("{name}"))
+                        }
+                        (None, None) => None,
+                }
+        }
+
+        /// How the run is reported, from the output flags.
+        fn output_style(&self) -> OutputStyle {
+                match (self.json, self.quiet, self.tui) {
+                        (true, _, _) => OutputStyle::Json,
+                        (_, true, _) => OutputStyle::Quiet,
+                        (_, _, true) => OutputStyle::Tui,
+                        _ => OutputStyle::Human { long: self.long },
+                }
+        }
+
+        /// Whether the type filters (`--files-only`, ...) let an entry through; all do if none are given.
+        fn is_wanted_type(&self, entry: &DirEntry) -> bool {
+                if !(self.files_only || self.dirs_only || self.symlinks_only) {
+                        return true;
+                }
+                let file_type = entry.file_type();
+                (self.files_only && file_type.is_file())
+                || (self.dirs_only && file_type.is_dir())
+                || (self.symlinks_only && entry.path_is_symlink())
+        }
+
+        /// The regexes to compile: the pattern (or every `-e` pattern) as given, or escaped with `--fixed-string`,
+        /// with either separator matching either with `--any-sep`, and anchored with `--exact`.
+        fn patterns<'a>(&'a self) -> Vec<Cow<'a, str>> {
+                let given = match self.regexps.is_empty() {
+                        true => std::slice::from_ref(&self.regex),
+                        false => &self.regexps,
+                };
+                let anchored = |pattern: Cow<'a, str>| match self.exact {
+                        true => Cow::Owned(format!("^(?:{})$", pattern)),
+                        false => pattern,
+                };
+                given.iter()
+                     .map(|pattern| match self.fixed_string {
+                             true => Cow::Owned(regex::escape(pattern)),
+                             false => Cow::Borrowed(pattern.as_str()),
+                     })
+                     .map(|pattern| match self.any_sep {
+                             true => Cow::Owned(matcher::any_separator(&pattern)),
+                             false => pattern,
+                     })
+                     .map(anchored)
+                     .collect()
+        }
+
+        /// The search pattern, as one regex: [`Self::patterns`], or their alternation if there are several.
+        fn pattern(&self) -> Cow<'_, str> {
+                match self.patterns().as_slice() {
+                        [pattern] => pattern.clone(),
+                        patterns => Cow::Owned(matcher::alternation(patterns)),
+                }
+        }
+
+        /// What the pattern is matched against.
+        fn match_subject(&self) -> MatchSubject {
+                match (self.full_path, self.stem_only) {
+                        (true, _) => MatchSubject::FullPath(self.path_sep),
+                        (false, true) => MatchSubject::Stem,
+                        (false, false) => MatchSubject::Name,
+                }
+        }
+
+        /// Size bounds from `--larger-than`/`--smaller-than`.
+        fn size_range(&self) -> SizeRange {
+                SizeRange { larger_than: self.larger_than, smaller_than: self.smaller_than }
+        }
+
+        /// Modification time bounds from `--newer-than`/`--older-than` (ages count back from now) and their `-file`
+        /// forms.
+        fn mtime_range(&self) -> MtimeRange {
+                let now = SystemTime::now();
+                let ago = |age: &Age| now.checked_sub(age.0).unwrap_or(SystemTime::UNIX_EPOCH);
+                MtimeRange { newer_than: self.newer_than.as_ref().map(ago).or(self.newer_than_file),
+                             older_than: self.older_than.as_ref().map(ago).or(self.older_than_file), }
+        }
+
+        /// Whether the walk goes below a root's direct children.
+        fn is_recursive(&self) -> bool {
+                self.recurse || self.min_depth.is_some() || self.max_depth.is_some_and(|depth| depth > 1)
+        }
+
+        /// Inclusive (min, max) depth of walked entries below a root, from `--recurse` and the depth flags.
+        fn depths(&self) -> (usize, usize) {
+                let min_depth = self.min_depth.map_or(1, usize::from);
+                let max_depth = match self.max_depth {
+                        Some(depth) => usize::from(depth),
+                        None if self.is_recursive() => usize::MAX,
+                        None => 1,
+                };
+                (min_depth, max_depth)
+        }
+
+        /// Roots as given, positionally and with `--path`, or the current directory if none were given.
+        fn given_roots(&self) -> Vec<PathBuf> {
+                let given: Vec<PathBuf> = self.paths.iter().chain(&self.path_flags).cloned().collect();
+                match given.is_empty() {
+                        true => vec![PathBuf::from(".")],
+                        false => given,
+                }
+        }
+
+        /// Roots to walk: [`Self::given_roots`], with duplicate roots dropped.  (Nested roots are kept; entries
+        /// reached from two roots are planned once.)
+        fn roots(&self) -> Vec<PathBuf> {
+                roots::dedupe(self.given_roots(), self.is_recursive())
+        }
+
+        /// These args, with the pattern (and unset replacement/recurse) taken from a recipe, and its filters added.
+        /// (See [`Self::with_regexps`] for the positional arguments.)
+        fn with_recipe(&self, recipe: &Recipe) -> Result<Self> {
+                let mut args = self.clone();
+                args.regex = recipe.pattern.clone();
+                args.replacement = args.replacement.or_else(|| recipe.replacement.clone());
+                args.recurse |= recipe.recurse;
+                let filters = &recipe.filters;
+                args.ext.extend(filters.ext.iter().cloned());
+                for pattern in &filters.exclude {
+                        args.exclude.push(Regex::new(pattern)?);
+                }
+                args.files_only |= filters.files_only;
+                args.dirs_only |= filters.dirs_only;
+                args.hidden |= filters.hidden;
+                args.larger_than = args.larger_than.or(filters.larger_than);
+                args.smaller_than = args.smaller_than.or(filters.smaller_than);
+                Ok(args)
+        }
+
+        /// These args, with the positional regex taken as the first path (as `-e` or `--recipe` gives the patterns).
+        fn with_regexps(&self) -> Self {
+                let mut args = self.clone();
+                if !args.regex.is_empty() {
+                        args.paths.insert(0, PathBuf::from(std::mem::take(&mut args.regex)));
+                }
+                args
+        }
+
+        /// These args, with unset flags filled in from a (merged) config profile.
+        fn with_profile(&self, profile: &Profile) -> Self {
+                let mut args = self.clone();
+                args.recurse |= profile.recurse.unwrap_or(false);
+                args.preview |= profile.preview.unwrap_or(false);
+                args.stats |= profile.stats.unwrap_or(false);
+                args.update_refs.extend(profile.update_refs.iter().cloned());
+                args.refuse_dir_rename_if_referenced.extend(profile.refuse_dir_rename_if_referenced.iter().cloned());
+                args
+        }
 }
 
 /// Application code.  (main in lib.rs)
 #[tracing::instrument]
 pub fn app(args: &Args) -> Result<()> {
-        let re = Regex::new(&args.regex)?;
+        if args.version {
+                println!("{}", version::version_text(args.verbose));
+                return Ok(());
+        }
+        if args.nice {
+                if let Err(e) = priority::lower() {
+                        tracing::warn!("Could not lower priority: {}", e);
+                }
+        }
+        match &args.command {
+                Some(Command::Bench(bench_args)) => return bench::run(bench_args),
+                Some(Command::Batch(batch_args)) => return batch::run_batch(batch_args, args.config.as_ref()),
+                Some(Command::Daemon(daemon_args)) => return daemon::serve(daemon_args, args.config.as_ref()),
+                Some(Command::Features) => return features::run(),
+                Some(Command::Integrate(integrate_args)) => return integrate::run(integrate_args),
+                Some(Command::Alias(AliasCommand::List)) => return alias::list(&Config::load(args.config.as_deref())?),
+                Some(Command::Recipe(recipe_command)) => {
+                        // Project recipes: those of the current directory's project config
+                        let mut config = Config::load(args.config.as_deref())?;
+                        config.merge_project_configs(&[PathBuf::from(".")])?;
+                        return match recipe_command {
+                                RecipeCommand::List => recipe::list(&config.project_recipes),
+                                RecipeCommand::Show { name } => recipe::show(name, &config.project_recipes),
+                        };
+                }
+                None => {}
+        }
+        let config = Config::load(args.config.as_deref())?;
+        run(args, config)
+}
+
+/// The find/rename run itself, given the loaded user config.
+pub(crate) fn run(args: &Args, config: Config) -> Result<()> {
+        run_in(args, &mut RunContext::new(config))
+}
+
+/// As [`run`], reusing (and adding to) what `context` has worked out for earlier runs.
+pub(crate) fn run_in(args: &Args, context: &mut RunContext) -> Result<()> {
+        let mut out = Output::stdout();
+        let result = run_with_context(args.output_style().reporter(&mut out).as_mut(), args, context);
+        out.flush()?;
+        result
+}
+
+/// As [`run`], printing to `out`.
+pub(crate) fn run_to(out: &mut impl Write, args: &Args, config: Config) -> Result<()> {
+        run_with(args.output_style().reporter(out).as_mut(), args, config)
+}
+
+/// As [`run`], reporting to `reporter` instead of printing.
+pub fn run_with(reporter: &mut dyn Reporter, args: &Args, config: Config) -> Result<()> {
+        run_with_context(reporter, args, &mut RunContext::new(config))
+}
+
+/// As [`run_with`], reusing (and adding to) what `context` has worked out for earlier runs.
+#[tracing::instrument(skip(reporter, context))]
+pub fn run_with_context(reporter: &mut dyn Reporter, args: &Args, context: &mut RunContext) -> Result<()> {
+        let args = &match args.recipe.is_some() || !args.regexps.is_empty() {
+                true => args.with_regexps(),
+                false => args.clone(),
+        };
+        let mut config = context.config(&args.given_roots())?;
+        // After merging: a project config can define recipes
+        let args = &match &args.recipe {
+                Some(name) => {
+                        let mut recipes = context.recipes()?;
+                        recipes.add_project(&config.project_recipes);
+                        args.with_recipe(recipes.get(name)?)?
+                }
+                None => args.clone(),
+        };
+        let profile = config.apply_profiles(&args.profile)?;
+        let mut args = args.with_profile(&profile);
+        args.project_ignores.clone_from(&config.project_ignores);
+        let args = &args;
+        // Guard: --fancy in a build without fancy-regex
+        if args.fancy && !cfg!(feature = "fancy") {
+                return Err("--fancy needs the `fancy` feature, which this binary was built without (see `rename_files \
+                            features`)"
+                                       .into());
+        }
+        let matcher = context.matcher(&args.patterns(), args.ignore_case, args.fancy)?;
+
+        // Guard: with --not there is no match, so nothing for capture references to refer to
+        if args.invert && args.rename_to.as_ref().is_some_and(|template| template.contains('$')) {
+                return Err("--not selects names the pattern does not match: a --rename-to template cannot use \
+                            capture references (`$1`, `${name}`, ...)"
+                                                                      .into());
+        }
+        if let Some(transform) = args.transform() {
+                transform.check()?;
+                // Guard: --fast-preview reads no metadata, so has no modification times
+                if args.fast_preview && transform.uses_mtime() {
+                        return Err("--fast-preview reads no metadata: a template cannot use `{mtime:...}`".into());
+                }
+        }
+        // Guard: --fast-preview with a recipe's size filters (the flags themselves are refused when parsing)
+        if args.fast_preview && !args.size_range().is_unbounded() {
+                return Err("--fast-preview reads no metadata: it cannot be used with size filters".into());
+        }
+        for template in args.replacement.iter().chain(&args.rename_to) {
+                matcher.check_template(template)?;
+                for warning in matcher.template_warnings(template) {
+                        tracing::warn!("{}", warning);
+                }
+        }
+        if let (Some(min_depth), Some(max_depth)) = (args.min_depth, args.max_depth) {
+                if min_depth > max_depth {
+                        return Err(format!("--min-depth {} is deeper than --max-depth {}",
+                                           min_depth,
+                                           max_depth).into());
+                }
+        }
+        let protected = ProtectedPaths::new(&config);
+        let roots = args.roots();
+        // Guard: --wait-for-mount, roots that are bare mountpoints (checked before missing roots: a root may only
+        // exist once mounted)
+        if let Some(wait) = &args.wait_for_mount {
+                if let Some(root) = roots::wait_for_mounts(&roots, wait.0) {
+                        return Err(format!("{:?} is not on a mounted filesystem (is the share/drive mounted?)",
+                                           root).into());
+                }
+        }
+        // Guard: missing roots, rather than quietly walking nothing (file roots are single candidates)
+        if args.files_from.is_none() && !args.stdin {
+                if let Some(root) = roots.iter().find(|root| std::fs::symlink_metadata(root).is_err()) {
+                        return Err(tr("err-missing-root", &[("path", &format!("{:?}", root))]).into());
+                }
+        }
+        // Guard: protected roots
+        if let Some(root) = roots.iter().find(|root| protected.contains(root)) {
+                if !args.allow_protected {
+                        return Err(tr("err-protected-root", &[("path", &format!("{:?}", root))]).into());
+                }
+        }
+        // Guard: recursive renames from `/` or `~` with a catch-all pattern need a human to confirm
+        let will_rename = args.transform().is_some() && !args.is_preview();
+        if let Some(root) = roots.iter().find(|root| roots::is_sweeping(root)) {
+                if will_rename && args.is_recursive() && roots::is_broad_pattern(matcher.regex()) {
+                        let message_args: &[(&str, &dyn Display)] =
+                                &[("root", &format!("{:?}", root)), ("pattern", &format!("{:?}", args.pattern()))];
+                        tracing::warn!("{}", tr("warn-broad-recursive", message_args));
+                        if !confirm_interactively(&tr("confirm-broad-question", message_args))? {
+                                return Err(tr("err-broad-aborted", &[]).into());
+                        }
+                }
+        }
+        let file_list = match args.stdin {
+                true => Some(Path::new("-")),
+                false => args.files_from.as_deref(),
+        };
+        let mut plan = match file_list {
+                Some(list) => build_plan_from_files(read_file_list(list, args.null_separated)?, &matcher, args),
+                None => build_plan(roots, &matcher, args),
+        };
+        plan.unvalidated = args.fast_preview;
+        // Guard: --strict-walk, part of the tree could not be searched
+        if args.strict_walk && plan.stats.walk_errors > 0 {
+                return Err(format!("{} walk error(s); no files were renamed:\n{}",
+                                   plan.stats.walk_errors,
+                                   unwalked_summary(&plan)).into());
+        }
+        if let Some(shard) = &args.shard {
+                shard.retain(&mut plan);
+        }
+        let name_rules = NameRules { allow_bare_ext:       args.allow_bare_ext,
+                                     allow_dotfile_change: args.allow_dotfile_change,
+                                     auto_trim:            args.auto_trim, };
+        name_rules.reject_invalid(&mut plan);
+        // Guard: protected renames
+        let is_protected_rename = |entry: &PlanEntry| {
+                !args.fast_preview && entry.new_filename.is_some() && protected.contains(&entry.path)
+        };
+        if let Some(entry) = plan.entries.iter().find(|entry| is_protected_rename(entry)) {
+                if !args.allow_protected {
+                        return Err(tr("err-protected-rename", &[("path", &format!("{:?}", entry.path))]).into());
+                }
+        }
+        if !args.refuse_dir_rename_if_referenced.is_empty() {
+                let references = ReferenceFiles::collect(&args.refuse_dir_rename_if_referenced)?;
+                skip_referenced_dir_renames(&mut plan, &references);
+        }
+        if args.count {
+                reporter.counts(&plan, args.per_dir)?;
+                return match plan.entries.is_empty() {
+                        true => Err(NoMatches.into()),
+                        false => Ok(()),
+                };
+        }
+        if args.list_descendants {
+                plan.list_descendants();
+        }
+        if args.json {
+                plan.explain_matches(&matcher, args.match_subject());
+        }
+        let reference_updates = match args.update_refs.is_empty() {
+                true => Vec::new(),
+                false => ReferenceFiles::collect(&args.update_refs)?.updates(&plan.entries),
+        };
+        // Guard: safe mode downgrades an unconfirmed run to a preview
+        let has_renames = plan.entries.iter().any(|entry| entry.new_filename.is_some());
+        let is_unconfirmed = config.safe_mode && has_renames && !args.is_preview() && !args.yes;
+        if is_unconfirmed {
+                tracing::warn!("{}", tr("warn-safe-mode", &[]));
+        }
+        if let Some(path) = &args.emit_test_fixture {
+                let origin = PlanOrigin::new(&args.pattern(), args.transform().as_ref());
+                Fixture::capture(&plan, &origin, args.depths().1).write(path)?;
+        }
+        if let Some(dir) = &args.snapshot {
+                Snapshot::capture(&plan, &plan.conflicts()).write(dir)?;
+        }
+        execute_plan(reporter, &plan, &reference_updates, args, args.is_preview() || is_unconfirmed)?;
+        if !args.is_preview() && !is_unconfirmed {
+                for state in &plan.states {
+                        state.save()?;
+                }
+        }
+        if !plan.loops.is_empty() {
+                let loops: Vec<_> =
+                        plan.loops.iter().map(|(path, ancestor)| format!("  {:?} -> {:?}", path, ancestor)).collect();
+                tracing::warn!("{} symlink loop(s) not followed:\n{}", loops.len(), loops.join("\n"));
+        }
+        if !plan.sanitized.is_empty() {
+                let names: Vec<_> = plan.sanitized
+                                        .iter()
+                                        .map(|(path, unsanitized)| format!("  {:?}: was {:?}", path, unsanitized))
+                                        .collect();
+                tracing::warn!("{} new name(s) sanitized:\n{}", names.len(), names.join("\n"));
+        }
+        if !plan.timed_out.is_empty() {
+                let paths: Vec<_> = plan.timed_out.iter().map(|path| format!("  {:?}", path)).collect();
+                tracing::warn!("{} entry(s) timed out and were skipped (see `--entry-timeout`):\n{}",
+                               paths.len(),
+                               paths.join("\n"));
+        }
+        if plan.stats.walk_errors > 0 {
+                tracing::warn!("{} walk error(s); these subtrees were skipped (see `--strict-walk`):\n{}",
+                               plan.stats.walk_errors,
+                               unwalked_summary(&plan));
+        }
+        if args.stats {
+                reporter.stats(&plan.stats)?;
+        }
+        match is_unconfirmed {
+                true => Err(tr("err-safe-mode", &[]).into()),
+                false => Ok(()),
+        }
+}
+
+/// One line per path the walk failed at, with the reason.
+fn unwalked_summary(plan: &Plan) -> String {
+        plan.unwalked.iter().map(|(path, reason)| format!("  {:?}: {}", path, reason)).collect::<Vec<_>>().join("\n")
+}
+
+/// Walks every root into a single plan, sorted for execution.
+///
+/// A root that is not a directory is a single candidate entry itself, rather than something to walk into.
+#[tracing::instrument(skip(matcher))]
+pub(crate) fn build_plan(roots: Vec<PathBuf>, matcher: &Matcher, args: &Args) -> Plan {
+        let is_numbered = args.transform().as_ref().is_some_and(Transform::is_numbered);
+        let walk_root = |root: PathBuf| {
+                // Guard: a file root is a single candidate, not something to walk
+                if !root.is_dir() {
+                        let file = with_parent(root);
+                        return (file.clone(), WalkDir::new(file).max_depth(0));
+                }
+                let (min_depth, max_depth) = args.depths();
+                let walkable_space =
+                        walkdir_build_with_depths(&root, args.is_recursive()).min_depth(min_depth).max_depth(max_depth);
+                let walkable_space = match is_numbered {
+                        true => walkable_space.sort_by_file_name(),
+                        false => walkable_space,
+                };
+                (root, walkable_space)
+        };
+        let given = roots.clone();
+        let mut plan = plan_walks(roots.into_iter().map(walk_root), matcher, args);
+        plan.roots = given;
+        plan
+}
+
+/// As [`build_plan`], for an explicit list of entries (`--files-from`) instead of walked roots.
+pub(crate) fn build_plan_from_files(files: Vec<PathBuf>, matcher: &Matcher, args: &Args) -> Plan {
+        let walks = files.into_iter().map(|file| {
+                                             let parent = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+                                             let walkable_space = WalkDir::new(&file).max_depth(0);
+                                             (parent, walkable_space)
+                                     });
+        plan_walks(walks, matcher, args)
+}
 
-        if let Some(replacement) = &args.replacement {
-                check_for_common_syntax_error(replacement)?;
+/// Runs each (root, walk) pair through the core loop into one sorted plan.
+fn plan_walks(walks: impl Iterator<Item=(PathBuf, WalkDir)>, matcher: &Matcher, args: &Args) -> Plan {
+        let mut plan = Plan::default();
+        let transform = args.transform();
+        let is_per_entry =
+                transform.as_ref().is_some_and(|transform| transform.is_numbered() || transform.uses_entry());
+        // Full paths never repeat, so memoizing them would only cost memory
+        let mut cache = TransformCache::new(!args.no_memo && !is_per_entry && !args.full_path);
+        for (root, walkable_space) in walks {
+                if args.changed_only {
+                        plan.states.push(RunState::load(&root, &args.pattern(), transform.as_ref()));
+                }
+                core_process_loop(walkable_space, &root, matcher, transform.as_ref(), &mut cache, args, &mut plan);
         }
-        let walkable_space = walkdir_build_with_depths(args.recurse);
-        core_process_loop(walkable_space, &re, args)
+        plan.sort();
+        plan
+}
+
+/// Paths listed one per line (or NUL-separated) in `list` (`-`: stdin).  Bare names get a `./` so every entry has a
+/// parent.
+fn read_file_list(list: &Path, is_null_separated: bool) -> Result<Vec<PathBuf>> {
+        let text = match list == Path::new("-") {
+                true => std::io::read_to_string(std::io::stdin())?,
+                false => std::fs::read_to_string(list).map_err(|e| format!("Could not read {:?}: {}", list, e))?,
+        };
+        let lines: Vec<&str> = match is_null_separated {
+                true => text.split('\0').collect(),
+                false => text.lines().collect(),
+        };
+        let files = lines.into_iter()
+                         .filter(|line| !line.is_empty())
+                         .map(|line| with_parent(PathBuf::from(line)))
+                         .collect();
+        Ok(files)
 }
 
-/// Walks a WalkDir, handles errors, prints matches, optionally executes
+/// `path`, with `./` prepended if it is a bare name, so that it has a parent.
+fn with_parent(path: PathBuf) -> PathBuf {
+        match path.parent() {
+                Some(parent) if parent.as_os_str().is_empty() => Path::new(".").join(path),
+                _ => path,
+        }
+}
+
+/// Walks a WalkDir, handles errors, and adds matches (with any replacement) to the plan
 ///
 /// # Note 1, single-purpose violation:
 /// Breaking this function into smaller pieces would create indirection and complexity.
@@ -58,95 +1133,437 @@ pub fn app(args: &Args) -> Result<()> {
 /// BUT: while charming, the lack of shared scope makes passing references along past multiple
 /// guards quite awkward.  And the workarounds end up being deeply nested and more verbose
 /// without any clear benefit.
-#[tracing::instrument]
-fn core_process_loop(walkable_space: WalkDir, re: &Regex, args: &Args) -> Result<()> {
-        let rep = &args.replacement;
-        let is_test_run = args.preview;
-        let mut num_matches: u64 = 0;
-
-        for entry in walkable_space {
+#[tracing::instrument(skip(cache, plan))]
+fn core_process_loop(walkable_space: WalkDir,
+                     root: &Path,
+                     matcher: &Matcher,
+                     transform: Option<&Transform>,
+                     cache: &mut TransformCache,
+                     args: &Args,
+                     plan: &mut Plan) {
+        let mut scratch = String::new();
+        let buffer_size = args.walk_buffer.max(1);
+        let fetcher = args.fetcher();
+        let mut pending = Vec::with_capacity(buffer_size);
+        let (size_range, mtime_range) = (args.size_range(), args.mtime_range());
+        // Only stat for the filters and `{mtime:...}` that need it: the walk does not need to
+        let needs_metadata = !size_range.is_unbounded()
+                             || !mtime_range.is_unbounded()
+                             || args.changed_only
+                             || transform.is_some_and(Transform::uses_mtime);
+        let skip_marked = args.skip_marked;
+        // --insert positions count within the name, even when the pattern is matched against the path
+        let is_name_insert = args.full_path && args.insert.is_some();
+        let extensions = Extensions::new(&args.ext);
+        let match_subject = args.match_subject();
+        // With --not, entries did not match, so names are built from an empty match at their start
+        let match_start = Matcher::new("^").expect("valid, static regex");
+        let name_matcher = if args.invert { &match_start } else { matcher };
+        // Excluded entries are pruned by the walk itself, so excluded directories are never read.  (Pruning needs each
+        // directory before its contents; the plan is sorted deepest-first afterwards regardless of walk order.)
+        let (excluded, hidden) = (Cell::new(0), Cell::new(0));
+        let mut ignore_rules = args.gitignore.then(|| IgnoreRules::new(root));
+        let canonical_root =
+                (!args.project_ignores.is_empty()).then(|| root.canonicalize().unwrap_or_else(|_| root.to_path_buf()));
+        let is_included = |entry: &DirEntry| {
+                // Guard: hidden entries (a root is always considered, even `.` or an explicitly listed dotfile)
+                if !args.hidden && entry.depth() > 0 && is_hidden(entry) {
+                        hidden.set(hidden.get() + 1);
+                        return false;
+                }
+                let relative = match entry.path().strip_prefix(root) {
+                        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                        _ => Path::new(entry.file_name()),
+                };
+                let relative = relative.to_string_lossy();
+                let is_excluded =
+                        args.exclude.iter().any(|re| re.is_match(&relative))
+                        || ignore_rules.as_mut()
+                                       .is_some_and(|rules| rules.is_ignored(entry.path(), entry.file_type().is_dir()))
+                        || canonical_root.as_ref().is_some_and(|canonical_root| {
+                                                          let path = entry.path()
+                                                                          .strip_prefix(root)
+                                                                          .map_or_else(|_| entry.path().to_path_buf(),
+                                                                                       |rel| canonical_root.join(rel));
+                                                          gitignore::is_ignored_by(&args.project_ignores,
+                                                                                   &path,
+                                                                                   entry.file_type().is_dir())
+                                                  });
+                if is_excluded {
+                        excluded.set(excluded.get() + 1);
+                        tracing::trace!("Excluded: {:?}", entry.path());
+                }
+                !is_excluded
+        };
+        // Everything after matching, a batch of candidates at a time: per-entry reads (once per entry, for the whole
+        // batch at once), the filters that need them, new names, and resolution
+        let mut plan_batch = |batch: Vec<Candidate>, plan: &mut Plan| {
+                let batch = Arc::new(batch);
+                let follow_symlinks = args.follow_symlinks;
+                let probes = match needs_metadata || skip_marked {
+                        true => fetcher.map(Arc::clone(&batch), move |candidate| {
+                                               candidate.probe(needs_metadata, follow_symlinks, skip_marked)
+                                       }),
+                        false => vec![Some(Probe::default()); batch.len()],
+                };
+                let mut resolvable = Vec::with_capacity(batch.len());
+                for (candidate, probe) in batch.iter().zip(probes) {
+                        // Guard: per-entry reads timed out (--entry-timeout)
+                        let Some(probe) = probe else {
+                                skip_timed_out(plan, candidate.path.clone());
+                                continue;
+                        };
+                        let metadata = probe.metadata.as_ref();
+                        // Guard: size filters
+                        if !size_range.contains(metadata) {
+                                plan.stats.filtered_by_size += 1;
+                                continue;
+                        }
+                        // Guard: modification time filters
+                        if !mtime_range.contains(metadata) {
+                                plan.stats.filtered_by_mtime += 1;
+                                continue;
+                        }
+                        // Guard: --changed-only, entry seen unchanged by the last run (the current root's state is the
+                        // latest)
+                        if args.changed_only
+                           && plan.states.last_mut().is_some_and(|state| state.is_unchanged(&candidate.path, metadata))
+                        {
+                                plan.stats.unchanged += 1;
+                                tracing::trace!("Unchanged since last run: {:?}", candidate.path);
+                                continue;
+                        }
+                        // Guard: --skip-marked, entry already processed by a --mark run
+                        if probe.is_marked {
+                                plan.stats.marked += 1;
+                                tracing::trace!("Marked by an earlier run: {:?}", candidate.path);
+                                continue;
+                        }
+                        let Candidate { path, is_dir, filename, subject } = candidate;
+                        let mtime = metadata.and_then(|metadata| metadata.modified().ok());
+                        plan.stats.matched += 1;
+                        let transform_subject = if is_name_insert { filename } else { subject };
+                        let (new_filename, is_memo_hit) = match transform {
+                                Some(transform) => cache.get_or_compute(transform_subject, || {
+                                                                transform.apply_with(name_matcher,
+                                                                                     transform_subject,
+                                                                                     Some(path),
+                                                                                     mtime,
+                                                                                     &mut scratch)
+                                                        }),
+                                None => (None, false),
+                        };
+                        let new_filename = match is_name_insert {
+                                true => new_filename,
+                                false => new_filename.map(|new_subject| {
+                                                             match_subject.new_filename(filename, subject, new_subject)
+                                                     }),
+                        };
+                        let new_filename = match args.ascii {
+                                true => new_filename.map(|new_filename| transliterate(&new_filename)),
+                                false => new_filename,
+                        };
+                        let new_filename = match args.slug {
+                                true => new_filename.map(|new_filename| slugify(&new_filename)),
+                                false => new_filename,
+                        };
+                        let new_filename = match (args.sanitize, new_filename) {
+                                (true, Some(new_filename)) => {
+                                        let sanitized = sanitize(&new_filename);
+                                        if sanitized != new_filename {
+                                                plan.stats.sanitized += 1;
+                                                plan.sanitized.push((path.clone(), new_filename));
+                                        }
+                                        Some(sanitized)
+                                }
+                                (_, new_filename) => new_filename,
+                        };
+                        let new_filename = match (args.max_len, new_filename) {
+                                (Some(max_len), Some(new_filename)) if new_filename.len() > max_len => {
+                                        plan.stats.truncated += 1;
+                                        Some(truncate(&new_filename, max_len).into_owned())
+                                }
+                                (_, new_filename) => new_filename,
+                        };
+                        if let Some(new_filename) = &new_filename {
+                                match is_memo_hit {
+                                        true => plan.stats.memo_hits += 1,
+                                        false => plan.stats.replacements_built += 1,
+                                }
+                                plan.stats.replacement_bytes += new_filename.len() as u64;
+                        }
+                        resolvable.push((path.clone(), *is_dir, new_filename));
+                }
+                // Guard: --fast-preview (no resolution)
+                if args.fast_preview {
+                        for (path, is_dir, new_filename) in resolvable {
+                                plan.push(PlanEntry::new_unresolved(root, &path, is_dir, new_filename));
+                        }
+                        return;
+                }
+                push_resolved(plan, PlanEntry::new_batch(root, resolvable, &fetcher));
+        };
+        let walk = walkable_space.follow_links(args.follow_symlinks).contents_first(false).into_iter();
+        for entry in walk.filter_entry(is_included) {
+                plan.stats.walked += 1;
                 // Guard: walk errors (e.g. loop encountered)
-                let Ok(entry) = entry else {
-                        tracing::error!("Error encountered while walking dir: {:?}", entry);
+                let entry = match entry {
+                        Ok(entry) => entry,
+                        // Guard: symlink loop (only with --follow-symlinks); the loop is not followed, the walk goes on
+                        Err(e) if e.loop_ancestor().is_some() => {
+                                let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) else { continue };
+                                tracing::debug!("Symlink loop: {:?} points back to {:?}", path, ancestor);
+                                if !plan.loops.iter().any(|(looping, _)| looping == path) {
+                                        plan.stats.symlink_loops += 1;
+                                        plan.loops.push((path.to_path_buf(), ancestor.to_path_buf()));
+                                }
+                                continue;
+                        }
+                        Err(e) => {
+                                plan.stats.walk_errors += 1;
+                                tracing::error!("Error encountered while walking dir: {:?}", e);
+                                if let Some(path) = e.path() {
+                                        let reason = e.io_error().map_or_else(|| e.to_string(), ToString::to_string);
+                                        plan.unwalked.push((path.to_path_buf(), reason));
+                                }
+                                continue;
+                        }
+                };
+                // Guard: entry type filters
+                if !args.is_wanted_type(&entry) {
+                        plan.stats.filtered_by_type += 1;
                         continue;
+                }
+                // Guard: --ext (name bytes only)
+                if !extensions.contains(entry.file_name()) {
+                        plan.stats.filtered_by_ext += 1;
+                        continue;
+                }
+                // With --stem-only/--full-path the pattern sees part of the name, or the path relative to the root
+                let subject = match match_subject {
+                        MatchSubject::Name => Cow::Borrowed(entry.file_name()),
+                        _ => match match_subject.of(root, entry.path()) {
+                                Some(subject) => Cow::Owned(OsString::from(subject.into_owned())),
+                                None => {
+                                        plan.stats.non_utf8 += 1;
+                                        tracing::error!("Entry path could not convert to a string: {:?}", entry.path());
+                                        continue;
+                                }
+                        },
                 };
+                // Guard: literal prefix/suffix mismatch (byte comparison only; with --not, a sure keeper)
+                if !args.invert && matcher.prefilter_rejects(&subject) {
+                        plan.stats.rejected_early += 1;
+                        plan.stats.rejected_literal += 1;
+                        tracing::trace!("No Match for Entry (literal check): {:?}", subject);
+                        continue;
+                }
+                // Guard: no regex match, or with --not a match (raw bytes; no allocation or utf8 conversion for the
+                // common case)
+                if matcher.is_match_os(&subject) == args.invert {
+                        plan.stats.rejected_early += 1;
+                        tracing::trace!("No Match for Entry: {:?}", subject);
+                        continue;
+                }
                 // Guard: entry~>path~>pathentry.path().'s_file_name
+                let is_dir = entry.file_type().is_dir();
                 let entry = entry.path();
-                let parent = entry.parent().expect("all entries should have parents due to WalkDir min_depth=1");
                 let Some(filename) = entry.file_name() else {
                         tracing::error!("Leaf neither file nor directory: {:?}", entry);
                         continue;
                 };
                 // Guard: path's_file_name~>str errors (e.g. non-utf8 paths)
-                let Some(filename) = filename.to_str() else {
+                let (Some(filename), Some(subject)) = (filename.to_str(), subject.to_str()) else {
+                        plan.stats.non_utf8 += 1;
                         tracing::error!("Entry path could not convert to a string: {:?}", filename);
                         continue;
                 };
-                // Guard: no regex match
-                // PERF: repetitive with replaces...
-                let Some(_) = re.find(filename) else {
-                        tracing::trace!("No Match for Entry: {:?}", filename);
-                        continue;
-                };
-                num_matches += 1;
-                // Guard: no replacement
-                let Some(rep) = rep else {
-                        println!("Match found: {}/{}",
-                                 parent.to_string_lossy().blue(),
-                                 &filename.black().bold().on_green());
-                        continue;
+                pending.push(Candidate { path: entry.to_path_buf(),
+                                         is_dir,
+                                         filename: filename.to_string(),
+                                         subject: subject.to_string() });
+                if pending.len() >= buffer_size {
+                        plan_batch(std::mem::take(&mut pending), plan);
+                }
+        }
+        plan_batch(pending, plan);
+        plan.stats.excluded += excluded.get();
+        plan.stats.hidden += hidden.get();
+}
+
+/// A matched entry, before its per-entry reads.
+#[derive(Debug)]
+struct Candidate {
+        path:     PathBuf,
+        is_dir:   bool,
+        filename: String,
+        /// What the pattern was matched against (see [`MatchSubject`]).
+        subject:  String,
+}
+
+impl Candidate {
+        /// Reads what the filters need: the entry's metadata (its target's, for a followed symlink, as the walk's
+        /// `DirEntry` would give it) and whether it is marked.
+        fn probe(&self, needs_metadata: bool, follow_symlinks: bool, skip_marked: bool) -> Probe {
+                let metadata = match (needs_metadata, follow_symlinks) {
+                        (false, _) => None,
+                        (true, true) => std::fs::metadata(&self.path).ok(),
+                        (true, false) => std::fs::symlink_metadata(&self.path).ok(),
                 };
-                let new_filename = re.replace(filename, rep);
-                // Guard: --test-run
+                Probe { metadata, is_marked: skip_marked && marker::is_marked(&self.path) }
+        }
+}
+
+/// What a [`Candidate`]'s per-entry reads found; each is only read when needed.
+#[derive(Debug, Clone, Default)]
+struct Probe {
+        metadata:  Option<Metadata>,
+        is_marked: bool,
+}
+
+/// Adds resolved entries to the plan, recording those that timed out.
+fn push_resolved(plan: &mut Plan, batch: Vec<std::result::Result<PlanEntry, PathBuf>>) {
+        for resolved in batch {
+                match resolved {
+                        Ok(planned) => {
+                                plan.push(planned);
+                        }
+                        Err(path) => skip_timed_out(plan, path),
+                }
+        }
+}
+
+/// Records an entry given up on after `--entry-timeout`.
+fn skip_timed_out(plan: &mut Plan, path: PathBuf) {
+        plan.stats.timed_out += 1;
+        tracing::error!("Entry timed out, skipped: {:?}", path);
+        plan.timed_out.push(path);
+}
+
+/// Drops directory renames whose current path is referenced by one of the reference files.
+#[tracing::instrument(skip_all)]
+fn skip_referenced_dir_renames(plan: &mut Plan, references: &ReferenceFiles) {
+        plan.entries.retain(|entry| {
+                            // Guard: only directory renames
+                            if !entry.is_dir || entry.new_filename.is_none() {
+                                    return true;
+                            }
+                            let Some(file) = references.find_reference(&entry.path) else { return true };
+                            tracing::warn!("{}",
+                                           tr("warn-skip-referenced", &[("path", &format!("{:?}", entry.path)),
+                                                                        ("file", &format!("{:?}", file))]));
+                            false
+                    });
+}
+
+/// Reports (and, unless `is_test_run`, carries out) every entry of a sorted plan.
+///
+/// Conflicts are checked across the whole plan first; if any are found nothing is renamed.
+/// Reference updates are written before any renames, while the referencing files are still at their old paths.
+#[tracing::instrument(skip(reporter, plan, reference_updates))]
+fn execute_plan(reporter: &mut dyn Reporter,
+                plan: &Plan,
+                reference_updates: &[ReferenceUpdate],
+                args: &Args,
+                is_test_run: bool)
+                -> Result<()> {
+        let conflicts = match args.fast_preview {
+                true => Vec::new(),
+                false => plan.conflicts(),
+        };
+        for conflict in &conflicts {
+                tracing::error!("Conflict: {}", conflict);
+        }
+        let origin = PlanOrigin::new(&args.pattern(), args.transform().as_ref());
+        reporter.plan(plan, &conflicts, reference_updates, &origin)?;
+        // Guard: conflicts, unless only previewing
+        if !conflicts.is_empty() && !is_test_run {
+                return Err(tr("err-conflicts", &[("count", &conflicts.len())]).into());
+        }
+        for update in reference_updates {
+                reporter.reference_update(update, is_test_run)?;
+                if !is_test_run {
+                        update.apply()?;
+                }
+        }
+        let limiter = args.rate.map(RateLimiter::new);
+        let run_started = Local::now().to_rfc3339();
+        let mut failures = 0;
+        for entry in &plan.entries {
+                reporter.entry(entry, is_test_run)?;
+                // Guard: no replacement or --test-run
+                let Some(destination) = entry.destination() else { continue };
                 if is_test_run {
-                        println!("--test-run mapping: {}/{} ~~> {}",
-                                 parent.to_string_lossy().blue(),
-                                 &filename.black().bold().on_green(),
-                                 &new_filename.red().bold().on_blue());
                         continue;
                 }
-                println!("Renaming: {}/{} ~~> {}",
-                         parent.to_string_lossy().blue(),
-                         &filename.black().bold().on_green(),
-                         &new_filename.red().bold().on_blue());
-                std::fs::rename(entry, entry.with_file_name(new_filename.as_ref()))?;
-                // std::fs::rename(entry, new_filename.as_ref())?;
+                if let Some(limiter) = &limiter {
+                        limiter.wait();
+                }
+                if let Err(e) = std::fs::rename(&entry.path, &destination) {
+                        failures += 1;
+                        let message = format!("Could not rename {:?}: {}", entry.path, e);
+                        // Guard: --max-errors exceeded, likely something systemic (e.g. a read-only remount)
+                        if failures > args.max_errors {
+                                let message = match args.max_errors {
+                                        0 => message,
+                                        max => format!("Aborting after {} failed renames (--max-errors {}); last: {}",
+                                                       failures, max, message),
+                                };
+                                return Err(message.into());
+                        }
+                        tracing::error!("{}", message);
+                        continue;
+                }
+                if args.mark {
+                        if let Err(e) = marker::mark(&destination, &run_started) {
+                                tracing::warn!("{}", e);
+                        }
+                }
         }
-        println!("Total matches: {}", num_matches.cyan());
-        Ok(())
+        let result = match failures {
+                0 => Ok(()),
+                n => Err(format!("{} rename(s) failed", n).into()),
+        };
+        reporter.finished(plan)?;
+        result
 }
 
-/// Guard: Flagging unintended syntax
-///
-/// Checks replacement string for capture references making a common syntax error:
-/// A bare reference number followed by chars that would be combined with it and read as a name
-///
-/// e.g. `$1abc` will be parsed as ($1abc) NOT ($1)(abc) -- `${1}abc` is proper syntax
-#[tracing::instrument]
-fn check_for_common_syntax_error(rep_arg: &str) -> Result<()> {
-        const RE_SYNTAX_WARN: &str = r"(\$\d)[^\d\$\s]+";
+/// Asks a yes/no question on the terminal.  Refuses (errors) when stdin is not a terminal.
+fn confirm_interactively(prompt: &str) -> Result<bool> {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+                return Err(tr("err-not-terminal", &[("question", &prompt)]).into());
+        }
+        let expected = tr("confirm-answer", &[]);
+        print!("{}", tr("confirm-prompt", &[("question", &prompt.red().bold()), ("answer", &expected)]));
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        stdin.read_line(&mut answer)?;
+        Ok(answer.trim() == expected)
+}
 
-        let re_check = Regex::new(RE_SYNTAX_WARN).expect("valid, static regex");
-        if let Some(cap) = re_check.captures(rep_arg) {
-                tracing::warn!("\nWarning:\ncapture reference `{}` is being read as `{}`\nIf this is not intended use: `${{_}}...` instead.",
-                               cap[1].to_string().blue(),
-                               cap[0].to_string().red());
-                return Err("Ambiguous replacement syntax".into());
+/// Checks for a literal `.` prefix on an entry's name.
+///
+/// # Note: This will trigger on the `.` used to indicate the 'local' directory (so roots are not checked)
+fn is_hidden(entry: &DirEntry) -> bool {
+        let is_hidden = entry.file_name().to_str().map(|s| s.starts_with('.')).unwrap_or(false);
+        if is_hidden {
+                tracing::trace!("Ignoring hidden file: {:?}", entry.path());
         }
-        Ok(())
+        is_hidden
 }
 
-/// Build a WalkDir object with depth limits based information passed in
+/// Build a WalkDir object for a root with depth limits based information passed in
 #[tracing::instrument]
-fn walkdir_build_with_depths(does_recurse: bool) -> WalkDir {
+pub(crate) fn walkdir_build_with_depths(root: &Path, does_recurse: bool) -> WalkDir {
         match does_recurse {
                 true => {
                         tracing::debug!("Recursable WalkDir");
-                        WalkDir::new(".").contents_first(true).min_depth(1)
+                        WalkDir::new(root).contents_first(true).min_depth(1)
                 }
                 false => {
                         tracing::debug!("non-recursing (shallow) WalkDir");
-                        WalkDir::new(".").contents_first(true).min_depth(1).max_depth(1)
+                        WalkDir::new(root).contents_first(true).min_depth(1).max_depth(1)
                 }
         }
 }
@@ -163,6 +1580,7 @@ pub mod tests {
         use test_log::test;
 
         use super::*;
+        use crate::report::SilentReporter;
 
         pub type Result<T> = core::result::Result<T, Error>;
         pub type Error = Box<dyn std::error::Error>;
@@ -189,6 +1607,16 @@ pub mod tests {
                 f()
         }
 
+        /// Runs as [`app`] does, but with the config file given by `args.config` (or the defaults), never the
+        /// developer's own.
+        fn utility_app(args: &Args) -> Result<()> {
+                let config = match &args.config {
+                        Some(path) => Config::load(Some(path))?,
+                        None => Config::default(),
+                };
+                run(args, config)
+        }
+
         /// Generate a fixed, populated temporary directory.
         ///
         /// dir_structure:
@@ -237,43 +1665,17 @@ pub mod tests {
         // Test the app() function
         // Test the core_process_loop() function
 
-        /// Test the check_for_common_syntax_error() function
+        /// File lists: one path per line, or NUL-separated; bare names get a `./` parent.
         #[test]
-        fn test_check_for_common_syntax_error() {
-                let test_cases = vec![("$1abc", true),
-                                      ("${1}abc", false),
-                                      //
-                                      ("$1a", true),
-                                      ("${1}a", false),
-                                      //
-                                      ("$1", false),
-                                      ("${1}", false),
-                                      //
-                                      ("$1 ", false),
-                                      ("${1} ", false),
-                                      //
-                                      ("${1} abc ", false),
-                                      ("$1 abc ", false),
-                                      //
-                                      ("$1abc$2", true),
-                                      ("${1}abc$2", false),
-                                      //
-                                      ("$1abc$2def", true),
-                                      ("${1}abc$2def", true),
-                                      ("$1abc${2}def", true),
-                                      ("${1}abc${2}def", false),
-                                      //
-                                      ("${1} $2", false),
-                                      ("$1$2 ", false)];
-                for (input, expect_error) in test_cases {
-                        let result = check_for_common_syntax_error(input);
-                        match (result.is_err(), expect_error) {
-                                (true, true) => continue,
-                                (false, false) => continue,
-                                (true, false) => panic!("Expected no error for input: {}", input),
-                                (false, true) => panic!("Expected an error for input: {}", input),
-                        }
-                }
+        fn test_read_file_list() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let list = temp_dir.path().join("list");
+                fs::write(&list, "a.txt\nsub/b.txt\n\n")?;
+                assert_eq!(read_file_list(&list, false)?, vec![PathBuf::from("./a.txt"), PathBuf::from("sub/b.txt")]);
+                fs::write(&list, "line\nbreak.txt\0sub/b.txt\0")?;
+                assert_eq!(read_file_list(&list, true)?, vec![PathBuf::from("./line\nbreak.txt"),
+                                                              PathBuf::from("sub/b.txt")]);
+                Ok(())
         }
 
         /// Flat, iterative change of file names.
@@ -285,14 +1687,15 @@ pub mod tests {
         fn test_app_with_norecursion() -> Result<()> {
                 utility_with_global_mutex(|| {
                         let temp_dir = utility_test_dir_gen()?;
-                        std::env::set_current_dir(&temp_dir.path())?;
+                        std::env::set_current_dir(temp_dir.path())?;
 
                         // run fresh
-                        let args = Args { regex:       "(file_.*)".to_string(),
+                        let args = Args { regex: "(file_.*)".to_string(),
                                           replacement: Some("changed-${1}".to_string()),
-                                          recurse:     false,
-                                          preview:     false, };
-                        app(&args)?;
+                                          recurse: false,
+                                          preview: false,
+                                          ..Default::default() };
+                        utility_app(&args)?;
                         println!("temp: {:?}", temp_dir);
 
                         assert!(temp_dir.path().join("changed-file_0a.txt").exists());
@@ -300,11 +1703,12 @@ pub mod tests {
                         assert!(temp_dir.path().join("changed-file_0c.txt").exists());
 
                         // run on changed
-                        let args = Args { regex:       "(file_.*)".to_string(),
+                        let args = Args { regex: "(file_.*)".to_string(),
                                           replacement: Some("changed-${1}".to_string()),
-                                          recurse:     false,
-                                          preview:     false, };
-                        app(&args)?;
+                                          recurse: false,
+                                          preview: false,
+                                          ..Default::default() };
+                        utility_app(&args)?;
                         println!("temp: {:?}", temp_dir);
 
                         assert!(temp_dir.path().join("changed-changed-file_0a.txt").exists());
@@ -325,14 +1729,15 @@ pub mod tests {
         fn test_app_with_yesrecursion() -> Result<()> {
                 utility_with_global_mutex(|| {
                         let temp_dir = utility_test_dir_gen()?;
-                        std::env::set_current_dir(&temp_dir.path())?;
+                        std::env::set_current_dir(temp_dir.path())?;
 
                         // run fresh
-                        let args = Args { regex:       "(file.*)".to_string(),
+                        let args = Args { regex: "(file.*)".to_string(),
                                           replacement: Some("changed-${1}".to_string()),
-                                          recurse:     true,
-                                          preview:     false, };
-                        app(&args)?;
+                                          recurse: true,
+                                          preview: false,
+                                          ..Default::default() };
+                        utility_app(&args)?;
                         println!("temp: {:?}", temp_dir);
 
                         assert!(temp_dir.path().join("changed-file_0a.txt").exists());
@@ -351,11 +1756,12 @@ pub mod tests {
                                         .exists());
 
                         // run against dirs
-                        let args = Args { regex:       "(dir.*)".to_string(),
+                        let args = Args { regex: "(dir.*)".to_string(),
                                           replacement: Some("changed-${1}".to_string()),
-                                          recurse:     true,
-                                          preview:     false, };
-                        app(&args)?;
+                                          recurse: true,
+                                          preview: false,
+                                          ..Default::default() };
+                        utility_app(&args)?;
                         println!("temp: {:?}", temp_dir);
 
                         assert!(temp_dir.path().join("changed-file_0a.txt").exists());
@@ -378,11 +1784,12 @@ pub mod tests {
                                         .exists());
 
                         // run against both
-                        let args = Args { regex:       r"(\d+)".to_string(),
+                        let args = Args { regex: r"(\d+)".to_string(),
                                           replacement: Some("d${1}".to_string()),
-                                          recurse:     true,
-                                          preview:     false, };
-                        app(&args)?;
+                                          recurse: true,
+                                          preview: false,
+                                          ..Default::default() };
+                        utility_app(&args)?;
                         println!("temp: {:?}", temp_dir);
 
                         assert!(temp_dir.path().join("changed-file_d0a.txt").exists());
@@ -406,4 +1813,622 @@ pub mod tests {
                         Ok(())
                 })
         }
+
+        /// Multiple roots, including one reached via symlink, are planned together.
+        ///
+        /// Entries reachable from two roots are renamed once; collisions abort before any rename.
+        #[cfg(unix)]
+        #[test]
+        fn test_app_with_multiple_roots() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let other_dir = utility_test_dir_gen()?;
+                let alias = other_dir.path().join("alias_of_dir_1");
+                std::os::unix::fs::symlink(temp_dir.path().join("dir_1"), &alias)?;
+
+                let args = Args { regex: "(file_.*)".to_string(),
+                                  paths: vec![temp_dir.path().join("dir_1"), alias.clone()],
+                                  replacement: Some("changed-${1}".to_string()),
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("changed-file_1a.txt").exists());
+
+                // a file root is a candidate itself, and not a tree to report matches under
+                let args = Args { regex: "(file_.*)".to_string(),
+                                  paths: vec![temp_dir.path().join("file_0a.txt"),
+                                              temp_dir.path().join("file_0c.txt")],
+                                  replacement: Some("changed-${1}".to_string()),
+                                  ..Default::default() };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                assert!(!String::from_utf8(out)?.contains("Matches under"));
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+                assert!(temp_dir.path().join("changed-file_0c.txt").exists());
+                assert!(temp_dir.path().join("file_0b.txt").exists());
+
+                // collision: two files would take the same name -- nothing is renamed (`--path` roots count too)
+                let args = Args { regex: "file_0.".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  path_flags: vec![other_dir.path().to_path_buf()],
+                                  replacement: Some("same".to_string()),
+                                  ..Default::default() };
+                assert!(utility_app(&args).is_err());
+                assert!(temp_dir.path().join("file_0b.txt").exists());
+                assert!(other_dir.path().join("file_0a.txt").exists());
+                Ok(())
+        }
+
+        /// With a single root (here via `--path`), printed paths are relative to it; with several, they are not.
+        #[test]
+        fn test_app_path_relative_output() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let root = temp_dir.path().to_string_lossy().into_owned();
+                let args = Args { regex: "file_1a".to_string(),
+                                  path_flags: vec![temp_dir.path().to_path_buf()],
+                                  recurse: true,
+                                  preview: true,
+                                  replacement: Some("x".to_string()),
+                                  ..Default::default() };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                let text = String::from_utf8(out)?;
+                assert!(text.contains("./dir_1") && !text.contains(&*root), "{}", text);
+
+                let args = Args { paths: vec![temp_dir.path().join("dir_2")], ..args };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                assert!(String::from_utf8(out)?.contains(&*root));
+                Ok(())
+        }
+
+        /// A project config's ignore rules prune the walk, and its recipes (with their filters) can be used.
+        #[test]
+        fn test_app_project_config() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                fs::write(temp_dir.path().join(config::PROJECT_CONFIG_FILE),
+                          "ignore = [\"dir_11/\", \"file_0b*\"]\n\
+                           [recipe.tag]\ndescription = \"tag\"\npattern = '^(file_.*)'\n\
+                           replacement = 'tagged-${1}'\n[recipe.tag.filters]\nexclude = ['0c']\n")?;
+                let args = Args { recipe: Some("tag".to_string()),
+                                  regex: temp_dir.path().to_string_lossy().into_owned(),
+                                  recurse: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("tagged-file_0a.txt").exists());
+                assert!(temp_dir.path().join("file_0b.txt").exists());
+                assert!(temp_dir.path().join("file_0c.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("tagged-file_1a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("file_11a.txt").exists());
+                Ok(())
+        }
+
+        /// Excluded directories are pruned whole; excluded files are skipped.
+        #[test]
+        fn test_app_with_exclude() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "(file_.*)".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  exclude: vec![Regex::new("^dir_1$")?, Regex::new("0b")?],
+                                  replacement: Some("changed-${1}".to_string()),
+                                  recurse: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+                assert!(temp_dir.path().join("file_0b.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("file_1a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("file_11a.txt").exists());
+                Ok(())
+        }
+
+        #[test]
+        fn test_app_with_depths() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "(file_.*)".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  min_depth: Some(2),
+                                  max_depth: Some(2),
+                                  replacement: Some("changed-${1}".to_string()),
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("changed-file_1a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("file_11a.txt").exists());
+
+                // A nested root is walked even where the outer root's depth limit stops short of it
+                let args = Args { paths: vec![temp_dir.path().to_path_buf(),
+                                              temp_dir.path().join("dir_1").join("dir_11")],
+                                  min_depth: None,
+                                  ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("changed-file_11a.txt").exists());
+                assert!(temp_dir.path()
+                                .join("dir_1")
+                                .join("dir_11")
+                                .join("dir_111")
+                                .join("changed-file_111a.txt")
+                                .exists());
+
+                let args = Args { min_depth: Some(3), max_depth: Some(2), ..args };
+                assert!(utility_app(&args).is_err());
+                Ok(())
+        }
+
+        /// Unwalkable paths are skipped and listed, or with `--strict-walk` fail the run before anything is renamed.
+        #[test]
+        fn test_app_strict_walk() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let list = temp_dir.path().join("list");
+                fs::write(&list,
+                          format!("{}\n{}\n",
+                                  temp_dir.path().join("file_0a.txt").display(),
+                                  temp_dir.path().join("missing").display()))?;
+                let mut args = Args { regex: "(file_0a.*)".to_string(),
+                                      files_from: Some(list),
+                                      replacement: Some("changed-${1}".to_string()),
+                                      strict_walk: true,
+                                      ..Default::default() };
+                assert!(utility_app(&args).is_err());
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+
+                args.strict_walk = false;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+                Ok(())
+        }
+
+        /// A missing root fails the run up front.
+        #[test]
+        fn test_app_missing_root() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "(file_0a.*)".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf(), temp_dir.path().join("missing")],
+                                  replacement: Some("changed-${1}".to_string()),
+                                  ..Default::default() };
+                let error = utility_app(&args).unwrap_err();
+                assert!(error.to_string().contains("missing"), "{}", error);
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+                Ok(())
+        }
+
+        /// Embedders get a run's events through their own reporter.
+        #[test]
+        fn test_run_with_reporter() -> Result<()> {
+                #[derive(Default)]
+                struct Collector {
+                        entries:     Vec<PathBuf>,
+                        is_finished: bool,
+                }
+                impl Reporter for Collector {
+                        fn entry(&mut self, entry: &PlanEntry, _is_test_run: bool) -> std::io::Result<()> {
+                                self.entries.push(entry.path.clone());
+                                Ok(())
+                        }
+
+                        fn finished(&mut self, _plan: &Plan) -> std::io::Result<()> {
+                                self.is_finished = true;
+                                Ok(())
+                        }
+                }
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "^file_0[ab]".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  ..Default::default() };
+                let mut collector = Collector::default();
+                run_with(&mut collector, &args, Config::default())?;
+                assert_eq!(collector.entries.len(), 2);
+                assert!(collector.is_finished);
+                Ok(())
+        }
+
+        /// Failed renames are tolerated up to `--max-errors`, then the run aborts.
+        #[test]
+        fn test_execute_plan_max_errors() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let root = temp_dir.path();
+                let mut plan = Plan::default();
+                for name in ["gone_1.txt", "file_0a.txt", "gone_2.txt", "file_0b.txt"] {
+                        plan.push(PlanEntry::new(root, &root.join(name), false, Some(format!("changed-{}", name))));
+                }
+                let args = Args { max_errors: 1, ..Default::default() };
+                let error = execute_plan(&mut SilentReporter, &plan, &[], &args, false).unwrap_err();
+                assert!(error.to_string().contains("--max-errors 1"), "{}", error);
+                assert!(root.join("changed-file_0a.txt").exists());
+                assert!(root.join("file_0b.txt").exists());
+
+                let args = Args { max_errors: 2, ..Default::default() };
+                let mut plan = Plan::default();
+                for name in ["gone_1.txt", "file_0b.txt"] {
+                        plan.push(PlanEntry::new(root, &root.join(name), false, Some(format!("changed-{}", name))));
+                }
+                assert!(execute_plan(&mut SilentReporter, &plan, &[], &args, false).is_err());
+                assert!(root.join("changed-file_0b.txt").exists());
+                Ok(())
+        }
+
+        /// Symlinked directories are only descended into with `--follow-symlinks`.
+        #[cfg(unix)]
+        #[test]
+        fn test_app_follow_symlinks() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let linked = TempDir::new()?;
+                std::os::unix::fs::symlink(temp_dir.path().join("dir_1"), linked.path().join("link"))?;
+                let mut args = Args { regex: "(file_1a.*)".to_string(),
+                                      paths: vec![linked.path().to_path_buf()],
+                                      replacement: Some("changed-${1}".to_string()),
+                                      recurse: true,
+                                      ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("file_1a.txt").exists());
+
+                args.follow_symlinks = true;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("changed-file_1a.txt").exists());
+                Ok(())
+        }
+
+        /// A symlink loop is reported once and skipped; the rest of the tree is still processed.
+        #[cfg(unix)]
+        #[test]
+        fn test_build_plan_symlink_loop() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                std::os::unix::fs::symlink("..", temp_dir.path().join("dir_1").join("loop"))?;
+                let args = Args { regex: "(file_.*)".to_string(),
+                                  replacement: Some("changed-${1}".to_string()),
+                                  recurse: true,
+                                  follow_symlinks: true,
+                                  ..Default::default() };
+                let plan = build_plan(vec![temp_dir.path().to_path_buf()], &Matcher::new(&args.regex)?, &args);
+                assert_eq!(plan.loops, vec![(temp_dir.path().join("dir_1").join("loop"),
+                                             temp_dir.path().to_path_buf())]);
+                assert_eq!(plan.stats.walk_errors, 0);
+                assert!(plan.entries.iter().any(|entry| entry.path.ends_with("dir_1/dir_11/dir_111/file_111a.txt")));
+                Ok(())
+        }
+
+        #[test]
+        fn test_build_plan_type_filters() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let matcher = Matcher::new("_1")?;
+                let args = Args { recurse: true, files_only: true, ..Default::default() };
+                let plan = build_plan(vec![temp_dir.path().to_path_buf()], &matcher, &args);
+                assert!(plan.entries.iter().all(|entry| !entry.is_dir));
+                assert_eq!(plan.entries.len(), 3);
+
+                let args = Args { dirs_only: true, ..args };
+                let plan = build_plan(vec![temp_dir.path().to_path_buf()], &matcher, &args);
+                assert_eq!(plan.entries.len(), 6);
+                assert_eq!(plan.stats.filtered_by_type, 0);
+
+                let args = Args { files_only: false, ..args };
+                let plan = build_plan(vec![temp_dir.path().to_path_buf()], &matcher, &args);
+                assert!(plan.entries.iter().all(|entry| entry.is_dir));
+                assert_eq!(plan.stats.filtered_by_type, 6);
+                Ok(())
+        }
+
+        /// Size and mtime filters and `{mtime:...}` tokens share one metadata fetch per entry, batched across threads.
+        #[test]
+        fn test_build_plan_metadata_filters() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                fs::write(temp_dir.path().join("file_0a.txt"), [0; 100])?;
+                let matcher = Matcher::new("file_")?;
+                let args = Args { recurse: true,
+                                  larger_than: Some(Size(10)),
+                                  newer_than: Some(Age(std::time::Duration::from_secs(60 * 60))),
+                                  replacement: Some("{mtime:%Y}_".to_string()),
+                                  walk_buffer: 2,
+                                  metadata_threads: 3,
+                                  ..Default::default() };
+                let plan = build_plan(vec![temp_dir.path().to_path_buf()], &matcher, &args);
+                let new_names: Vec<_> = plan.entries.iter().map(|entry| entry.new_filename.clone()).collect();
+                assert_eq!(new_names, vec![Some(format!("{}_0a.txt", Local::now().format("%Y")))]);
+                assert_eq!(plan.stats.filtered_by_size, 5);
+                Ok(())
+        }
+
+        /// `--all` replaces every match, within `--max-matches-per-file`.
+        #[test]
+        fn test_app_replace_all() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let mut args = Args { regex: "t".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      replacement: Some("T".to_string()),
+                                      all: true,
+                                      max_matches_per_file: 1,
+                                      ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+
+                args.max_matches_per_file = DEFAULT_MAX_MATCHES_PER_FILE;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("file_0a.TxT").exists());
+                Ok(())
+        }
+
+        /// Invalid new names are shown with the reason and not renamed; the rest of the plan goes ahead.
+        #[test]
+        fn test_app_rejects_invalid_names() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "^file_0[ab]".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("".to_string()),
+                                  ..Default::default() };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                assert!(String::from_utf8(out)?.contains("only the extension is left"));
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+
+                let args = Args { regex: "^file_0a".to_string(),
+                                  allow_bare_ext: true,
+                                  allow_dotfile_change: true,
+                                  ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join(".txt").exists());
+                Ok(())
+        }
+
+        /// `--full-path` matches the relative path; only the last component is renamed.
+        #[test]
+        fn test_app_full_path() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: r"^dir_1/dir_11/(file_\w+)".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("dir_1/dir_11/changed-${1}".to_string()),
+                                  full_path: true,
+                                  recurse: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("changed-file_11a.txt").exists());
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("dir_111").join("file_111a.txt").exists());
+
+                // changing a parent component is refused
+                let args = Args { regex: r"^dir_1/(file_1a)".to_string(),
+                                  replacement: Some("dir_2/${1}".to_string()),
+                                  ..args };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                assert!(String::from_utf8(out)?.contains("only the last path component can be renamed"));
+                assert!(temp_dir.path().join("dir_1").join("file_1a.txt").exists());
+
+                // backslash-separated paths and templates; a `/`-written pattern matches them with --any-sep
+                let args = Args { regex: r"^dir_1/(file_1a)".to_string(),
+                                  replacement: Some(r"dir_1\changed-${1}".to_string()),
+                                  path_sep: PathSep::Backslash,
+                                  any_sep: true,
+                                  ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("changed-file_1a.txt").exists());
+                let args = Args { regex: r"^dir_2\\(dir_21)$".to_string(),
+                                  replacement: Some(r"dir_3\${1}".to_string()),
+                                  any_sep: false,
+                                  ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_2").join("dir_21").exists());
+                let args = Args { replacement: Some(r"dir_2\${1}x".to_string()), ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_2").join("dir_21x").exists());
+
+                // --insert positions count within the name, not the path
+                let args = Args { regex: r"^dir_1\\dir_11\\changed-".to_string(),
+                                  replacement: None,
+                                  insert: Some("new-".to_string()),
+                                  at: InsertAt::Start,
+                                  ..args };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("dir_1").join("dir_11").join("new-changed-file_11a.txt").exists());
+                Ok(())
+        }
+
+        /// `--fast-preview` renames nothing, says it checked nothing, and refuses options that need metadata.
+        #[test]
+        fn test_app_fast_preview() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "(file_0a.*)".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("changed-${1}".to_string()),
+                                  fast_preview: true,
+                                  ..Default::default() };
+                let mut out = Vec::new();
+                run_to(&mut out, &args, Config::default())?;
+                assert!(String::from_utf8(out)?.contains("were NOT checked"));
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+                let mut out = Vec::new();
+                run_to(&mut out, &Args { json: true, ..args.clone() }, Config::default())?;
+                assert!(String::from_utf8(out)?.contains(r#""validated": false"#));
+
+                let args = Args { replacement: Some("{mtime:%Y}_${1}".to_string()), ..args };
+                assert!(utility_app(&args).is_err());
+                for flag in ["--larger-than=1k", "--newer-than=1d", "--changed-only", "--skip-marked"] {
+                        assert!(Args::try_parse_from(["rename_files", "x", "--fast-preview", flag]).is_err(),
+                                "{}",
+                                flag);
+                }
+                Ok(())
+        }
+
+        /// Whitespace left at the ends of a new name is rejected, or trimmed with `--auto-trim`.
+        #[test]
+        fn test_app_auto_trim() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let mut args = Args { regex: r"^file_0a\.txt$".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      replacement: Some(" new.txt ".to_string()),
+                                      ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+
+                args.auto_trim = true;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("new.txt").exists());
+                Ok(())
+        }
+
+        /// `--stem-only` keeps the extension out of reach of the pattern.
+        #[test]
+        fn test_app_stem_only() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let args = Args { regex: "^file_0a.*".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("${0}_new".to_string()),
+                                  stem_only: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("file_0a_new.txt").exists());
+                Ok(())
+        }
+
+        /// `--fixed-string` takes regex metacharacters literally.
+        #[test]
+        fn test_app_fixed_string() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                File::create(temp_dir.path().join("a (1)+.txt"))?;
+                let args = Args { regex: "(1)+.".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("1.".to_string()),
+                                  fixed_string: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("a 1.txt").exists());
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+                Ok(())
+        }
+
+        /// `--exact` matches whole names only, and `--rep` then gives the whole new name.
+        #[test]
+        fn test_app_exact() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                File::create(temp_dir.path().join("old_file_0d.txt"))?;
+                let args = Args { regex: r"file_0(\w)\.txt".to_string(),
+                                  paths: vec![temp_dir.path().to_path_buf()],
+                                  replacement: Some("${1}.md".to_string()),
+                                  exact: true,
+                                  ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("a.md").exists());
+                assert!(temp_dir.path().join("c.md").exists());
+                assert!(temp_dir.path().join("old_file_0d.txt").exists());
+                Ok(())
+        }
+
+        /// `--not` selects, and can rename, the entries the pattern does not match.
+        #[test]
+        fn test_app_not() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let mut args = Args { regex: "^file_0[ab]".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      rename_to: Some("x_$1".to_string()),
+                                      invert: true,
+                                      ..Default::default() };
+                assert!(utility_app(&args).is_err());
+                args.rename_to = Some("done_{name}".to_string());
+                utility_app(&args)?;
+                for name in ["file_0a.txt", "file_0b.txt", "done_file_0c.txt", "done_dir_1", "done_dir_2"] {
+                        assert!(temp_dir.path().join(name).exists(), "{}", name);
+                }
+                Ok(())
+        }
+
+        /// Hidden entries are skipped (and hidden directories not descended into) unless `--hidden` is given.
+        #[test]
+        fn test_app_with_hidden() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                fs::create_dir(temp_dir.path().join(".dir_h"))?;
+                fs::File::create(temp_dir.path().join(".dir_h").join("file_h1.txt"))?;
+                fs::File::create(temp_dir.path().join(".file_h0.txt"))?;
+                let mut args = Args { regex: "(file_h.*)".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      replacement: Some("changed-${1}".to_string()),
+                                      recurse: true,
+                                      ..Default::default() };
+                utility_app(&args)?;
+                assert!(temp_dir.path().join(".file_h0.txt").exists());
+                assert!(temp_dir.path().join(".dir_h").join("file_h1.txt").exists());
+
+                args.hidden = true;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join(".changed-file_h0.txt").exists());
+                assert!(temp_dir.path().join(".dir_h").join("changed-file_h1.txt").exists());
+                Ok(())
+        }
+
+        /// Directory renames referenced from matching files are skipped; unreferenced ones proceed.
+        ///
+        /// # Warning:
+        /// This test manipulates the working directory manipulation (which is a process-wide global state).
+        /// Code execution is controlled by a global mutex to make this function thread-safe.
+        #[test]
+        fn test_app_refuses_referenced_dir_renames() -> Result<()> {
+                utility_with_global_mutex(|| {
+                        let temp_dir = utility_test_dir_gen()?;
+                        std::env::set_current_dir(temp_dir.path())?;
+                        fs::write(temp_dir.path().join("Makefile"), "build:\n\tls dir_2/dir_21\n")?;
+
+                        let args = Args { regex: "(dir_2.*)".to_string(),
+                                          replacement: Some("changed-${1}".to_string()),
+                                          recurse: true,
+                                          refuse_dir_rename_if_referenced: vec!["**/Makefile".to_string()],
+                                          ..Default::default() };
+                        utility_app(&args)?;
+
+                        assert!(temp_dir.path().join("dir_2").join("dir_21").join("changed-dir_211").exists());
+                        assert!(!temp_dir.path().join("changed-dir_2").exists());
+                        Ok(())
+                })
+        }
+
+        /// Safe mode (`safe_mode = true`, off by default) refuses unconfirmed renames; `--yes` allows them.
+        #[test]
+        fn test_app_safe_mode() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let config_dir = TempDir::new()?;
+                let config_path = config_dir.path().join("config.toml");
+                fs::write(&config_path, "safe_mode = true\n")?;
+
+                let mut args = Args { regex: "(file_0a.*)".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      replacement: Some("changed-${1}".to_string()),
+                                      config: Some(config_path),
+                                      ..Default::default() };
+                assert!(utility_app(&args).is_err());
+                assert!(temp_dir.path().join("file_0a.txt").exists());
+
+                args.yes = true;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+
+                args.yes = false;
+                args.config = None;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-changed-file_0a.txt").exists());
+                Ok(())
+        }
+
+        /// Protected paths (here: from config) are refused as roots and as renames without `--allow-protected`.
+        #[test]
+        fn test_app_protected_paths() -> Result<()> {
+                let temp_dir = utility_test_dir_gen()?;
+                let config_dir = TempDir::new()?;
+                let config_path = config_dir.path().join("config.toml");
+                let dir_1 = temp_dir.path().join("dir_1");
+                fs::write(&config_path, format!("protected_paths = [{:?}, {:?}]\n", temp_dir.path(), dir_1))?;
+
+                // as a root
+                let mut args = Args { regex: "(file_0a.*)".to_string(),
+                                      paths: vec![temp_dir.path().to_path_buf()],
+                                      replacement: Some("changed-${1}".to_string()),
+                                      config: Some(config_path),
+                                      ..Default::default() };
+                assert!(utility_app(&args).is_err());
+                args.allow_protected = true;
+                utility_app(&args)?;
+                assert!(temp_dir.path().join("changed-file_0a.txt").exists());
+
+                // as a rename
+                args.allow_protected = false;
+                args.paths = vec![temp_dir.path().join(".")];
+                args.regex = "(dir_1)".to_string();
+                assert!(utility_app(&args).is_err());
+                assert!(dir_1.exists());
+                Ok(())
+        }
 }
@@ -5,12 +5,25 @@
 //! clear; el; carr -- '(C|c)argo.*(\..*)' --rep '$1ogra$2' --preview
 //! clear; el; carr -- '(C|c)argo.*(\..*)' --rep '${1}ogra$2' --preview
 //! ```
+//!
+//! # Exit codes:
+//! - 0: success
+//! - 1: `--count` found no matches
+//! - 2: error
+
+use std::process::ExitCode;
 
-use clap::Parser;
-use rename_files::{app, error::Result, logging, Args};
+use rename_files::{app, error::NoMatches, logging, Args};
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
         logging::tracing_subscribe_boilerplate("warn");
-        let args = Args::parse();
-        app(&args)
+        // Display, not Debug: keeps multi-line errors (e.g. config locations) readable
+        match Args::parse_with_aliases().and_then(|args| app(&args)) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) if e.is::<NoMatches>() => ExitCode::from(1),
+                Err(e) => {
+                        eprintln!("Error: {}", e);
+                        ExitCode::from(2)
+                }
+        }
 }
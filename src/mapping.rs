@@ -0,0 +1,88 @@
+//! `--map`: new text for matches looked up in a two-column table (e.g. a spreadsheet export).
+//!
+//! Each row maps the captured text (the pattern's first participating group, or the whole match) to its
+//! replacement.  Files ending `.tsv` or `.tab` are tab separated, anything else comma separated; fields may be
+//! quoted as spreadsheets write them.  There is no header row: every row is a mapping.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+/// A `--map` table, as loaded from `source`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Mapping {
+        source: String,
+        table:  Arc<HashMap<String, String>>,
+}
+
+impl Mapping {
+        /// From (key, value) rows; the last row for a key wins.
+        pub fn new(source: &str, rows: impl IntoIterator<Item=(String, String)>) -> Self {
+                Self { source: source.to_string(), table: Arc::new(rows.into_iter().collect()) }
+        }
+
+        /// The file the table was loaded from.
+        pub fn source(&self) -> &str {
+                &self.source
+        }
+
+        /// Value `key` maps to, if any.
+        pub fn get(&self, key: &str) -> Option<&str> {
+                self.table.get(key).map(String::as_str)
+        }
+}
+
+/// Loads the table for `--map FILE`.  Every row must have exactly two fields.
+pub fn load(path: &str) -> Result<Mapping, String> {
+        let delimiter = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("tsv") || ext.eq_ignore_ascii_case("tab") => b'\t',
+                _ => b',',
+        };
+        let mut reader = csv::ReaderBuilder::new().has_headers(false)
+                                                  .flexible(true)
+                                                  .delimiter(delimiter)
+                                                  .from_path(path)
+                                                  .map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        let mut rows = Vec::new();
+        for (i, record) in reader.records().enumerate() {
+                let record = record.map_err(|e| format!("could not read {:?}: {}", path, e))?;
+                match (record.len(), record.get(0), record.get(1)) {
+                        (2, Some(key), Some(value)) => rows.push((key.to_string(), value.to_string())),
+                        (fields, ..) => {
+                                return Err(format!("{:?} row {}: expected 2 fields (from, to), found {}",
+                                                   path,
+                                                   i + 1,
+                                                   fields));
+                        }
+                }
+        }
+        Ok(Mapping::new(path, rows))
+}
+
+#[cfg(test)]
+mod tests {
+        use std::fs;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_load() -> Result<(), Box<dyn std::error::Error>> {
+                let temp_dir = TempDir::new()?;
+                let csv = temp_dir.path().join("people.csv");
+                fs::write(&csv, "jdoe,Jane Doe\n\"smith, j\",John Smith\njdoe,Jane Q. Doe\n")?;
+                let mapping = load(csv.to_str().unwrap())?;
+                assert_eq!(mapping.get("jdoe"), Some("Jane Q. Doe"));
+                assert_eq!(mapping.get("smith, j"), Some("John Smith"));
+                assert_eq!(mapping.get("nobody"), None);
+
+                let tsv = temp_dir.path().join("codes.tsv");
+                fs::write(&tsv, "NY\tNew York, NY\n")?;
+                assert_eq!(load(tsv.to_str().unwrap())?.get("NY"), Some("New York, NY"));
+
+                fs::write(&csv, "a,b\nc\n")?;
+                assert!(load(csv.to_str().unwrap()).unwrap_err().contains("row 2: expected 2 fields"));
+                assert!(load("no_such_map.csv").is_err());
+                Ok(())
+        }
+}
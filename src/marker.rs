@@ -0,0 +1,63 @@
+//! `--mark` / `--skip-marked`: a processed marker kept in an extended attribute on renamed entries.
+//!
+//! Unlike `--changed-only` state, the marker travels with the entry, so a watch or cron job never reprocesses a file
+//! it already renamed, even if the new name happens to match the pattern again.  The attribute's value is when the
+//! marking run started.  Only supported where extended attributes are (Linux, macOS); the symlink itself is marked,
+//! not its target, which Linux does not allow for `user.` attributes.
+
+use std::path::Path;
+
+use crate::error::Result;
+
+/// Name of the extended attribute marking a processed entry.
+pub const ATTR: &str = "user.rename_files.run";
+
+/// Marks `path` as processed by the run started at `run`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+pub fn mark(path: &Path, run: &str) -> Result<()> {
+        rustix::fs::lsetxattr(path, ATTR, run.as_bytes(), rustix::fs::XattrFlags::empty())
+                .map_err(|e| format!("Could not mark {:?} as processed: {}", path, e).into())
+}
+
+/// Marks `path` as processed.  (Unsupported on this platform.)
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+pub fn mark(path: &Path, _run: &str) -> Result<()> {
+        Err(format!("Could not mark {:?} as processed: extended attributes are not supported here", path).into())
+}
+
+/// Whether `path` carries the processed marker.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+pub fn is_marked(path: &Path) -> bool {
+        // Only presence matters; an empty buffer asks for the value's size
+        rustix::fs::lgetxattr(path, ATTR, &mut []).is_ok()
+}
+
+/// Whether `path` carries the processed marker.  (Never, on this platform.)
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+pub fn is_marked(_path: &Path) -> bool {
+        false
+}
+
+#[cfg(test)]
+mod tests {
+        use std::fs;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_mark() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let file = temp_dir.path().join("photo.jpg");
+                fs::write(&file, "")?;
+                assert!(!is_marked(&file));
+                // Not every filesystem (e.g. some tmpfs) takes user attributes
+                if mark(&file, "2024-01-02T03:04:05+00:00").is_ok() {
+                        assert!(is_marked(&file));
+                }
+                assert!(mark(&temp_dir.path().join("missing"), "").is_err());
+                Ok(())
+        }
+}
@@ -0,0 +1,975 @@
+//! Filename matching and replacement.
+//!
+//! Patterns are compiled once, up front.  Per-entry work is ordered so that entries which do not match are rejected
+//! without any allocation or utf8 conversion: the cheap byte-level check runs first, and only matches pay for
+//! captures and replacement building.
+//!
+//! With `--fancy`, a pattern the regex crate cannot compile (lookaround, backreferences) is compiled with fancy-regex
+//! instead (see [`crate::engine`]).  Such patterns skip the byte-level checks: names are converted to utf8 first.
+
+use std::{borrow::Cow, collections::HashMap, ffi::OsStr, fmt, str::FromStr};
+
+use regex::bytes;
+use regex_syntax::hir::{Hir, HirKind, Look,
+                        literal::{ExtractKind, Extractor}};
+use serde::Serialize;
+
+use crate::{engine::{Captures, Engine},
+            error::Result};
+
+/// Replacement tokens beyond capture references: whole match, text before/after it, and the whole name, its stem,
+/// and its extension (with the dot; empty if none).
+pub const TOKENS: &[&str] = &["{keep}", "{before}", "{after}", "{name}", "{stem}", "{ext}"];
+
+/// sed/perl-style case escapes in replacements: `\U`/`\L` upper/lowercase everything after them, up to `\E`;
+/// `\u`/`\l` only the next character.  Any other backslash is literal text.
+pub const CASE_ESCAPES: &[&str] = &[r"\U", r"\L", r"\E", r"\u", r"\l"];
+
+/// Which (non-empty) match in a name `--occurrence` replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrence {
+        /// The Nth match, counting from 1.
+        Nth(usize),
+        /// The last match.
+        Last,
+}
+
+impl FromStr for Occurrence {
+        type Err = String;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s {
+                        "last" => Ok(Self::Last),
+                        _ => match s.parse() {
+                                Ok(n) if n > 0 => Ok(Self::Nth(n)),
+                                _ => Err(format!("expected a match number from 1, or last; got {:?}", s)),
+                        },
+                }
+        }
+}
+
+/// As parsed by [`Occurrence::from_str`].
+impl fmt::Display for Occurrence {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        Self::Nth(n) => write!(f, "{}", n),
+                        Self::Last => write!(f, "last"),
+                }
+        }
+}
+
+/// Compiled search pattern.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+        re:           Engine,
+        /// Same pattern, over raw (os encoded) filename bytes.  Lets non-matches skip utf8 conversion.  (Standard
+        /// engine only.)
+        bytes_re:     Option<bytes::Regex>,
+        /// Literal prefix/suffix check for anchored patterns, if any could be extracted.
+        prefilter:    Option<Prefilter>,
+        /// With several patterns ([`Matcher::any_of`]): each on its own, to build new names from the one that matched.
+        /// `re` is then their alternation, for matching alone.
+        alternatives: Vec<Engine>,
+}
+
+impl Matcher {
+        /// Compiles the pattern.
+        pub fn new(pattern: &str) -> Result<Self> {
+                Self::with_case(pattern, false)
+        }
+
+        /// Compiles the pattern, case-insensitively if `ignore_case` (as a leading `(?i)` would).
+        pub fn with_case(pattern: &str, ignore_case: bool) -> Result<Self> {
+                Self::compile(pattern, ignore_case, false)
+        }
+
+        /// Compiles the pattern, with fancy-regex if `allow_fancy` and the standard engine cannot.
+        fn compile(pattern: &str, ignore_case: bool, allow_fancy: bool) -> Result<Self> {
+                let re = Engine::new(pattern, ignore_case, allow_fancy)?;
+                let bytes_re = match re.standard() {
+                        Some(_) => Some(bytes::RegexBuilder::new(pattern).case_insensitive(ignore_case).build()?),
+                        None => None,
+                };
+                let prefilter = regex_syntax::ParserBuilder::new().case_insensitive(ignore_case)
+                                                                  .build()
+                                                                  .parse(pattern)
+                                                                  .ok()
+                                                                  .and_then(|hir| Prefilter::new(&hir));
+                tracing::debug!("Literal prefilter: {:?}", prefilter);
+                Ok(Self { re, bytes_re, prefilter, alternatives: Vec::new() })
+        }
+
+        /// Compiles several patterns, matching names that match any of them.
+        ///
+        /// Matching is a single pass over their [`alternation`]; new names are built from the first pattern (in the
+        /// order given) that matches, with its own capture groups.  Group names must differ between patterns.
+        ///
+        /// With `allow_fancy` (`--fancy`), patterns the standard engine cannot compile use fancy-regex.
+        pub fn any_of(patterns: &[impl AsRef<str>], ignore_case: bool, allow_fancy: bool) -> Result<Self> {
+                if let [pattern] = patterns {
+                        return Self::compile(pattern.as_ref(), ignore_case, allow_fancy);
+                }
+                let mut matcher = Self::compile(&alternation(patterns), ignore_case, allow_fancy)?;
+                matcher.alternatives = patterns.iter()
+                                               .map(|pattern| Engine::new(pattern.as_ref(), ignore_case, allow_fancy))
+                                               .collect::<Result<_>>()?;
+                Ok(matcher)
+        }
+
+        /// The compiled (`str`) pattern.
+        pub fn regex(&self) -> &Engine {
+                &self.re
+        }
+
+        /// Regexes new names are built with: each alternative, or the only pattern.
+        fn patterns(&self) -> &[Engine] {
+                match self.alternatives.is_empty() {
+                        true => std::slice::from_ref(&self.re),
+                        false => &self.alternatives,
+                }
+        }
+
+        /// Regex to build `name`'s new name with: the first alternative that matches it, or the only pattern.
+        fn matching(&self, name: &str) -> &Engine {
+                self.alternatives.iter().find(|re| re.is_match(name)).unwrap_or(&self.re)
+        }
+
+        /// Cheapest pre-check: literal prefix/suffix byte comparison.  `true` means the name cannot match.
+        ///
+        /// Only available for patterns anchored at the start (`^IMG_`) or end (`\.jpg$`) of the name; otherwise
+        /// never rejects.
+        pub fn prefilter_rejects(&self, name: &OsStr) -> bool {
+                self.prefilter.as_ref().is_some_and(|prefilter| !prefilter.is_candidate(name.as_encoded_bytes()))
+        }
+
+        /// Cheap pre-check on a raw filename.  No allocation, no utf8 validation.
+        ///
+        /// Equivalent to [`Engine::is_match`] for utf8 names.  A non-utf8 name may pass; it is rejected later, at
+        /// utf8 conversion.  (Patterns that needed fancy-regex do convert, and never pass non-utf8 names.)
+        pub fn is_match_os(&self, name: &OsStr) -> bool {
+                match &self.bytes_re {
+                        Some(bytes_re) => bytes_re.is_match(name.as_encoded_bytes()),
+                        None => name.to_str().is_some_and(|name| self.re.is_match(name)),
+                }
+        }
+
+        /// Replaces the first match in `name`, expanding `rep` via `scratch` (a buffer reused across calls).
+        ///
+        /// `rep` may use capture references, the [`TOKENS`], and [case escapes](CASE_ESCAPES).
+        /// Returns `None` if `name` does not match.  The returned name is allocated at its exact size.
+        pub fn replace(&self, name: &str, rep: &str, scratch: &mut String) -> Option<String> {
+                self.replace_with(name, rep, &[], scratch)
+        }
+
+        /// As [`Matcher::replace`], with `extra` (token, value) pairs as well.
+        pub fn replace_with(&self,
+                            name: &str,
+                            rep: &str,
+                            extra: &[(&str, &str)],
+                            scratch: &mut String)
+                            -> Option<String> {
+                let caps = self.matching(name).captures(name)?;
+                let whole = caps.get(0).expect("group 0 always participates");
+                scratch.clear();
+                expand(&caps, name, rep, extra, scratch);
+                let mut new_name = String::with_capacity(whole.start() + scratch.len() + (name.len() - whole.end()));
+                new_name.push_str(&name[..whole.start()]);
+                new_name.push_str(scratch);
+                new_name.push_str(&name[whole.end()..]);
+                Some(new_name)
+        }
+
+        /// Replaces the captured text of the first match in `name` (its first participating group, or the whole
+        /// match) with what `lookup` gives for it.  `None` if `name` does not match, or `lookup` has nothing.
+        pub fn replace_captured<'a>(&self, name: &str, lookup: impl FnOnce(&str) -> Option<&'a str>) -> Option<String> {
+                let caps = self.matching(name).captures(name)?;
+                let captured = caps.iter().skip(1).flatten().next().or_else(|| caps.get(0))?;
+                let value = lookup(captured.as_str())?;
+                let mut new_name = String::with_capacity(name.len() - captured.len() + value.len());
+                new_name.push_str(&name[..captured.start()]);
+                new_name.push_str(value);
+                new_name.push_str(&name[captured.end()..]);
+                Some(new_name)
+        }
+
+        /// Replaces one match in `name`, as [`Matcher::replace`] does the first.  Empty matches are not counted.
+        /// Returns `None` if `name` has no such match.
+        pub fn replace_nth_with(&self,
+                                name: &str,
+                                rep: &str,
+                                occurrence: Occurrence,
+                                extra: &[(&str, &str)],
+                                scratch: &mut String)
+                                -> Option<String> {
+                let mut matches = self.matching(name).captures_iter(name).filter(|caps| !caps.whole().is_empty());
+                let caps = match occurrence {
+                        Occurrence::Nth(n) => matches.nth(n - 1)?,
+                        Occurrence::Last => matches.last()?,
+                };
+                let whole = caps.get(0).expect("group 0 always participates");
+                scratch.clear();
+                expand(&caps, name, rep, extra, scratch);
+                let mut new_name = String::with_capacity(whole.start() + scratch.len() + (name.len() - whole.end()));
+                new_name.push_str(&name[..whole.start()]);
+                new_name.push_str(scratch);
+                new_name.push_str(&name[whole.end()..]);
+                Some(new_name)
+        }
+
+        /// Replaces every match in `name`, as [`Matcher::replace`] does the first.
+        ///
+        /// Empty matches are left alone: otherwise a pattern like `a*` would put `rep` between every character.
+        /// Returns `None` if `name` has no non-empty match, or (with a warning) more than `max_matches` of them.
+        pub fn replace_all(&self, name: &str, rep: &str, max_matches: usize, scratch: &mut String) -> Option<String> {
+                self.replace_all_with(name, rep, max_matches, &[], scratch)
+        }
+
+        /// As [`Matcher::replace_all`], with `extra` (token, value) pairs as well.
+        pub fn replace_all_with(&self,
+                                name: &str,
+                                rep: &str,
+                                max_matches: usize,
+                                extra: &[(&str, &str)],
+                                scratch: &mut String)
+                                -> Option<String> {
+                let mut new_name = String::with_capacity(name.len());
+                let (mut copied_to, mut count) = (0, 0);
+                for caps in self.matching(name).captures_iter(name) {
+                        let whole = caps.get(0).expect("group 0 always participates");
+                        if whole.is_empty() {
+                                continue;
+                        }
+                        count += 1;
+                        // Guard: pathological pattern, e.g. `.` on a long name
+                        if count > max_matches {
+                                tracing::warn!("{:?} has more than {} matches; not renamed (see \
+                                                --max-matches-per-file)",
+                                               name,
+                                               max_matches);
+                                return None;
+                        }
+                        scratch.clear();
+                        expand(&caps, name, rep, extra, scratch);
+                        new_name.push_str(&name[copied_to..whole.start()]);
+                        new_name.push_str(scratch);
+                        copied_to = whole.end();
+                }
+                if count == 0 {
+                        return None;
+                }
+                new_name.push_str(&name[copied_to..]);
+                Some(new_name)
+        }
+
+        /// Builds a whole new name from `template` (capture references, [`TOKENS`], case escapes, and the `extra`
+        /// tokens), if `name` matches.  Unlike [`Matcher::replace`] the text around the match is not kept.
+        pub fn render(&self,
+                      name: &str,
+                      template: &str,
+                      extra: &[(&str, &str)],
+                      scratch: &mut String)
+                      -> Option<String> {
+                let caps = self.matching(name).captures(name)?;
+                scratch.clear();
+                expand(&caps, name, template, extra, scratch);
+                Some(scratch.as_str().to_owned())
+        }
+
+        /// Checks that every capture reference in a replacement `template` names a group of the pattern (of any of
+        /// them, with several).  Group 0 (`$0`, `${0}`) is the whole match, which every pattern has.
+        ///
+        /// References are read as the regex crate reads them: `$name` takes the longest run of `[_0-9a-zA-Z]`, so
+        /// `$1a` and `$12` name groups `1a` and `12`.  When such a reference names no group but a prefix of it does,
+        /// the error suggests the braced form (`${1}a`, `${1}2`).
+        pub fn check_template(&self, template: &str) -> Result<()> {
+                let has_group = |group: &str| self.patterns().iter().any(|re| has_group(re, group));
+                for (group, is_braced) in capture_refs(template) {
+                        if has_group(group) {
+                                continue;
+                        }
+                        let written = match is_braced {
+                                true => format!("${{{}}}", group),
+                                false => format!("${}", group),
+                        };
+                        // Unbraced references are ascii, so every length is a char boundary
+                        let prefix = match is_braced {
+                                true => None,
+                                false => {
+                                        (1..group.len()).rev().map(|len| &group[..len]).find(|prefix| has_group(prefix))
+                                }
+                        };
+                        return Err(match prefix {
+                                Some(prefix) => format!("Replacement refers to unknown capture group `{}`; did you \
+                                                         mean `${{{}}}{}`?",
+                                                        written,
+                                                        prefix,
+                                                        &group[prefix.len()..]),
+                                None => format!("Replacement refers to unknown capture group `{}`, but {}",
+                                                written,
+                                                self.groups_described(group)),
+                        }.into());
+                }
+                Ok(())
+        }
+
+        /// What groups there are, for an error about the missing `group` (an index or a name).
+        fn groups_described(&self, group: &str) -> String {
+                let patterns = self.patterns();
+                if group.parse::<usize>().is_ok() {
+                        let count = patterns.iter().map(|re| re.captures_len() - 1).max().unwrap_or(0);
+                        let groups = match count {
+                                1 => "1 capture group".to_string(),
+                                count => format!("{} capture groups", count),
+                        };
+                        return match patterns {
+                                [re] if count == 0 => format!("the pattern `{}` has no capture groups", re.as_str()),
+                                [re] => format!("the pattern `{}` has only {}", re.as_str(), groups),
+                                _ => format!("no pattern has more than {}", groups),
+                        };
+                }
+                let missing = match patterns {
+                        [re] => format!("the pattern `{}` has no group named `{}`", re.as_str(), group),
+                        _ => format!("no pattern has a group named `{}`", group),
+                };
+                let names: Vec<&str> = patterns.iter().flat_map(|re| re.capture_names().flatten()).collect();
+                match names.is_empty() {
+                        true => missing,
+                        false => format!("{} (named groups: {})", missing, names.join(", ")),
+                }
+        }
+
+        /// Likely mistakes in a (valid, see [`Matcher::check_template`]) replacement `template`, one message each:
+        /// capture groups it never uses, and references to groups that can never match (so are always empty).
+        pub fn template_warnings(&self, template: &str) -> Vec<String> {
+                let refs = capture_refs(template);
+                let mut warnings = Vec::new();
+                for re in self.patterns() {
+                        let unmatchable = unmatchable_groups(re.as_str());
+                        for (index, name) in re.capture_names().enumerate().skip(1) {
+                                let group = match name {
+                                        Some(name) => format!("${{{}}}", name),
+                                        None => format!("${}", index),
+                                };
+                                let is_used =
+                                        refs.iter()
+                                            .any(|(group, _)| {
+                                                    group.parse::<usize>().ok() == Some(index) || Some(*group) == name
+                                            });
+                                if !is_used {
+                                        warnings.push(format!("Capture group `{}` is not used in the replacement",
+                                                              group));
+                                } else if unmatchable.contains(&index) {
+                                        warnings.push(format!("Capture group `{}` can never match, so it is always \
+                                                               empty in the replacement",
+                                                              group));
+                                }
+                        }
+                }
+                warnings
+        }
+
+        /// Where the pattern (first) matched `name`, and what each capture group took.  `None` if it does not match.
+        pub fn explain(&self, name: &str) -> Option<MatchExplanation> {
+                let re = self.matching(name);
+                let caps = re.captures(name)?;
+                let whole = caps.get(0).expect("group 0 always participates");
+                let captures = re.capture_names()
+                                 .enumerate()
+                                 .skip(1)
+                                 .map(|(index, group_name)| {
+                                         let group = caps.get(index);
+                                         Capture { index,
+                                                   name: group_name.map(str::to_owned),
+                                                   value: group.map(|group| group.as_str().to_owned()),
+                                                   start: group.map(|group| group.start()),
+                                                   end: group.map(|group| group.end()) }
+                                 })
+                                 .collect();
+                Some(MatchExplanation { start: whole.start(), end: whole.end(), captures })
+        }
+}
+
+/// Why a name matched: the span of the (first) match and the value of every capture group.
+///
+/// Offsets are utf8 byte offsets into the name, end-exclusive (as `&name[start..end]`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchExplanation {
+        pub start:    usize,
+        pub end:      usize,
+        /// Groups 1.., in pattern order.  Groups that did not participate have no value or offsets.
+        pub captures: Vec<Capture>,
+}
+
+/// One capture group's part of a match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capture {
+        pub index: usize,
+        /// Group name, for `(?<name>...)` groups.
+        pub name:  Option<String>,
+        pub value: Option<String>,
+        pub start: Option<usize>,
+        pub end:   Option<usize>,
+}
+
+/// A single pattern matching any of `patterns`: each in its own non-capturing group, joined with `|`.
+pub fn alternation(patterns: &[impl AsRef<str>]) -> String {
+        patterns.iter().map(|pattern| format!("(?:{})", pattern.as_ref())).collect::<Vec<_>>().join("|")
+}
+
+/// `pattern`, with each path separator in it (`/`, or an escaped `\\` or `\/`) matching either separator.
+pub fn any_separator(pattern: &str) -> String {
+        const EITHER: &str = r"[/\\]";
+        let mut rewritten = String::with_capacity(pattern.len());
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+                match c {
+                        '/' => rewritten.push_str(EITHER),
+                        '\\' => match chars.next() {
+                                Some('/' | '\\') => rewritten.push_str(EITHER),
+                                Some(escaped) => {
+                                        rewritten.push('\\');
+                                        rewritten.push(escaped);
+                                }
+                                None => rewritten.push('\\'),
+                        },
+                        _ => rewritten.push(c),
+                }
+        }
+        rewritten
+}
+
+/// Capture references in a replacement template, in order: each group as written, and whether it was braced.  `$$`
+/// and a `$` that starts no reference are literal.
+fn capture_refs(template: &str) -> Vec<(&str, bool)> {
+        let mut refs = Vec::new();
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+                rest = &rest[dollar + 1..];
+                if let Some(after) = rest.strip_prefix('$') {
+                        rest = after;
+                } else if let Some(after) = rest.strip_prefix('{') {
+                        // Unclosed or empty braces are literal text
+                        if let Some(close) = after.find('}').filter(|&close| close > 0) {
+                                refs.push((&after[..close], true));
+                                rest = &after[close + 1..];
+                        }
+                } else {
+                        let len = rest.find(|c: char| c != '_' && !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+                        if len > 0 {
+                                refs.push((&rest[..len], false));
+                                rest = &rest[len..];
+                        }
+                }
+        }
+        refs
+}
+
+/// Whether `group` (an index or a name) is a capture group of `re`.
+fn has_group(re: &Engine, group: &str) -> bool {
+        match group.parse::<usize>() {
+                Ok(index) => index < re.captures_len(),
+                Err(_) => re.capture_names().any(|name| name == Some(group)),
+        }
+}
+
+/// Indices of the capture groups of `pattern` that can never match: e.g. `([^\s\S])`, or any group inside one.
+fn unmatchable_groups(pattern: &str) -> Vec<usize> {
+        fn walk(hir: &Hir, is_unmatchable: bool, found: &mut Vec<usize>) {
+                // An alternation's length bounds give up on any unmatchable branch; its branches are checked instead
+                let is_alternation = matches!(hir.kind(), HirKind::Alternation(_));
+                let is_unmatchable = is_unmatchable || (!is_alternation && hir.properties().minimum_len().is_none());
+                match hir.kind() {
+                        HirKind::Capture(capture) => {
+                                if is_unmatchable {
+                                        found.push(capture.index as usize);
+                                }
+                                walk(&capture.sub, is_unmatchable, found);
+                        }
+                        HirKind::Repetition(repetition) => walk(&repetition.sub, is_unmatchable, found),
+                        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => {
+                                hirs.iter().for_each(|hir| walk(hir, is_unmatchable, found))
+                        }
+                        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => {}
+                }
+        }
+        let mut found = Vec::new();
+        if let Ok(hir) = regex_syntax::parse(pattern) {
+                walk(&hir, false, &mut found);
+        }
+        found
+}
+
+/// Splits a name into stem and extension (with its dot).  A leading dot marks a hidden file, not an extension.
+pub fn split_ext(name: &str) -> (&str, &str) {
+        match name.rfind('.') {
+                Some(dot) if dot > 0 => name.split_at(dot),
+                _ => (name, ""),
+        }
+}
+
+/// Expands `rep` into `dst`: capture references, [`TOKENS`], `extra` (token, value) pairs, and [`CASE_ESCAPES`].
+fn expand(caps: &Captures, name: &str, rep: &str, extra: &[(&str, &str)], dst: &mut String) {
+        let expand_plain = |rep: &str, dst: &mut String| match rep.contains('{') {
+                true => expand_with_tokens(caps, name, rep, extra, dst),
+                false => caps.expand(rep, dst),
+        };
+        if !rep.contains('\\') {
+                return expand_plain(rep, dst);
+        }
+        // Each run of text between escapes is expanded on its own, then cased as it is appended
+        let mut case = CaseState::default();
+        let mut segment = String::new();
+        let mut rest = rep;
+        while !rest.is_empty() {
+                let escape = rest.match_indices('\\').find(|(i, _)| CaseState::is_escape(rest[i + 1..].chars().next()));
+                let (text, after) = match escape {
+                        Some((i, _)) => (&rest[..i], &rest[i + 1..]),
+                        None => (rest, ""),
+                };
+                segment.clear();
+                expand_plain(text, &mut segment);
+                case.push(&segment, dst);
+                let mut after = after.chars();
+                if let Some(escape) = after.next() {
+                        case.escape(escape);
+                }
+                rest = after.as_str();
+        }
+}
+
+/// Case conversion in effect while expanding a replacement (see [`CASE_ESCAPES`]).
+#[derive(Debug, Default)]
+struct CaseState {
+        /// From `\U`/`\L` until `\E`: `Some(true)` for upper.
+        span: Option<bool>,
+        /// `\u`/`\l`, for the next character only.
+        next: Option<bool>,
+}
+
+impl CaseState {
+        fn is_escape(c: Option<char>) -> bool {
+                matches!(c, Some('U' | 'L' | 'E' | 'u' | 'l'))
+        }
+
+        fn escape(&mut self, c: char) {
+                match c {
+                        'U' => self.span = Some(true),
+                        'L' => self.span = Some(false),
+                        'E' => self.span = None,
+                        'u' => self.next = Some(true),
+                        'l' => self.next = Some(false),
+                        _ => unreachable!("only case escapes are split on"),
+                }
+        }
+
+        /// Appends `text` to `dst`, cased.
+        fn push(&mut self, text: &str, dst: &mut String) {
+                let mut chars = text.chars();
+                if let (Some(upper), Some(first)) = (self.next, text.chars().next()) {
+                        push_cased(first.encode_utf8(&mut [0; 4]), Some(upper), dst);
+                        chars.next();
+                        self.next = None;
+                }
+                push_cased(chars.as_str(), self.span, dst);
+        }
+}
+
+/// Appends `text`, upper- (`Some(true)`) or lowercased (`Some(false)`), or as is.
+fn push_cased(text: &str, upper: Option<bool>, dst: &mut String) {
+        match upper {
+                Some(true) => dst.extend(text.chars().flat_map(char::to_uppercase)),
+                Some(false) => dst.extend(text.chars().flat_map(char::to_lowercase)),
+                None => dst.push_str(text),
+        }
+}
+
+/// Expands `rep` into `dst`, substituting [`TOKENS`] and `extra` (token, value) pairs as well as capture references.
+///
+/// Token-like text inside a capture reference (`${keep}` names a group) or after `$$` is left to the regex crate.
+fn expand_with_tokens(caps: &Captures, name: &str, rep: &str, extra: &[(&str, &str)], dst: &mut String) {
+        let whole = caps.get(0).expect("group 0 always participates");
+        let (stem, ext) = split_ext(name);
+        let token_value = |token: &str| match token {
+                "{keep}" => Some(whole.as_str()),
+                "{before}" => Some(&name[..whole.start()]),
+                "{after}" => Some(&name[whole.end()..]),
+                "{name}" => Some(name),
+                "{stem}" => Some(stem),
+                "{ext}" => Some(ext),
+                _ => extra.iter().find(|(extra_token, _)| *extra_token == token).map(|(_, value)| *value),
+        };
+        // `{token:0W}`: a numeric extra token, zero-padded to width W
+        let padded_value = |token: &str| {
+                let (base, width) = token.strip_suffix('}')?.split_once(":0")?;
+                let width: usize = width.parse().ok()?;
+                let value: i64 = token_value(&format!("{}}}", base))?.parse().ok()?;
+                Some(format!("{:0width$}", value))
+        };
+        let bytes = rep.as_bytes();
+        let (mut literal_start, mut i) = (0, 0);
+        while i < bytes.len() {
+                match bytes[i] {
+                        b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                                i = rep[i..].find('}').map_or(bytes.len(), |close| i + close + 1);
+                        }
+                        b'$' => i += 2,
+                        b'{' => {
+                                let token = rep[i..].find('}').map(|close| &rep[i..=i + close]);
+                                let value = |token| {
+                                        token_value(token).map(Cow::Borrowed)
+                                                          .or_else(|| padded_value(token).map(Cow::Owned))
+                                };
+                                let Some((token, value)) = token.and_then(|token| Some((token, value(token)?))) else {
+                                        i += 1;
+                                        continue;
+                                };
+                                caps.expand(&rep[literal_start..i], dst);
+                                dst.push_str(&value);
+                                i += token.len();
+                                literal_start = i;
+                        }
+                        _ => i += 1,
+                }
+        }
+        caps.expand(&rep[literal_start..], dst);
+}
+
+/// Literal prefixes/suffixes that every match of an anchored pattern must begin/end the name with.
+#[derive(Debug, Clone)]
+struct Prefilter {
+        /// Name must start with one of these (if not empty).
+        prefixes: Vec<Vec<u8>>,
+        /// Name must end with one of these (if not empty).
+        suffixes: Vec<Vec<u8>>,
+}
+
+impl Prefilter {
+        /// Extracts literals from the pattern.  `None` if the pattern is unanchored or has no usable literals.
+        fn new(hir: &Hir) -> Option<Self> {
+                let props = hir.properties();
+                let prefixes = match props.look_set_prefix().contains(Look::Start) {
+                        true => literals(hir, ExtractKind::Prefix),
+                        false => Vec::new(),
+                };
+                let suffixes = match props.look_set_suffix().contains(Look::End) {
+                        true => literals(hir, ExtractKind::Suffix),
+                        false => Vec::new(),
+                };
+                (!prefixes.is_empty() || !suffixes.is_empty()).then_some(Self { prefixes, suffixes })
+        }
+
+        /// Whether the name passes the literal checks (and so may match).
+        fn is_candidate(&self, name: &[u8]) -> bool {
+                let has_prefix = self.prefixes.is_empty() || self.prefixes.iter().any(|p| name.starts_with(p));
+                let has_suffix = self.suffixes.is_empty() || self.suffixes.iter().any(|s| name.ends_with(s));
+                has_prefix && has_suffix
+        }
+}
+
+/// Finite, non-empty literal set of the given kind, or empty if none is usable.
+fn literals(hir: &Hir, kind: ExtractKind) -> Vec<Vec<u8>> {
+        let seq = Extractor::new().kind(kind).extract(hir);
+        match seq.literals() {
+                Some(literals) if !literals.is_empty() && literals.iter().all(|lit| !lit.as_bytes().is_empty()) => {
+                        literals.iter().map(|lit| lit.as_bytes().to_vec()).collect()
+                }
+                _ => Vec::new(),
+        }
+}
+
+/// Memo of filename -> replacement, shared across all roots of a run.
+///
+/// Trees often hold thousands of identically named files (`index.js`, `cover.jpg`); with the cache the regex and
+/// replacement expansion run once per distinct name.  Must be disabled when the replacement depends on more than
+/// the filename (e.g. metadata).
+#[derive(Debug, Default)]
+pub struct TransformCache {
+        map: Option<HashMap<String, Option<String>>>,
+}
+
+impl TransformCache {
+        /// A cache, or a pass-through if not enabled.
+        pub fn new(is_enabled: bool) -> Self {
+                Self { map: is_enabled.then(HashMap::new) }
+        }
+
+        /// Cached transform of `name`, computing (and storing) it on first sight.
+        ///
+        /// Returns the transform and whether it came from the cache.
+        pub fn get_or_compute(&mut self,
+                              name: &str,
+                              compute: impl FnOnce() -> Option<String>)
+                              -> (Option<String>, bool) {
+                let Some(map) = &mut self.map else { return (compute(), false) };
+                if let Some(cached) = map.get(name) {
+                        return (cached.clone(), true);
+                }
+                let computed = compute();
+                map.insert(name.to_string(), computed.clone());
+                (computed, false)
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        /// Replacement agrees with `Regex::replace` (first match only).
+        #[test]
+        fn test_replace_matches_regex_replace() {
+                let test_cases = vec![(r"(\d+)", "d${1}", "file_12_34.txt"),
+                                      (r"^(file)", "$1-x", "file_0a.txt"),
+                                      (r"\.txt$", "", "file_0a.txt"),
+                                      ("(a)(b)?", "[$2$1]", "cab")];
+                let mut scratch = String::new();
+                for (pattern, rep, name) in test_cases {
+                        let matcher = Matcher::new(pattern).unwrap();
+                        let expected = matcher.regex().standard().unwrap().replace(name, rep).into_owned();
+                        assert_eq!(matcher.replace(name, rep, &mut scratch), Some(expected));
+                        assert!(matcher.is_match_os(OsStr::new(name)));
+                }
+                assert_eq!(Matcher::new("z").unwrap().replace("abc", "y", &mut scratch), None);
+        }
+
+        #[test]
+        fn test_replace_all() {
+                let mut scratch = String::new();
+                let matcher = Matcher::new(r"(\d+)").unwrap();
+                assert_eq!(matcher.replace_all("a1_b22_c3.txt", "<$1>", 10, &mut scratch).as_deref(),
+                           Some("a<1>_b<22>_c<3>.txt"));
+                assert_eq!(matcher.replace_all("a1_b22_c3.txt", "<$1>", 2, &mut scratch), None);
+                assert_eq!(matcher.replace_all("abc", "x", 10, &mut scratch), None);
+                // empty matches are skipped, not filled in between every character
+                let matcher = Matcher::new("a*").unwrap();
+                assert_eq!(matcher.replace_all("abaac", "x", 10, &mut scratch).as_deref(), Some("xbxc"));
+                assert_eq!(matcher.replace_all("bc", "x", 10, &mut scratch), None);
+        }
+
+        /// `--fancy` patterns build new names (and explanations) as standard ones do.
+        #[cfg(feature = "fancy")]
+        #[test]
+        fn test_fancy() {
+                let mut scratch = String::new();
+                let matcher = Matcher::any_of(&[r"(?<=IMG_)(\d+)(?=\.)"], false, true).unwrap();
+                assert!(matcher.is_match_os(OsStr::new("IMG_0042.jpg")));
+                assert!(!matcher.is_match_os(OsStr::new("DSC_0042.jpg")));
+                assert_eq!(matcher.replace("IMG_0042.jpg", "{keep}_$1", &mut scratch).as_deref(),
+                           Some("IMG_0042_0042.jpg"));
+                assert_eq!(matcher.explain("IMG_0042.jpg").map(|explained| (explained.start, explained.end)),
+                           Some((4, 8)));
+                // Doubled words: a backreference
+                let matcher = Matcher::any_of(&[r"([a-z0-9]+)-\1", "^x"], false, true).unwrap();
+                assert_eq!(matcher.replace_all_with("final-final_v2-v2", "$1", 10, &[], &mut scratch).as_deref(),
+                           Some("final_v2"));
+                assert!(matcher.check_template("$2").is_err());
+                assert!(Matcher::any_of(&[r"(\w)\1"], false, false).is_err());
+        }
+
+        #[test]
+        fn test_replace_nth() {
+                let mut scratch = String::new();
+                let matcher = Matcher::new(r"\d{4}").unwrap();
+                let replace_nth = |name, occurrence: &str, scratch: &mut String| {
+                        matcher.replace_nth_with(name, "2022", occurrence.parse().unwrap(), &[], scratch)
+                };
+                assert_eq!(replace_nth("2021_backup_2021.tar", "2", &mut scratch).as_deref(),
+                           Some("2021_backup_2022.tar"));
+                assert_eq!(replace_nth("2021_backup_2021.tar", "1", &mut scratch).as_deref(),
+                           Some("2022_backup_2021.tar"));
+                assert_eq!(replace_nth("1999_2000_2021.tar", "last", &mut scratch).as_deref(),
+                           Some("1999_2000_2022.tar"));
+                assert_eq!(replace_nth("2021_backup.tar", "2", &mut scratch), None);
+                assert!("0".parse::<Occurrence>().is_err());
+                assert!("first".parse::<Occurrence>().is_err());
+                // empty matches are not counted
+                let matcher = Matcher::new("a*").unwrap();
+                assert_eq!(matcher.replace_nth_with("abaac", "x", Occurrence::Nth(2), &[], &mut scratch).as_deref(),
+                           Some("abxc"));
+        }
+
+        #[test]
+        fn test_replace_tokens() {
+                let mut scratch = String::new();
+                let test_cases = vec![(r"\.jpg$", "_small{keep}", "cat.jpg", "cat_small.jpg"),
+                                      (r"\.jpg$", "_small$0", "cat.jpg", "cat_small.jpg"),
+                                      ("b", "[{before}|{after}]", "abc", "a[a|c]c"),
+                                      ("(b)", "${1}{keep}", "abc", "abbc"),
+                                      ("(?P<keep>b)", "${keep}{x}", "abc", "ab{x}c"),
+                                      ("b", "$${keep}", "abc", "a$bc"),
+                                      ("b", "old_${0}", "abc", "aold_bc"),
+                                      ("^x", "{stem}|{ext}|{name}|", "x.tar.gz", "x.tar|.gz|x.tar.gz|.tar.gz")];
+                for (pattern, rep, name, expected) in test_cases {
+                        let matcher = Matcher::new(pattern).unwrap();
+                        assert_eq!(matcher.replace(name, rep, &mut scratch).as_deref(), Some(expected), "rep: {}", rep);
+                }
+        }
+
+        #[test]
+        fn test_case_escapes() {
+                let mut scratch = String::new();
+                let test_cases = vec![(r"^(\w+)_(\w+)", r"\U$1\E_$2", "foo_bar.txt", "FOO_bar.txt"),
+                                      (r"^(\w+)_(\w+)", r"\u$1 \u$2", "foo_bar.txt", "Foo Bar.txt"),
+                                      (r"^(\w+)", r"\L\u$1", "hELLO.txt", "Hello.txt"),
+                                      (r"\.(\w+)$", r".\L{keep}", "a.JPG", "a..jpg"),
+                                      (r"^x", r"\Uy\E\n", "xz", "Y\\nz"),
+                                      ("^(ä)", r"\U$1", "äb", "Äb")];
+                for (pattern, rep, name, expected) in test_cases {
+                        let matcher = Matcher::new(pattern).unwrap();
+                        assert_eq!(matcher.replace(name, rep, &mut scratch).as_deref(), Some(expected), "rep: {}", rep);
+                }
+        }
+
+        /// Capture references are resolved against the pattern's groups, not guessed at.
+        #[test]
+        fn test_check_template() {
+                let matcher = Matcher::new(r"(a)(?<word>\w+)").unwrap();
+                let test_cases = vec![("$1", true),
+                                      ("${1}abc", true),
+                                      ("$1 abc", true),
+                                      ("$1$2", true),
+                                      ("${word}_$word", true),
+                                      ("$$1abc", true),
+                                      ("$0{keep}", true),
+                                      ("old_${0}", true),
+                                      ("$00", true),
+                                      (r"\U$1\E_$2", true),
+                                      ("cost: $", true),
+                                      ("$1abc", false),
+                                      ("$12", false),
+                                      ("${3}", false),
+                                      ("${words}", false),
+                                      ("${1}abc$2def", false)];
+                for (template, is_valid) in test_cases {
+                        assert_eq!(matcher.check_template(template).is_ok(), is_valid, "template: {}", template);
+                }
+                let error = matcher.check_template("$12").unwrap_err().to_string();
+                assert!(error.contains("`${1}2`"), "{}", error);
+                // Errors name both the reference and the groups there are
+                let error = matcher.check_template("${1}_${3}").unwrap_err().to_string();
+                assert_eq!(error,
+                           "Replacement refers to unknown capture group `${3}`, but the pattern `(a)(?<word>\\w+)` has \
+                            only 2 capture groups");
+                let error = matcher.check_template("${words}").unwrap_err().to_string();
+                assert!(error.ends_with("has no group named `words` (named groups: word)"), "{}", error);
+                assert!(Matcher::new(&"(x)".repeat(12)).unwrap().check_template("$12").is_ok());
+                // With several patterns, a group of any of them will do
+                let matcher = Matcher::any_of(&["(?<a>x)", "(?<b>y)"], false, false).unwrap();
+                assert!(matcher.check_template("${a}${b}").is_ok());
+        }
+
+        #[test]
+        fn test_template_warnings() {
+                let matcher = Matcher::new(r"^(\d+)_(?<title>\w+)(jpg|png)?").unwrap();
+                assert!(matcher.template_warnings("$1 ${title}$3").is_empty());
+                assert_eq!(matcher.template_warnings("${title}"),
+                           ["Capture group `$1` is not used in the replacement",
+                            "Capture group `$3` is not used in the replacement"]);
+                let matcher = Matcher::new(r"(a)|(?:[^\s\S](b))").unwrap();
+                assert_eq!(matcher.template_warnings("$1$2"),
+                           ["Capture group `$2` can never match, so it is always empty in the replacement"]);
+        }
+
+        /// New names come from the first pattern that matches, with its own groups.
+        #[test]
+        fn test_any_of() {
+                let mut scratch = String::new();
+                let matcher = Matcher::any_of(&[r"^IMG_(\d+)", r"^DSC(\d+)", r"(\d+)"], false, false).unwrap();
+                assert!(matcher.is_match_os(OsStr::new("DSC7.jpg")));
+                assert!(!matcher.is_match_os(OsStr::new("notes.txt")));
+                assert_eq!(matcher.replace("IMG_12.jpg", "photo_$1", &mut scratch).as_deref(), Some("photo_12.jpg"));
+                assert_eq!(matcher.replace("DSC7.jpg", "photo_$1", &mut scratch).as_deref(), Some("photo_7.jpg"));
+                assert_eq!(matcher.replace("x9.jpg", "photo_$1", &mut scratch).as_deref(), Some("xphoto_9.jpg"));
+                assert_eq!(matcher.explain("DSC7.jpg").map(|explanation| explanation.captures.len()), Some(1));
+                assert_eq!(alternation(&["a", "b|c"]), "(?:a)|(?:b|c)");
+        }
+
+        /// `--any-sep`: a pattern written with either separator matches paths using either.
+        #[test]
+        fn test_any_separator() {
+                assert_eq!(any_separator(r"^a/b\\c\/\d[^/]"), r"^a[/\\]b[/\\]c[/\\]\d[^[/\\]]");
+                for pattern in [r"photos\\(\d+)\\(.*)", "photos/(\\d+)/(.*)"] {
+                        let matcher = Matcher::new(&any_separator(pattern)).unwrap();
+                        assert!(matcher.is_match_os(OsStr::new(r"photos\2023\a.jpg")));
+                        assert!(matcher.is_match_os(OsStr::new("photos/2023/a.jpg")));
+                }
+        }
+
+        #[test]
+        fn test_ignore_case() {
+                let matcher = Matcher::with_case(r"^img_(\d+)", true).unwrap();
+                assert!(!matcher.prefilter_rejects(OsStr::new("IMG_1.jpg")));
+                assert!(matcher.prefilter_rejects(OsStr::new("notes.txt")));
+                assert!(matcher.is_match_os(OsStr::new("Img_1.jpg")));
+                // the match keeps the name's case; only the replacement text is new
+                assert_eq!(matcher.replace("IMG_1.jpg", "photo_${1}", &mut String::new()).as_deref(),
+                           Some("photo_1.jpg"));
+                assert!(!Matcher::new(r"^img_").unwrap().is_match_os(OsStr::new("IMG_1.jpg")));
+        }
+
+        /// Prefilter only rejects names the regex cannot match, and only for anchored patterns.
+        #[test]
+        fn test_prefilter() {
+                let names = ["IMG_001.jpg", "img_001.JPG", "IMG_002.png", "notes.txt", "x_IMG_1.jpg"];
+                let test_cases = vec![(r"^IMG_", true),
+                                      (r"\.jpg$", true),
+                                      (r"^(IMG|DSC)_\d+\.(jpg|png)$", true),
+                                      (r"(?i)^img_", true),
+                                      (r"IMG_", false),
+                                      (r"^.*\.txt", false)];
+                for (pattern, has_prefilter) in test_cases {
+                        let matcher = Matcher::new(pattern).unwrap();
+                        assert_eq!(matcher.prefilter.is_some(), has_prefilter, "pattern: {}", pattern);
+                        for name in names {
+                                if matcher.prefilter_rejects(OsStr::new(name)) {
+                                        assert!(!matcher.regex().is_match(name),
+                                                "pattern: {}, name: {}",
+                                                pattern,
+                                                name);
+                                }
+                        }
+                }
+                assert!(Matcher::new(r"^IMG_").unwrap().prefilter_rejects(OsStr::new("notes.txt")));
+        }
+
+        #[test]
+        fn test_render() {
+                let matcher = Matcher::new(r"IMG_(\d+)").unwrap();
+                let mut scratch = String::new();
+                assert_eq!(matcher.render("IMG_0042.jpg", "shot-$1-{n}{ext}", &[("{n}", "7")], &mut scratch)
+                                  .as_deref(),
+                           Some("shot-0042-7.jpg"));
+                assert_eq!(matcher.render("notes.txt", "{stem}", &[], &mut scratch), None);
+                assert_eq!(split_ext(".bashrc"), (".bashrc", ""));
+        }
+
+        #[test]
+        fn test_explain() {
+                let matcher = Matcher::new(r"IMG_(?<num>\d+)(_x)?").unwrap();
+                let explanation = matcher.explain("ä IMG_0042.jpg").unwrap();
+                assert_eq!((explanation.start, explanation.end), (3, 11));
+                assert_eq!(explanation.captures, vec![Capture { index: 1,
+                                                                name:  Some("num".to_string()),
+                                                                value: Some("0042".to_string()),
+                                                                start: Some(7),
+                                                                end:   Some(11), },
+                                                      Capture { index: 2,
+                                                                name:  None,
+                                                                value: None,
+                                                                start: None,
+                                                                end:   None, }]);
+                assert_eq!(matcher.explain("notes.txt"), None);
+        }
+
+        #[test]
+        fn test_transform_cache() {
+                let mut cache = TransformCache::new(true);
+                assert_eq!(cache.get_or_compute("index.js", || Some("a".to_string())), (Some("a".to_string()), false));
+                assert_eq!(cache.get_or_compute("index.js", || Some("b".to_string())), (Some("a".to_string()), true));
+
+                let mut cache = TransformCache::new(false);
+                assert_eq!(cache.get_or_compute("index.js", || Some("a".to_string())), (Some("a".to_string()), false));
+                assert_eq!(cache.get_or_compute("index.js", || Some("b".to_string())), (Some("b".to_string()), false));
+        }
+}
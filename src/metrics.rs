@@ -0,0 +1,152 @@
+//! Prometheus-format metrics for long-running modes (`daemon --metrics <ADDR>`).
+//!
+//! Served over plain HTTP at `/metrics` from a background thread, so a home-server auto-sorter can be scraped
+//! alongside other services.  Bind to a loopback address unless the network is trusted: there is no authentication.
+//!
+//! # Metrics:
+//! - `rename_files_requests_total`: requests handled
+//! - `rename_files_errors_total`: requests answered with an error (bad request or failed operation)
+//! - `rename_files_files_renamed_total`: entries renamed by `execute` requests
+//! - `rename_files_queue_depth`: clients connected and waiting to be served
+
+use std::{fmt::Write as _,
+          io::{BufRead, BufReader, Write},
+          net::{SocketAddr, TcpListener, TcpStream},
+          sync::{Arc,
+                 atomic::{AtomicU64, Ordering}}};
+
+use serde_json::Value;
+
+use crate::error::Result;
+
+/// Counters shared between the serving loop and the metrics endpoint.
+#[derive(Debug, Default)]
+pub struct Metrics {
+        requests:      AtomicU64,
+        errors:        AtomicU64,
+        files_renamed: AtomicU64,
+        queue_depth:   AtomicU64,
+}
+
+impl Metrics {
+        /// Counts one handled request from its method and JSON-RPC response.
+        pub fn record(&self, method: &str, response: &Value) {
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                if response.get("error").is_some() {
+                        self.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                if method == "execute" {
+                        let is_rename = |entry: &&Value| !entry["new_filename"].is_null();
+                        let renamed =
+                                response["result"]["entries"].as_array().map_or(0, |entries| {
+                                                                                entries.iter().filter(is_rename).count()
+                                                                        });
+                        self.files_renamed.fetch_add(renamed as u64, Ordering::Relaxed);
+                }
+        }
+
+        /// A client is waiting to be served.
+        pub fn enqueued(&self) {
+                self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        }
+
+        /// A waiting client is now being served.
+        pub fn dequeued(&self) {
+                self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        /// Prometheus text exposition format.
+        pub fn render(&self) -> String {
+                let metrics =
+                        [("rename_files_requests_total", "counter", "Requests handled.", &self.requests),
+                         ("rename_files_errors_total", "counter", "Requests answered with an error.", &self.errors),
+                         ("rename_files_files_renamed_total",
+                          "counter",
+                          "Entries renamed by execute requests.",
+                          &self.files_renamed),
+                         ("rename_files_queue_depth",
+                          "gauge",
+                          "Clients connected and waiting to be served.",
+                          &self.queue_depth)];
+                let mut text = String::new();
+                for (name, kind, help, value) in metrics {
+                        let _ = writeln!(text, "# HELP {} {}", name, help);
+                        let _ = writeln!(text, "# TYPE {} {}", name, kind);
+                        let _ = writeln!(text, "{} {}", name, value.load(Ordering::Relaxed));
+                }
+                text
+        }
+}
+
+/// Serves `metrics` at `http://<addr>/metrics` from a background thread.
+pub fn spawn_endpoint(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        tracing::warn!("Metrics at http://{}/metrics", listener.local_addr()?);
+        std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                        let result = stream.and_then(|stream| answer(stream, &metrics));
+                        if let Err(e) = result {
+                                tracing::debug!("Metrics request failed: {}", e);
+                        }
+                }
+        });
+        Ok(())
+}
+
+/// Answers one HTTP request: the metrics for `GET /metrics`, else 404.
+fn answer(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Drain the headers; the request has no body we care about
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+                header.clear();
+        }
+        let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                ["GET", "/metrics"] => ("200 OK", metrics.render()),
+                _ => ("404 Not Found", "Not found; try /metrics\n".to_string()),
+        };
+        write!(stream,
+               "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: \
+                close\r\n\r\n{}",
+               status,
+               body.len(),
+               body)
+}
+
+#[cfg(test)]
+mod tests {
+        use serde_json::json;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_metrics() -> Result<()> {
+                let metrics = Arc::new(Metrics::default());
+                let renamed = json!({ "result": { "entries": [{ "new_filename": "a" }, { "new_filename": null }] } });
+                metrics.record("execute", &renamed);
+                metrics.record("plan", &renamed);
+                metrics.record("plan", &json!({ "error": { "code": -32000 } }));
+                metrics.enqueued();
+
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                let addr = listener.local_addr()?;
+                let served = Arc::clone(&metrics);
+                let server =
+                        std::thread::spawn(move || -> std::io::Result<()> { answer(listener.accept()?.0, &served) });
+                let mut client = TcpStream::connect(addr)?;
+                write!(client, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+                let mut response = String::new();
+                std::io::Read::read_to_string(&mut client, &mut response)?;
+                server.join().unwrap()?;
+
+                assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+                assert!(response.contains("\nrename_files_requests_total 3\n"), "{}", response);
+                assert!(response.contains("\nrename_files_errors_total 1\n"), "{}", response);
+                assert!(response.contains("\nrename_files_files_renamed_total 1\n"), "{}", response);
+                assert!(response.contains("\nrename_files_queue_depth 1\n"), "{}", response);
+                Ok(())
+        }
+}
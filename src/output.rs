@@ -0,0 +1,72 @@
+//! Output layer: all user-facing (stdout) output goes through here.
+//!
+//! `println!` locks and (line-buffered) flushes stdout on every call, which dominates runtime when printing hundreds
+//! of thousands of matches.  [`Output`] holds the stdout lock for the whole run and writes through a `BufWriter`,
+//! flushing every [`FLUSH_EVERY_LINES`] lines so progress is still visible on long runs.
+
+use std::io::{self, BufWriter, StdoutLock, Write};
+
+/// Lines written between forced flushes.
+pub const FLUSH_EVERY_LINES: usize = 4096;
+
+/// Buffered, periodically flushed writer.
+#[derive(Debug)]
+pub struct Output<W: Write> {
+        writer:          BufWriter<W>,
+        lines_unflushed: usize,
+}
+
+impl Output<StdoutLock<'static>> {
+        /// Locks stdout for the lifetime of the returned writer.
+        pub fn stdout() -> Self {
+                Self::new(io::stdout().lock())
+        }
+}
+
+impl<W: Write> Output<W> {
+        /// Wraps any writer (e.g. a `Vec<u8>` in tests).
+        pub fn new(inner: W) -> Self {
+                Self { writer: BufWriter::new(inner), lines_unflushed: 0 }
+        }
+
+        /// Flushes and returns the inner writer.
+        pub fn into_inner(self) -> io::Result<W> {
+                self.writer.into_inner().map_err(|e| e.into_error())
+        }
+}
+
+impl<W: Write> Write for Output<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let written = self.writer.write(buf)?;
+                self.lines_unflushed += buf[..written].iter().filter(|&&b| b == b'\n').count();
+                if self.lines_unflushed >= FLUSH_EVERY_LINES {
+                        self.flush()?;
+                }
+                Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+                self.lines_unflushed = 0;
+                self.writer.flush()
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        /// Everything written comes out, in order, and the line counter resets on periodic flushes.
+        #[test]
+        fn test_output_flushes_periodically() {
+                let mut out = Output::new(Vec::new());
+                for i in 0..FLUSH_EVERY_LINES + 2 {
+                        writeln!(out, "line {}", i).unwrap();
+                }
+                assert_eq!(out.lines_unflushed, 2);
+                let text = String::from_utf8(out.into_inner().unwrap()).unwrap();
+                assert_eq!(text.lines().count(), FLUSH_EVERY_LINES + 2);
+                assert!(text.ends_with(&format!("line {}\n", FLUSH_EVERY_LINES + 1)));
+        }
+}
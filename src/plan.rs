@@ -0,0 +1,808 @@
+//! Rename planning.
+//!
+//! All roots are walked *before* anything is renamed.  Matches from every root land in a single [`Plan`],
+//! so that ordering and collision checks see the union of entries rather than one root at a time.
+//!
+//! # Ordering:
+//! A sorted plan is in [`PlanEntry::execution_order`]: deepest first, then by path.  It depends only on the entries,
+//! never on walk order, thread count, or platform directory listing order, so previews, counters, and journals
+//! of the same tree are reproducible.
+
+use std::{borrow::Cow,
+          collections::{HashMap, HashSet},
+          fs,
+          io::{self, Write},
+          path::{Path, PathBuf},
+          sync::Arc};
+
+use chrono::{DateTime, Local};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{fetch::Fetcher,
+            i18n::tr,
+            matcher::{self, MatchExplanation, Matcher},
+            references::ReferenceUpdate,
+            state::RunState,
+            stats::Stats,
+            transform::Transform};
+
+/// A single matched entry and, if a replacement was given, its new filename.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanEntry {
+        /// Root (as passed by the user) that the entry was found under.
+        pub root:         PathBuf,
+        /// Path of the entry, as walked from its root.
+        pub path:         PathBuf,
+        /// New filename, if a replacement was provided.
+        pub new_filename: Option<String>,
+        /// Whether the entry is a directory (symlinks to directories are not).
+        pub is_dir:       bool,
+        /// For directory renames: number of entries beneath the directory, all of which are implicitly relocated.
+        pub descendants:  Option<u64>,
+        /// For directory renames, when requested: every descendant whose absolute path changes.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub relocated:    Option<Vec<Relocation>>,
+        /// Span and capture groups of the match that selected the entry, when requested (`--json`).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub matched:      Option<MatchExplanation>,
+        /// New filename that failed validation (see `validate.rs`), so the entry is not renamed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub rejected:     Option<Rejection>,
+        /// Absolute path of the entry, with its parent's symlinks resolved.  Used for identity across roots.
+        #[serde(skip)]
+        canonical:        PathBuf,
+}
+
+impl PlanEntry {
+        /// Creates an entry, resolving its canonical path.
+        ///
+        /// Only the parent is canonicalized: a symlink entry is identified as the link itself, not its target.
+        /// Falls back to the walked path if canonicalization fails (e.g. entry removed mid-walk).
+        ///
+        /// Directory renames also count their descendants, to show the blast radius of the rename.
+        pub fn new(root: &Path, path: &Path, is_dir: bool, new_filename: Option<String>) -> Self {
+                let canonical = match (path.parent().map(Path::canonicalize), path.file_name()) {
+                        (Some(Ok(parent)), Some(name)) => parent.join(name),
+                        _ => path.to_path_buf(),
+                };
+                let descendants = (is_dir && new_filename.is_some()).then(|| count_descendants(path));
+                Self { root: root.to_path_buf(),
+                       path: path.to_path_buf(),
+                       new_filename,
+                       is_dir,
+                       descendants,
+                       relocated: None,
+                       matched: None,
+                       rejected: None,
+                       canonical }
+        }
+
+        /// Creates an entry without touching the filesystem: no canonicalization and no descendant count.
+        ///
+        /// For `--fast-preview`.  Identity across roots then falls back to the walked path.
+        pub fn new_unresolved(root: &Path, path: &Path, is_dir: bool, new_filename: Option<String>) -> Self {
+                Self { root: root.to_path_buf(),
+                       path: path.to_path_buf(),
+                       new_filename,
+                       is_dir,
+                       descendants: None,
+                       relocated: None,
+                       matched: None,
+                       rejected: None,
+                       canonical: path.to_path_buf() }
+        }
+
+        /// Creates entries for a batch of walked paths, resolving them with `fetcher` (see [`crate::fetch`]).
+        ///
+        /// Resolution (canonicalizing, counting descendants) is stat-bound.  Order of the batch is preserved.  An entry
+        /// whose resolution timed out comes back as `Err` with its path.
+        pub fn new_batch(root: &Path,
+                         batch: Vec<(PathBuf, bool, Option<String>)>,
+                         fetcher: &Fetcher)
+                         -> Vec<Result<Self, PathBuf>> {
+                let batch = Arc::new(batch);
+                let root = root.to_path_buf();
+                let resolved = fetcher.map(Arc::clone(&batch), move |(path, is_dir, new_filename)| {
+                                              Self::new(&root, path, *is_dir, new_filename.clone())
+                                      });
+                resolved.into_iter()
+                        .zip(batch.iter())
+                        .map(|(entry, (path, ..))| entry.ok_or_else(|| path.clone()))
+                        .collect()
+        }
+
+        /// Withdraws the new filename, recording why.
+        pub fn reject(&mut self, reason: &'static str) {
+                if let Some(new_filename) = self.new_filename.take() {
+                        self.rejected = Some(Rejection { new_filename, reason });
+                }
+                self.descendants = None;
+                self.relocated = None;
+        }
+
+        /// Order in which entries are renamed, a total order:
+        /// 1. deeper canonical paths first, so children go before their parents
+        /// 2. then canonical paths, compared component by component (bytewise)
+        /// 3. then walked paths, then roots, should two entries share a canonical path
+        pub fn execution_order(a: &Self, b: &Self) -> std::cmp::Ordering {
+                let depth = |entry: &Self| entry.canonical.components().count();
+                depth(b).cmp(&depth(a))
+                        .then_with(|| a.canonical.cmp(&b.canonical))
+                        .then_with(|| a.path.cmp(&b.path))
+                        .then_with(|| a.root.cmp(&b.root))
+        }
+
+        /// Current filename of the entry.
+        pub fn filename(&self) -> &str {
+                self.path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .expect("plan entries are only built from utf8 filenames")
+        }
+
+        /// Path the entry will be renamed to, if a replacement was provided.
+        pub fn destination(&self) -> Option<PathBuf> {
+                self.new_filename.as_ref().map(|name| self.path.with_file_name(name))
+        }
+
+        /// Absolute path of the entry, with its parent's symlinks resolved.
+        pub(crate) fn canonical(&self) -> &Path {
+                &self.canonical
+        }
+
+        /// Canonical destination: canonical parent joined with the new filename.
+        ///
+        /// Two entries from different roots that point at the same directory will agree on this value.
+        pub(crate) fn canonical_destination(&self) -> Option<PathBuf> {
+                self.new_filename.as_ref().map(|name| self.canonical.with_file_name(name))
+        }
+
+        /// Print a match `ls -l` style: type, size in bytes, modification time, path (relative to `root`, if given).
+        ///
+        /// Metadata is read (without following symlinks) at print time; unreadable fields print as `?`.
+        pub fn print_long(&self, out: &mut impl Write, root: Option<&PrintRoot>) -> io::Result<()> {
+                let metadata = fs::symlink_metadata(&self.path).ok();
+                let kind = match metadata.as_ref().map(fs::Metadata::file_type) {
+                        Some(t) if t.is_symlink() => "l",
+                        Some(t) if t.is_dir() => "d",
+                        Some(t) if t.is_file() => "f",
+                        _ => "?",
+                };
+                let size = metadata.as_ref().map_or_else(|| "?".to_string(), |m| m.len().to_string());
+                let modified =
+                        metadata.and_then(|m| m.modified().ok())
+                                .map_or_else(|| "?".to_string(),
+                                             |time| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M").to_string());
+                let path = PrintRoot::relative(root, &self.path);
+                writeln!(out, "{} {:>12} {:>16} {}", kind, size, modified.dimmed(), path.to_string_lossy())
+        }
+
+        /// Print the entry in the standard 'match', 'preview', or 'renaming' styles, with paths relative to `root`
+        /// (if given).
+        pub fn print(&self, out: &mut impl Write, is_test_run: bool, root: Option<&PrintRoot>) -> io::Result<()> {
+                let parent = self.path.parent().expect("all entries should have parents due to WalkDir min_depth=1");
+                let parent = PrintRoot::relative(root, parent);
+                if let Some(rejected) = &self.rejected {
+                        return writeln!(out,
+                                        "{}",
+                                        tr("rejected-mapping", &[("parent", &parent.to_string_lossy().blue()),
+                                                                 ("name",
+                                                                  &self.filename().black().bold().on_green()),
+                                                                 ("new", &rejected.new_filename.red().bold()),
+                                                                 ("reason", &rejected.reason.yellow())]));
+                }
+                let Some(new_filename) = &self.new_filename else {
+                        return writeln!(out,
+                                        "{}",
+                                        tr("match-found", &[("parent", &parent.to_string_lossy().blue()),
+                                                            ("name", &self.filename().black().bold().on_green())]));
+                };
+                let id = if is_test_run { "preview-mapping" } else { "renaming" };
+                let dir_note = match self.descendants {
+                        Some(count) => tr("dir-note", &[("count", &count)]),
+                        None => String::new(),
+                };
+                writeln!(out,
+                         "{}",
+                         tr(id, &[("parent", &parent.to_string_lossy().blue()),
+                                  ("name", &self.filename().black().bold().on_green()),
+                                  ("new", &new_filename.red().bold().on_blue()),
+                                  ("note", &dir_note.yellow())]))?;
+                for relocation in self.relocated.iter().flatten() {
+                        writeln!(out,
+                                 "    {} ~~> {}",
+                                 PrintRoot::relative(root, &relocation.from).to_string_lossy().dimmed(),
+                                 PrintRoot::relative(root, &relocation.to).to_string_lossy())?;
+                }
+                Ok(())
+        }
+}
+
+/// The only root of a run: printed paths under it are shown relative to it (`./sub/a.txt`).
+#[derive(Debug, Clone)]
+pub struct PrintRoot {
+        /// As given (walked paths start with it).
+        root:      PathBuf,
+        /// Canonical (descendants listed by `--list-descendants` start with it).
+        canonical: PathBuf,
+}
+
+impl PrintRoot {
+        pub fn new(root: &Path) -> Self {
+                Self { root:      root.to_path_buf(),
+                       canonical: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()), }
+        }
+
+        /// `path` as printed: relative to `root`, if given and `path` is under it.
+        fn relative<'a>(root: Option<&Self>, path: &'a Path) -> Cow<'a, Path> {
+                let Some(root) = root else { return Cow::Borrowed(path) };
+                match path.strip_prefix(&root.root).or_else(|_| path.strip_prefix(&root.canonical)) {
+                        Ok(relative) if relative.as_os_str().is_empty() => Cow::Borrowed(Path::new(".")),
+                        Ok(relative) => Cow::Owned(Path::new(".").join(relative)),
+                        Err(_) => Cow::Borrowed(path),
+                }
+        }
+}
+
+/// What the pattern is matched against (and the replacement applied to).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchSubject {
+        /// The entry's name.
+        #[default]
+        Name,
+        /// The name without its extension (`--stem-only`); the extension is re-appended to the new name.
+        Stem,
+        /// The path relative to the root, with the given separator (`--full-path`); only the last component is
+        /// renamed.
+        FullPath(PathSep),
+}
+
+/// Separator between the components of a `--full-path` subject (`--path-sep`).
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathSep {
+        /// `/`
+        #[default]
+        Slash,
+        /// `\`
+        Backslash,
+}
+
+impl PathSep {
+        pub fn as_str(self) -> &'static str {
+                match self {
+                        Self::Slash => "/",
+                        Self::Backslash => "\\",
+                }
+        }
+}
+
+impl MatchSubject {
+        /// The text matched for the entry at `path` under `root`.  A root itself (e.g. a file root) is its name.
+        /// `None` if not utf8.
+        pub fn of<'a>(&self, root: &Path, path: &'a Path) -> Option<Cow<'a, str>> {
+                let name = path.file_name()?.to_str()?;
+                match self {
+                        Self::Name => Some(Cow::Borrowed(name)),
+                        Self::Stem => Some(Cow::Borrowed(matcher::split_ext(name).0)),
+                        Self::FullPath(separator) => {
+                                let relative = match path.strip_prefix(root) {
+                                        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                                        _ => Path::new(name),
+                                };
+                                let components: Option<Vec<&str>> =
+                                        relative.components().map(|component| component.as_os_str().to_str()).collect();
+                                Some(Cow::Owned(components?.join(separator.as_str())))
+                        }
+                }
+        }
+
+        /// The new filename, given the transformed subject of an entry named `name`.
+        ///
+        /// For [`Self::FullPath`]: the last component, if the parent components are unchanged.  Otherwise all of
+        /// `new_subject` (with `/` separators), whose separators then get the entry rejected (see `validate.rs`).
+        pub fn new_filename(&self, name: &str, subject: &str, new_subject: String) -> String {
+                match self {
+                        Self::Name => new_subject,
+                        Self::Stem => new_subject + matcher::split_ext(name).1,
+                        Self::FullPath(separator) => {
+                                let separator = separator.as_str();
+                                let parent = subject.rsplit_once(separator).map_or("", |(parent, _)| parent);
+                                let new_name = match parent.is_empty() {
+                                        true => Some(new_subject.as_str()),
+                                        false => new_subject.strip_prefix(parent)
+                                                            .and_then(|rest| rest.strip_prefix(separator)),
+                                };
+                                match new_name {
+                                        Some(new_name) if !new_name.contains(separator) => new_name.to_string(),
+                                        _ => new_subject.replace(separator, "/"),
+                                }
+                        }
+                }
+        }
+}
+
+/// A new filename refused before renaming, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct Rejection {
+        pub new_filename: String,
+        pub reason:       &'static str,
+}
+
+/// Absolute path change of a descendant of a renamed directory.
+///
+/// `to` is where the descendant ends up once the whole plan has run: renamed directories between it and the renamed
+/// directory, and the descendant's own rename, are applied too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Relocation {
+        pub from: PathBuf,
+        pub to:   PathBuf,
+}
+
+/// A rename that cannot safely be carried out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Conflict {
+        /// Multiple entries would be renamed to the same destination.
+        SharedDestination { destination: PathBuf, sources: Vec<PathBuf> },
+        /// Destination already exists on disk (and is not the entry itself).
+        DestinationExists { source: PathBuf, destination: PathBuf },
+        /// Destination's parent is missing, not a directory, or not writable; the rename would fail at execute time.
+        UnusableParent { source: PathBuf, parent: PathBuf, reason: &'static str },
+}
+
+impl std::fmt::Display for Conflict {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                        Conflict::SharedDestination { destination, sources } => {
+                                write!(f,
+                                       "{} entries would be renamed to {:?}: {:?}",
+                                       sources.len(),
+                                       destination,
+                                       sources)
+                        }
+                        Conflict::DestinationExists { source, destination } => {
+                                write!(f, "{:?} would overwrite existing {:?}", source, destination)
+                        }
+                        Conflict::UnusableParent { source, parent, reason } => {
+                                write!(f, "{:?} cannot be renamed into {:?}: {}", source, parent, reason)
+                        }
+                }
+        }
+}
+
+/// Matched entries, across all roots.
+#[derive(Debug, Default)]
+pub struct Plan {
+        pub entries:     Vec<PlanEntry>,
+        /// Counters gathered while walking into the plan.
+        pub stats:       Stats,
+        /// With `--changed-only`: per-root state to record once the run has completed.
+        pub states:      Vec<RunState>,
+        /// Paths the walk failed at (e.g. permission denied), with the error; their subtrees were not searched.
+        pub unwalked:    Vec<(PathBuf, String)>,
+        /// With `--follow-symlinks`: each link that leads back to one of its ancestors, with that ancestor.
+        pub loops:       Vec<(PathBuf, PathBuf)>,
+        /// With `--entry-timeout`: entries given up on, left out of the plan.
+        pub timed_out:   Vec<PathBuf>,
+        /// With `--sanitize`: entries whose new name was altered, with the name before sanitizing.
+        pub sanitized:   Vec<(PathBuf, String)>,
+        /// Roots walked into the plan (none for `--files-from`).
+        pub roots:       Vec<PathBuf>,
+        /// With `--fast-preview`: conflicts, protected paths, and new names were not checked.
+        pub unvalidated: bool,
+        seen:            HashSet<PathBuf>,
+}
+
+/// Version of the replacement template rules (capture references, tokens, `--insert` positions).
+///
+/// Bump it whenever an existing template would produce a different name, e.g. a new `{token}` that used to be
+/// literal text.  Recorded in exported plans so consumers can tell which rules the new names came from.  (This tool
+/// never reads plans back, so it has no apply-time dialect check and no `--migrate-plan` yet.)
+///
+/// 1. Capture references, `{keep}`-style tokens, and `--insert` positions.
+/// 2. `\U`, `\L`, `\u`, `\l`, `\E` case escapes (before, a backslash was literal).
+/// 3. `{n}` and `{n:0W}` counter tokens.
+/// 4. `{now:FORMAT}` and `{mtime:FORMAT}` date/time tokens.
+/// 5. `{parent}`, `{parent.parent}`, ... directory name tokens.
+pub const TEMPLATE_DIALECT: u32 = 5;
+
+/// What produced a plan: recorded in its JSON form, so an exported plan can be checked against the running tool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanOrigin {
+        /// `rename_files` version that built the plan.
+        pub tool_version:     String,
+        /// [`TEMPLATE_DIALECT`] of that version.
+        pub template_dialect: u32,
+        /// Search regex.
+        pub pattern:          String,
+        /// How new names were built, if at all.
+        pub transform:        Option<TransformSpec>,
+}
+
+/// A [`Transform`], as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransformSpec {
+        /// Flag used: `rep` (or `rep-all`, `rep-nth`), `insert`, `rename-to`, or `map`.
+        pub kind:       String,
+        pub template:   String,
+        /// For `insert`: the `--at` position.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub at:         Option<String>,
+        /// For `rep-nth`: the `--occurrence`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub occurrence: Option<String>,
+}
+
+impl PlanOrigin {
+        /// Origin of a plan built by this version of the tool.
+        pub fn new(pattern: &str, transform: Option<&Transform>) -> Self {
+                let transform = transform.map(|transform| {
+                                                 let (kind, template) = transform.spec();
+                                                 let at = match transform {
+                                                         Transform::Insert { at, .. } => Some(at.to_string()),
+                                                         _ => None,
+                                                 };
+                                                 let occurrence = match transform {
+                                                         Transform::ReplaceNth { occurrence, .. } => {
+                                                                 Some(occurrence.to_string())
+                                                         }
+                                                         _ => None,
+                                                 };
+                                                 TransformSpec { kind: kind.to_string(),
+                                                                 template: template.to_string(),
+                                                                 at,
+                                                                 occurrence }
+                                         });
+                Self { tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                       template_dialect: TEMPLATE_DIALECT,
+                       pattern: pattern.to_string(),
+                       transform }
+        }
+}
+
+/// Machine readable form of a plan, as printed by `--json`.
+#[derive(Debug, Serialize)]
+struct PlanReport<'a> {
+        origin:            &'a PlanOrigin,
+        entries:           &'a [PlanEntry],
+        conflicts:         &'a [Conflict],
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        reference_updates: &'a [ReferenceUpdate],
+        total_matches:     usize,
+        /// `false` for a `--fast-preview` plan: `conflicts` is then empty because nothing was checked.
+        validated:         bool,
+}
+
+impl Plan {
+        /// Adds an entry to the plan.
+        ///
+        /// Returns `false` (and discards the entry) if the same underlying entry was already added via another root.
+        pub fn push(&mut self, entry: PlanEntry) -> bool {
+                if !self.seen.insert(entry.canonical.clone()) {
+                        tracing::debug!("Entry already planned via another root, skipping: {:?}", entry.path);
+                        return false;
+                }
+                self.entries.push(entry);
+                true
+        }
+
+        /// Orders entries for execution (see [`PlanEntry::execution_order`]), so that children are renamed before
+        /// their parents regardless of which root they were found under.
+        pub fn sort(&mut self) {
+                self.entries.sort_by(PlanEntry::execution_order);
+        }
+
+        /// Lists, for every directory rename, the descendants whose absolute paths will change, and their final paths.
+        pub fn list_descendants(&mut self) {
+                let renames: HashMap<PathBuf, PathBuf> =
+                        self.entries
+                            .iter()
+                            .filter_map(|entry| Some((entry.canonical.clone(), entry.canonical_destination()?)))
+                            .collect();
+                // Each component renamed by the plan (the entry itself, and any directory above it) takes its new name
+                let final_path = |path: &Path| -> PathBuf {
+                        let mut current = PathBuf::new();
+                        let mut renamed = PathBuf::new();
+                        for component in path.components() {
+                                current.push(component);
+                                match renames.get(&current).and_then(|destination| destination.file_name()) {
+                                        Some(name) => renamed.push(name),
+                                        None => renamed.push(component),
+                                }
+                        }
+                        renamed
+                };
+                for entry in &mut self.entries {
+                        if !entry.is_dir || entry.new_filename.is_none() {
+                                continue;
+                        }
+                        let relocated = WalkDir::new(&entry.canonical).min_depth(1)
+                                                                      .into_iter()
+                                                                      .filter_map(|d| d.ok())
+                                                                      .map(|descendant| {
+                                                                              let from = descendant.into_path();
+                                                                              Relocation { to: final_path(&from), from }
+                                                                      })
+                                                                      .collect();
+                        entry.relocated = Some(relocated);
+                }
+        }
+
+        /// Records, for every entry, why `matcher` selected it.
+        pub fn explain_matches(&mut self, matcher: &Matcher, subject: MatchSubject) {
+                for entry in &mut self.entries {
+                        entry.matched =
+                                subject.of(&entry.root, &entry.path).and_then(|subject| matcher.explain(&subject));
+                }
+        }
+
+        /// Number of planned entries found under each root, in root order of first appearance.
+        pub fn counts_by_root(&self) -> Vec<(&Path, u64)> {
+                let mut counts: Vec<(&Path, u64)> = Vec::new();
+                for entry in &self.entries {
+                        match counts.iter_mut().find(|(root, _)| *root == entry.root) {
+                                Some((_, count)) => *count += 1,
+                                None => counts.push((&entry.root, 1)),
+                        }
+                }
+                counts
+        }
+
+        /// Renders the plan, where it came from, its conflicts, and any reference updates as pretty-printed JSON.
+        pub fn to_json(&self,
+                       origin: &PlanOrigin,
+                       conflicts: &[Conflict],
+                       reference_updates: &[ReferenceUpdate])
+                       -> serde_json::Result<String> {
+                serde_json::to_string_pretty(&PlanReport { origin,
+                                                           entries: &self.entries,
+                                                           conflicts,
+                                                           reference_updates,
+                                                           total_matches: self.entries.len(),
+                                                           validated: !self.unvalidated })
+        }
+
+        /// Finds renames that would collide with each other or with existing paths,
+        /// or that would fail outright because of their destination's parent directory.
+        pub fn conflicts(&self) -> Vec<Conflict> {
+                self.conflicts_with(check_parent, |entry, destination| {
+                            destination.symlink_metadata().is_ok() && !is_same_entry(&entry.path, destination)
+                    })
+        }
+
+        /// As [`Self::conflicts`], with the filesystem checks supplied: why a destination parent is unusable (if it
+        /// is), and whether a destination is taken by something other than the entry itself.
+        pub(crate) fn conflicts_with(&self,
+                                     check_parent: impl Fn(&Path) -> Option<&'static str>,
+                                     is_taken: impl Fn(&PlanEntry, &Path) -> bool)
+                                     -> Vec<Conflict> {
+                let mut conflicts = Vec::new();
+                let mut parent_checks: HashMap<&Path, Option<&'static str>> = HashMap::new();
+                let mut by_destination: HashMap<PathBuf, Vec<&PlanEntry>> = HashMap::new();
+                for entry in &self.entries {
+                        let Some(destination) = entry.canonical_destination() else { continue };
+                        by_destination.entry(destination).or_default().push(entry);
+                }
+                for entry in &self.entries {
+                        let (Some(destination), Some(canonical_destination)) =
+                                (entry.destination(), entry.canonical_destination())
+                        else {
+                                continue;
+                        };
+                        let parent = entry.path
+                                          .parent()
+                                          .expect("all entries should have parents due to WalkDir min_depth=1");
+                        if let Some(reason) = *parent_checks.entry(parent).or_insert_with(|| check_parent(parent)) {
+                                conflicts.push(Conflict::UnusableParent { source: entry.path.clone(),
+                                                                          parent: parent.to_path_buf(),
+                                                                          reason });
+                                continue;
+                        }
+                        let sharers = &by_destination[&canonical_destination];
+                        if sharers.len() > 1 {
+                                // report each shared destination once, from its first source
+                                if std::ptr::eq(sharers[0], entry) {
+                                        conflicts.push(Conflict::SharedDestination { destination,
+                                                                                     sources:
+                                                                                             sharers.iter()
+                                                                                                    .map(|e| {
+                                                                                                            e.path
+                                                                                                             .clone()
+                                                                                                    })
+                                                                                                    .collect() });
+                                }
+                                continue;
+                        }
+                        if canonical_destination == entry.canonical {
+                                continue;
+                        }
+                        // an existing destination that is the entry itself (e.g. case-only rename on a
+                        // case-insensitive filesystem) is not a conflict
+                        if is_taken(entry, &destination) {
+                                conflicts.push(Conflict::DestinationExists { source: entry.path.clone(), destination });
+                        }
+                }
+                conflicts
+        }
+}
+
+/// Number of entries (at any depth) beneath a directory.
+fn count_descendants(dir: &Path) -> u64 {
+        WalkDir::new(dir).min_depth(1).into_iter().filter_map(|entry| entry.ok()).count() as u64
+}
+
+/// Stats a destination parent directory, returning why a rename into it would fail (if it would).
+fn check_parent(parent: &Path) -> Option<&'static str> {
+        match parent.metadata() {
+                Err(_) => Some("parent does not exist"),
+                Ok(metadata) if !metadata.is_dir() => Some("parent is not a directory"),
+                Ok(_) if !is_writable(parent) => Some("parent is not writable"),
+                Ok(_) => None,
+        }
+}
+
+/// Whether the current user may create entries in a directory.
+#[cfg(unix)]
+fn is_writable(dir: &Path) -> bool {
+        rustix::fs::access(dir, rustix::fs::Access::WRITE_OK).is_ok()
+}
+
+/// Whether the current user may create entries in a directory.  (Read-only attribute only.)
+#[cfg(not(unix))]
+fn is_writable(dir: &Path) -> bool {
+        dir.metadata().map(|metadata| !metadata.permissions().readonly()).unwrap_or(false)
+}
+
+/// Whether two paths refer to the same filesystem entry (without following a final symlink).
+#[cfg(unix)]
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        match (a.symlink_metadata(), b.symlink_metadata()) {
+                (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+                _ => false,
+        }
+}
+
+/// Whether two paths refer to the same filesystem entry.
+#[cfg(not(unix))]
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+        matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+        use std::time::Duration;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        /// Destinations whose parent has gone missing are reported rather than failing mid-run.
+        #[test]
+        fn test_conflicts_unusable_parent() {
+                let temp_dir = TempDir::new().unwrap();
+                let missing = temp_dir.path().join("missing");
+                let mut plan = Plan::default();
+                plan.push(PlanEntry::new(temp_dir.path(), &missing.join("file"), false, Some("new_file".to_string())));
+
+                assert_eq!(plan.conflicts(), vec![Conflict::UnusableParent { source: missing.join("file"),
+                                                                             parent: missing,
+                                                                             reason: "parent does not exist", }]);
+        }
+
+        /// Directory renames carry their descendant count; file renames and plain matches do not.
+        #[test]
+        fn test_directory_entries_count_descendants() {
+                let temp_dir = TempDir::new().unwrap();
+                let dir = temp_dir.path().join("dir");
+                std::fs::create_dir_all(dir.join("sub")).unwrap();
+                std::fs::File::create(dir.join("sub").join("file")).unwrap();
+
+                let renamed = PlanEntry::new(temp_dir.path(), &dir, true, Some("new_dir".to_string()));
+                let matched = PlanEntry::new(temp_dir.path(), &dir, true, None);
+                let file = PlanEntry::new(temp_dir.path(), &dir.join("sub").join("file"), false, Some("x".to_string()));
+                assert_eq!(renamed.descendants, Some(2));
+                assert_eq!(matched.descendants, None);
+                assert_eq!(file.descendants, None);
+        }
+
+        /// Descendants are listed at their final paths, with nested renames applied.
+        #[test]
+        fn test_list_descendants() {
+                let temp_dir = TempDir::new().unwrap();
+                let root = temp_dir.path().canonicalize().unwrap();
+                let (a, b) = (root.join("a_x"), root.join("a_x").join("b_x"));
+                std::fs::create_dir_all(&b).unwrap();
+                std::fs::File::create(b.join("f_x")).unwrap();
+                std::fs::File::create(b.join("g")).unwrap();
+
+                let mut plan = Plan::default();
+                for (path, is_dir, new_filename) in
+                        [(b.join("f_x"), false, "f_y"), (b.clone(), true, "b_y"), (a, true, "a_y")]
+                {
+                        plan.push(PlanEntry::new(&root, &path, is_dir, Some(new_filename.to_string())));
+                }
+                plan.list_descendants();
+                let relocated = |index: usize| -> Vec<(PathBuf, PathBuf)> {
+                        let mut relocated: Vec<_> = plan.entries[index].relocated
+                                                                       .iter()
+                                                                       .flatten()
+                                                                       .map(|r| (r.from.clone(), r.to.clone()))
+                                                                       .collect();
+                        relocated.sort();
+                        relocated
+                };
+                let b_final = root.join("a_y").join("b_y");
+                assert_eq!(relocated(2), vec![(b.clone(), b_final.clone()),
+                                              (b.join("f_x"), b_final.join("f_y")),
+                                              (b.join("g"), b_final.join("g"))]);
+                assert_eq!(relocated(1), relocated(2)[1..]);
+                assert_eq!(plan.entries[0].relocated, None);
+        }
+
+        /// With a timeout, entries that resolve in time are the same, in the same order.
+        #[test]
+        fn test_new_batch_with_timeout() {
+                let temp_dir = TempDir::new().unwrap();
+                let batch: Vec<_> =
+                        ["a", "b", "c"].map(|name| (temp_dir.path().join(name), false, Some(format!("{}2", name))))
+                                       .into();
+                let resolve = |timeout| -> Vec<_> {
+                        let resolved =
+                                PlanEntry::new_batch(temp_dir.path(), batch.clone(), &Fetcher { threads: 2, timeout });
+                        resolved.into_iter()
+                                .map(|entry| entry.map(|entry| (entry.canonical, entry.new_filename)))
+                                .collect()
+                };
+                assert_eq!(resolve(Some(Duration::from_secs(60))), resolve(None));
+        }
+
+        /// Execution order depends only on the entries, not on the order they were found in.
+        #[test]
+        fn test_sort_is_deterministic() {
+                let root = Path::new("root");
+                let paths = ["root/b", "root/a/z", "root/a", "root/B", "root/a/y", "root/c"];
+                let sorted = |paths: &mut dyn Iterator<Item=&&str>| {
+                        let mut plan = Plan::default();
+                        for path in paths {
+                                plan.push(PlanEntry::new_unresolved(root, Path::new(path), false, None));
+                        }
+                        plan.sort();
+                        plan.entries.into_iter().map(|entry| entry.path).collect::<Vec<_>>()
+                };
+                let forward = sorted(&mut paths.iter());
+                assert_eq!(forward,
+                           ["root/a/y", "root/a/z", "root/B", "root/a", "root/b", "root/c"].map(PathBuf::from));
+                assert_eq!(sorted(&mut paths.iter().rev()), forward);
+        }
+
+        /// Long listing shows type and size.
+        #[test]
+        fn test_print_long() {
+                let temp_dir = TempDir::new().unwrap();
+                let file = temp_dir.path().join("file");
+                std::fs::write(&file, "12345").unwrap();
+                let mut out = Vec::new();
+                PlanEntry::new(temp_dir.path(), &file, false, None).print_long(&mut out, None).unwrap();
+                let line = String::from_utf8(out).unwrap();
+                assert!(line.starts_with("f            5 "), "{}", line);
+                assert!(line.trim_end().ends_with(&*file.to_string_lossy()), "{}", line);
+        }
+
+        /// JSON plans record the pattern, transform, version, and template dialect they were built with.
+        #[test]
+        fn test_json_origin() {
+                let transform = Transform::Insert { text: "_x".to_string(), at: crate::transform::InsertAt::Start };
+                let origin = PlanOrigin::new("^IMG", Some(&transform));
+                let json: serde_json::Value =
+                        serde_json::from_str(&Plan::default().to_json(&origin, &[], &[]).unwrap()).unwrap();
+                assert_eq!(json["origin"]["pattern"], "^IMG");
+                assert_eq!(json["origin"]["template_dialect"], TEMPLATE_DIALECT);
+                assert_eq!(json["origin"]["tool_version"], env!("CARGO_PKG_VERSION"));
+                assert_eq!(json["origin"]["transform"],
+                           serde_json::json!({ "kind": "insert", "template": "_x", "at": "start" }));
+        }
+}
@@ -0,0 +1,52 @@
+//! `--nice`: run at low CPU and IO priority, so background bulk renames don't make a desktop sluggish.
+//!
+//! Best effort, per platform:
+//! - Linux: raises the nice value.  IO priority follows it (no explicit IO class is set, so the kernel derives one
+//!   from the nice value)
+//! - macOS: raises the nice value, then marks the process as background (`taskpolicy -b`), which throttles its IO
+//! - other Unix: raises the nice value
+//! - Windows: sets the `Idle` priority class (via PowerShell)
+
+use crate::error::Result;
+
+/// Added to the nice value on Unix (as `nice` does by default).
+#[cfg(unix)]
+const NICE_INCREMENT: i32 = 10;
+
+/// Lowers this process's CPU and IO priority.
+#[cfg(unix)]
+pub fn lower() -> Result<()> {
+        let nice = rustix::process::nice(NICE_INCREMENT)?;
+        tracing::debug!("Nice value now {}", nice);
+        #[cfg(target_os = "macos")]
+        run_quietly(std::process::Command::new("taskpolicy").args(["-b", "-p", &std::process::id().to_string()]))?;
+        Ok(())
+}
+
+/// Lowers this process's CPU and IO priority.
+#[cfg(windows)]
+pub fn lower() -> Result<()> {
+        let script = format!("(Get-Process -Id {}).PriorityClass = 'Idle'", std::process::id());
+        run_quietly(std::process::Command::new("powershell.exe").args(["-NoProfile",
+                                                                       "-NonInteractive",
+                                                                       "-Command",
+                                                                       &script]))
+}
+
+/// Lowers this process's CPU and IO priority.  (Unsupported on this platform.)
+#[cfg(not(any(unix, windows)))]
+pub fn lower() -> Result<()> {
+        Err("--nice is not supported on this platform".into())
+}
+
+/// Runs a helper command to completion, failing if it cannot run or reports failure.
+#[cfg(any(target_os = "macos", windows))]
+fn run_quietly(command: &mut std::process::Command) -> Result<()> {
+        let output = command.output().map_err(|e| format!("Could not run {:?}: {}", command.get_program(), e))?;
+        match output.status.success() {
+                true => Ok(()),
+                false => Err(format!("{:?} failed: {}",
+                                     command.get_program(),
+                                     String::from_utf8_lossy(&output.stderr).trim()).into()),
+        }
+}
@@ -0,0 +1,71 @@
+//! Protected paths: locations that are never used as a root, nor renamed, without `--allow-protected`.
+//!
+//! Guards against catastrophic invocations (e.g. a recursive rename from `~` or `/etc`).
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Config};
+
+/// System locations protected on every platform they exist on.
+const BUILT_IN: &[&str] = &["/",
+                            "/bin",
+                            "/boot",
+                            "/dev",
+                            "/etc",
+                            "/lib",
+                            "/proc",
+                            "/sbin",
+                            "/sys",
+                            "/usr",
+                            "/var",
+                            "/Applications",
+                            "/Library",
+                            "/System",
+                            "/Users",
+                            "C:\\",
+                            "C:\\Program Files",
+                            "C:\\Users",
+                            "C:\\Windows"];
+
+/// The set of protected paths, canonicalized where possible.
+#[derive(Debug, Clone)]
+pub struct ProtectedPaths {
+        paths: Vec<PathBuf>,
+}
+
+impl ProtectedPaths {
+        /// Built-in system paths, the home directory, filesystem mount points, and any paths from config.
+        pub fn new(config: &Config) -> Self {
+                let mut paths: Vec<PathBuf> = BUILT_IN.iter().map(PathBuf::from).collect();
+                paths.extend(config::home_dir());
+                paths.extend(mount_points());
+                paths.extend(config.protected_paths.iter().map(|path| config::expand_tilde(path)));
+                let mut paths: Vec<PathBuf> =
+                        paths.into_iter().map(|path| path.canonicalize().unwrap_or(path)).collect();
+                paths.sort();
+                paths.dedup();
+                Self { paths }
+        }
+
+        /// Whether the path is itself protected.  (Paths *inside* a protected path are not.)
+        pub fn contains(&self, path: &Path) -> bool {
+                let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                self.paths.contains(&path)
+        }
+}
+
+/// Mount points of currently mounted filesystems.
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+        let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else { return Vec::new() };
+        mounts.lines()
+              .filter_map(|line| line.split_whitespace().nth(1))
+              .map(|mount_point| PathBuf::from(mount_point.replace("\\040", " ")))
+              .collect()
+}
+
+/// Mount points of currently mounted filesystems.  (Not detected on this platform.)
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+        Vec::new()
+}
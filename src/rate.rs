@@ -0,0 +1,85 @@
+//! `--rate`: throttling of rename syscalls, so bulk renames on shared network storage (NFS, SMB) leave room for
+//! other clients.
+//!
+//! The limiter hands out evenly spaced time slots (no bursts).  It is `Sync`: one limiter can be shared by every
+//! thread issuing renames, and the rate holds for all of them together.
+
+use std::{str::FromStr,
+          sync::Mutex,
+          time::{Duration, Instant}};
+
+/// Operations per time unit, as given on the command line: `N/s`, `N/m`, `N/h`, or a bare `N` (per second).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+        count: f64,
+        per:   Duration,
+}
+
+impl FromStr for Rate {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let (count, unit) = s.split_once('/').unwrap_or((s, "s"));
+                let per = match unit {
+                        "s" => Duration::from_secs(1),
+                        "m" => Duration::from_secs(60),
+                        "h" => Duration::from_secs(3600),
+                        _ => return Err(format!("unknown time unit {:?} (expected s, m, or h)", unit)),
+                };
+                match count.parse::<f64>() {
+                        Ok(count) if count > 0.0 && count.is_finite() => Ok(Self { count, per }),
+                        _ => Err(format!("expected a positive number of operations, e.g. 50/s; got {:?}", s)),
+                }
+        }
+}
+
+/// Spaces operations at most [`Rate`] apart, across all threads sharing it.
+#[derive(Debug)]
+pub struct RateLimiter {
+        interval:  Duration,
+        next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+        pub fn new(rate: Rate) -> Self {
+                Self { interval: rate.per.div_f64(rate.count), next_slot: Mutex::new(None) }
+        }
+
+        /// Blocks until the caller's turn.  The first call never waits.
+        pub fn wait(&self) {
+                let slot = {
+                        let mut next_slot = self.next_slot.lock().expect("limiter lock is never poisoned");
+                        let now = Instant::now();
+                        let slot = next_slot.map_or(now, |next| next.max(now));
+                        *next_slot = Some(slot + self.interval);
+                        slot
+                };
+                // Sleep outside the lock, so other threads can claim later slots meanwhile
+                std::thread::sleep(slot.saturating_duration_since(Instant::now()));
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_rate_limiter() {
+                assert_eq!("2/m".parse::<Rate>(), Ok(Rate { count: 2.0, per: Duration::from_secs(60) }));
+                assert_eq!("5".parse::<Rate>(), Ok(Rate { count: 5.0, per: Duration::from_secs(1) }));
+                assert!("0/s".parse::<Rate>().is_err());
+                assert!("5/d".parse::<Rate>().is_err());
+
+                let limiter = RateLimiter::new("200/s".parse().unwrap());
+                let start = Instant::now();
+                std::thread::scope(|scope| {
+                        for _ in 0..3 {
+                                scope.spawn(|| (0..3).for_each(|_| limiter.wait()));
+                        }
+                });
+                // 9 calls, 5ms apart: the last starts at least 40ms after the first
+                assert!(start.elapsed() >= Duration::from_millis(40), "{:?}", start.elapsed());
+        }
+}
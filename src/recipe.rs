@@ -0,0 +1,342 @@
+//! Recipes: named, reusable pattern + replacement pairs (and entry filters), selected with `--recipe NAME`.
+//!
+//! Built-in recipes are always available.  User recipes are discovered at startup from
+//! `$XDG_CONFIG_HOME/rename_files/recipes/` (or `~/.config/rename_files/recipes/`): one `<name>.toml` file per
+//! recipe, so recipes can be shared as single files.  A user recipe replaces a built-in of the same name.
+//!
+//! # Example (`recipes/shots.toml`):
+//! ```toml
+//! description = "Camera images to shot_<n>"
+//! pattern = '^IMG_(\d+)'
+//! replacement = 'shot_${1}'
+//! recurse = true
+//! example = "IMG_0042.jpg"
+//!
+//! # optional; as the flags of the same names
+//! [filters]
+//! ext = ["jpg", "jpeg"]
+//! exclude = ['(^|/)thumbnails$']
+//! files_only = true
+//! larger_than = "100k"
+//! ```
+//!
+//! A recipe's filters are added to those given on the command line; a size given there takes precedence.
+//!
+//! A project's `.rename_files.toml` can also define recipes, as `[recipe.<name>]` tables (see `config.rs` docs).
+//!
+//! `rename_files recipe show <name>` prints exactly what a recipe does.
+
+use std::{collections::BTreeMap,
+          io::Write,
+          path::{Path, PathBuf}};
+
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+
+use crate::{config, error::Result, filters::Size, matcher::Matcher, output::Output};
+
+/// A named pattern + replacement.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+        /// What the recipe does.
+        pub description: String,
+        /// Regex to search filenames with.
+        pub pattern:     String,
+        /// Replacement; without one the recipe only finds.
+        #[serde(default)]
+        pub replacement: Option<String>,
+        /// Recurse into child directories (as `--recurse`).
+        #[serde(default)]
+        pub recurse:     bool,
+        /// A filename the recipe applies to, shown with its transformation by `recipe show`.
+        #[serde(default)]
+        pub example:     Option<String>,
+        /// Which entries the recipe considers (`[filters]` table).
+        #[serde(default)]
+        pub filters:     RecipeFilters,
+        /// File the recipe was read from.  `None` for built-ins.
+        #[serde(skip)]
+        pub source:      Option<PathBuf>,
+}
+
+/// Entry filters of a recipe, as the flags of the same names.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecipeFilters {
+        pub ext:          Vec<String>,
+        /// Regexes, as `--exclude`.
+        pub exclude:      Vec<String>,
+        pub files_only:   bool,
+        pub dirs_only:    bool,
+        pub hidden:       bool,
+        pub larger_than:  Option<Size>,
+        pub smaller_than: Option<Size>,
+}
+
+impl RecipeFilters {
+        /// Each filter that is set, as its flag with its value, e.g. `--ext jpg,jpeg`.
+        pub fn describe(&self) -> Vec<String> {
+                let mut described = Vec::new();
+                if !self.ext.is_empty() {
+                        described.push(format!("--ext {}", self.ext.join(",")));
+                }
+                described.extend(self.exclude.iter().map(|pattern| format!("--exclude '{}'", pattern)));
+                let switches =
+                        [("--files-only", self.files_only), ("--dirs-only", self.dirs_only), ("--hidden", self.hidden)];
+                described.extend(switches.iter().filter(|(_, is_set)| *is_set).map(|(flag, _)| flag.to_string()));
+                described.extend(self.larger_than.map(|size| format!("--larger-than {}", size.0)));
+                described.extend(self.smaller_than.map(|size| format!("--smaller-than {}", size.0)));
+                described
+        }
+}
+
+/// Recipes shipped with the binary: (name, description, pattern, replacement, example).
+const BUILT_IN: &[(&str, &str, &str, &str, &str)] = &[("strip-copy-suffix",
+                                                       "Remove duplicate-download suffixes like ` (1)` and ` (copy)`",
+                                                       r"^(.+?) ?\((?:\d+|copy)\)(\.[^.]+)?$",
+                                                       "${1}${2}",
+                                                       "report (1).pdf"),
+                                                      ("trim-before-ext",
+                                                       "Remove whitespace before the extension",
+                                                       r"^(.*?)\s+(\.[^.]+)$",
+                                                       "${1}${2}",
+                                                       "notes .txt"),
+                                                      ("screenshot-date",
+                                                       "macOS screenshots to sortable names",
+                                                       r"^Screenshot (\d{4}-\d{2}-\d{2}) at (\d{2})\.(\d{2})\.(\d{2})",
+                                                       "screenshot_${1}_${2}${3}${4}",
+                                                       "Screenshot 2024-05-01 at 09.15.02.png")];
+
+/// All available recipes, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Recipes {
+        recipes: BTreeMap<String, Recipe>,
+}
+
+impl Recipes {
+        /// Built-in recipes plus those in the user recipe directory.
+        pub fn discover() -> Result<Self> {
+                let mut recipes = Self::built_in();
+                if let Some(dir) = recipe_dir().filter(|dir| dir.is_dir()) {
+                        recipes.load_dir(&dir)?;
+                }
+                Ok(recipes)
+        }
+
+        /// Only the built-in recipes.
+        pub fn built_in() -> Self {
+                let recipes = BUILT_IN.iter()
+                                      .map(|(name, description, pattern, replacement, example)| {
+                                              (name.to_string(),
+                                               Recipe { description: description.to_string(),
+                                                        pattern:     pattern.to_string(),
+                                                        replacement: Some(replacement.to_string()),
+                                                        recurse:     false,
+                                                        example:     Some(example.to_string()),
+                                                        filters:     RecipeFilters::default(),
+                                                        source:      None, })
+                                      })
+                                      .collect();
+                Self { recipes }
+        }
+
+        /// Adds every `*.toml` recipe in `dir`.  Invalid recipe files are errors.
+        pub fn load_dir(&mut self, dir: &Path) -> Result<()> {
+                let mut paths: Vec<PathBuf> =
+                        std::fs::read_dir(dir)?.filter_map(|entry| entry.ok())
+                                               .map(|entry| entry.path())
+                                               .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                                               .collect();
+                paths.sort();
+                for path in paths {
+                        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+                        let text = std::fs::read_to_string(&path)?;
+                        let mut recipe: Recipe =
+                                toml::from_str(&text).map_err(|e| format!("Invalid recipe {:?}: {}", path, e))?;
+                        recipe.validate().map_err(|e| format!("Invalid recipe {:?}: {}", path, e))?;
+                        if self.recipes.contains_key(name) {
+                                tracing::debug!("Recipe {:?} from {:?} replaces an earlier definition", name, path);
+                        }
+                        recipe.source = Some(path.clone());
+                        self.recipes.insert(name.to_string(), recipe);
+                }
+                Ok(())
+        }
+
+        /// The named recipe.  Errors (listing the available names) if there is none.
+        pub fn get(&self, name: &str) -> Result<&Recipe> {
+                self.recipes.get(name).ok_or_else(|| {
+                                              let names: Vec<&str> = self.recipes.keys().map(String::as_str).collect();
+                                              let available = names.join(", ");
+                                              format!("Unknown recipe {:?} (available: {})", name, available).into()
+                                      })
+        }
+
+        /// Adds recipes from project config files, unless a recipe of the same name is already defined.
+        pub fn add_project(&mut self, recipes: &BTreeMap<String, Recipe>) {
+                for (name, recipe) in recipes {
+                        self.recipes.entry(name.clone()).or_insert_with(|| recipe.clone());
+                }
+        }
+
+        /// All recipes, sorted by name.
+        pub fn iter(&self) -> impl Iterator<Item=(&String, &Recipe)> {
+                self.recipes.iter()
+        }
+}
+
+impl Recipe {
+        /// Checks that the pattern and `exclude` filters are valid regexes.
+        pub fn validate(&self) -> Result<()> {
+                for pattern in std::iter::once(&self.pattern).chain(&self.filters.exclude) {
+                        regex::Regex::new(pattern)?;
+                }
+                Ok(())
+        }
+
+        /// Prints the exact pattern, replacement, options, filters, origin, and the example's transformation.
+        pub fn show(&self, out: &mut impl Write, name: &str) -> Result<()> {
+                writeln!(out, "{}: {}", name.cyan().bold(), self.description)?;
+                writeln!(out, "  pattern:     {}", self.pattern)?;
+                writeln!(out, "  replacement: {}", self.replacement.as_deref().unwrap_or("(none: find only)"))?;
+                writeln!(out, "  recurse:     {}", self.recurse)?;
+                let filters = self.filters.describe();
+                match filters.is_empty() {
+                        true => writeln!(out, "  filters:     none")?,
+                        false => writeln!(out, "  filters:     {}", filters.join(" "))?,
+                }
+                match &self.source {
+                        Some(path) => writeln!(out, "  source:      {}", path.to_string_lossy())?,
+                        None => writeln!(out, "  source:      built-in")?,
+                }
+                let Some(example) = &self.example else {
+                        writeln!(out, "  example:     (none given)")?;
+                        return Ok(());
+                };
+                let matcher = Matcher::new(&self.pattern)?;
+                let result = match &self.replacement {
+                        Some(rep) => matcher.replace(example, rep, &mut String::new()),
+                        None => matcher.regex().is_match(example).then(|| example.clone()),
+                };
+                match result {
+                        Some(new_name) => writeln!(out, "  example:     {} ~~> {}", example, new_name.green())?,
+                        None => writeln!(out, "  example:     {} {}", example, "(does not match!)".red())?,
+                }
+                Ok(())
+        }
+}
+
+/// `recipe show`: one recipe in full.  `project` recipes are included (see [`Recipes::add_project`]).
+#[tracing::instrument(skip(project))]
+pub fn show(name: &str, project: &BTreeMap<String, Recipe>) -> Result<()> {
+        let mut out = Output::stdout();
+        let mut recipes = Recipes::discover()?;
+        recipes.add_project(project);
+        recipes.get(name)?.show(&mut out, name)?;
+        out.flush()?;
+        Ok(())
+}
+
+/// `recipe list`: every recipe's name and description.  `project` recipes are included.
+#[tracing::instrument(skip(project))]
+pub fn list(project: &BTreeMap<String, Recipe>) -> Result<()> {
+        let mut out = Output::stdout();
+        let mut recipes = Recipes::discover()?;
+        recipes.add_project(project);
+        for (name, recipe) in recipes.iter() {
+                writeln!(out, "{:<20} {}", name.cyan(), recipe.description)?;
+        }
+        out.flush()?;
+        Ok(())
+}
+
+/// Directory user recipes are discovered in.
+pub fn recipe_dir() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("recipes"))
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        /// Built-ins compile and do what their descriptions say.
+        #[test]
+        fn test_built_in() {
+                let recipes = Recipes::built_in();
+                let test_cases = vec![("strip-copy-suffix", "report (1).pdf", "report.pdf"),
+                                      ("trim-before-ext", "notes .txt", "notes.txt"),
+                                      ("screenshot-date",
+                                       "Screenshot 2024-05-01 at 09.15.02.png",
+                                       "screenshot_2024-05-01_091502.png")];
+                for (name, from, to) in test_cases {
+                        let recipe = recipes.get(name).unwrap();
+                        let re = regex::Regex::new(&recipe.pattern).unwrap();
+                        assert_eq!(re.replace(from, recipe.replacement.as_deref().unwrap()), to, "recipe: {}", name);
+                }
+                assert!(recipes.get("nope").is_err());
+        }
+
+        #[test]
+        fn test_show() -> Result<()> {
+                let recipes = Recipes::built_in();
+                let mut out = Vec::new();
+                recipes.get("trim-before-ext")?.show(&mut out, "trim-before-ext")?;
+                let text = String::from_utf8(out)?;
+                assert!(text.contains(r"^(.*?)\s+(\.[^.]+)$"), "{}", text);
+                assert!(text.contains("built-in"), "{}", text);
+                assert!(text.contains("filters:     none"), "{}", text);
+                assert!(text.contains("notes .txt ~~> ") && text.contains("notes.txt"), "{}", text);
+                Ok(())
+        }
+
+        #[test]
+        fn test_load_dir() -> Result<()> {
+                let temp_dir = tempfile::tempdir()?;
+                std::fs::write(temp_dir.path().join("shots.toml"),
+                               "description = \"x\"\npattern = '^IMG_(\\d+)'\nreplacement = 'shot_${1}'\n\
+                                recurse = true\n")?;
+                std::fs::write(temp_dir.path().join("README.md"), "not a recipe")?;
+                let mut recipes = Recipes::built_in();
+                recipes.load_dir(temp_dir.path())?;
+                let shots = recipes.get("shots")?;
+                assert!(shots.recurse);
+                assert_eq!(shots.source.as_deref(), Some(temp_dir.path().join("shots.toml").as_path()));
+
+                std::fs::write(temp_dir.path().join("bad.toml"), "description = \"x\"\npattern = '('\n")?;
+                assert!(Recipes::built_in().load_dir(temp_dir.path()).is_err());
+                Ok(())
+        }
+
+        /// A `[filters]` table is read as the flags of the same names; invalid filters are errors.
+        #[test]
+        fn test_load_dir_filters() -> Result<()> {
+                let temp_dir = tempfile::tempdir()?;
+                let recipe = "description = \"x\"\npattern = 'x'\n[filters]\next = [\"jpg\"]\nexclude = ['^thumbs$']\n\
+                              files_only = true\nlarger_than = \"4k\"\n";
+                std::fs::write(temp_dir.path().join("photos.toml"), recipe)?;
+                let mut recipes = Recipes::built_in();
+                recipes.load_dir(temp_dir.path())?;
+                let filters = &recipes.get("photos")?.filters;
+                assert_eq!(filters.ext, vec!["jpg"]);
+                assert_eq!(filters.exclude, vec!["^thumbs$"]);
+                assert!(filters.files_only && !filters.dirs_only);
+                assert_eq!(filters.larger_than, Some(Size(4096)));
+                assert_eq!(recipes.get("trim-before-ext")?.filters.ext, Vec::<String>::new());
+                let mut out = Vec::new();
+                recipes.get("photos")?.show(&mut out, "photos")?;
+                let text = String::from_utf8(out)?;
+                assert!(text.contains("filters:     --ext jpg --exclude '^thumbs$' --files-only --larger-than 4096"),
+                        "{}",
+                        text);
+
+                for bad in ["exclude = ['(']", "larger_than = \"4q\"", "older_than = \"1d\""] {
+                        std::fs::write(temp_dir.path().join("photos.toml"),
+                                       format!("description = \"x\"\npattern = 'x'\n[filters]\n{}\n", bad))?;
+                        assert!(Recipes::built_in().load_dir(temp_dir.path()).is_err(), "{}", bad);
+                }
+                Ok(())
+        }
+}
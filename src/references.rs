@@ -0,0 +1,166 @@
+//! Path references inside project files (Makefiles, configs, docs, ...).
+//!
+//! Renaming a directory silently breaks any file that mentions its path.  This module finds such files.
+
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{error::Result, plan::PlanEntry};
+
+/// Rewritten contents for a file whose path references are being updated.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceUpdate {
+        pub file:         PathBuf,
+        /// Number of path mentions rewritten.
+        pub replacements: usize,
+        #[serde(skip)]
+        pub contents:     String,
+}
+
+impl ReferenceUpdate {
+        /// Writes the rewritten contents back to the file.
+        pub fn apply(&self) -> std::io::Result<()> {
+                std::fs::write(&self.file, &self.contents)
+        }
+}
+
+/// Text files to check for path references, selected by glob (relative to the working directory).
+#[derive(Debug, Default)]
+pub struct ReferenceFiles {
+        files: Vec<(PathBuf, String)>,
+}
+
+impl ReferenceFiles {
+        /// Walks the working directory, reading every file matching any of the globs.
+        ///
+        /// Files that cannot be read are skipped with a warning.  Non-utf8 content is read lossily.
+        pub fn collect(globs: &[String]) -> Result<Self> {
+                let set = build_globset(globs)?;
+                let mut files = Vec::new();
+                for entry in WalkDir::new(".").min_depth(1).into_iter().filter_map(|entry| entry.ok()) {
+                        let relative = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                        if !entry.file_type().is_file() || !set.is_match(relative) {
+                                continue;
+                        }
+                        match std::fs::read(entry.path()) {
+                                Ok(bytes) => files.push((relative.to_path_buf(),
+                                                         String::from_utf8_lossy(&bytes).into_owned())),
+                                Err(e) => tracing::warn!("Could not read reference file {:?}: {}", entry.path(), e),
+                        }
+                }
+                tracing::debug!("{} reference files collected", files.len());
+                Ok(Self { files })
+        }
+
+        /// First file mentioning the path, either as written relative to the working directory or in absolute form.
+        pub fn find_reference(&self, path: &Path) -> Option<&Path> {
+                let needles = reference_needles(path);
+                self.files
+                    .iter()
+                    .find(|(_, contents)| needles.iter().any(|needle| contents.contains(needle.as_str())))
+                    .map(|(file, _)| file.as_path())
+        }
+
+        /// Rewrites, in every file, mentions of each renamed entry's old path to its new path.
+        ///
+        /// Entries must be in plan (deepest-first) order: a child's mention is rewritten before its parent's, so
+        /// a path through several renamed components ends up fully updated.  Only files with changes are returned.
+        pub fn updates(&self, entries: &[PlanEntry]) -> Vec<ReferenceUpdate> {
+                let pairs: Vec<(String, String)> = entries.iter().flat_map(rename_needles).collect();
+                let mut updates = Vec::new();
+                for (file, contents) in &self.files {
+                        let mut contents = contents.clone();
+                        let mut replacements = 0;
+                        for (old, new) in &pairs {
+                                let (rewritten, count) = replace_path_mentions(&contents, old, new);
+                                contents = rewritten;
+                                replacements += count;
+                        }
+                        if replacements > 0 {
+                                updates.push(ReferenceUpdate { file: file.clone(), replacements, contents });
+                        }
+                }
+                updates
+        }
+}
+
+/// (old, new) path strings for a rename, in working-directory relative and absolute forms.
+fn rename_needles(entry: &PlanEntry) -> Vec<(String, String)> {
+        let (Some(destination), Some(absolute_destination)) = (entry.destination(), entry.canonical_destination())
+        else {
+                return Vec::new();
+        };
+        let relative = |path: &Path| path.strip_prefix(".").unwrap_or(path).to_string_lossy().into_owned();
+        vec![(relative(&entry.path), relative(&destination)),
+             (entry.canonical().to_string_lossy().into_owned(), absolute_destination.to_string_lossy().into_owned())]
+}
+
+/// Replaces mentions of a path that stand alone as a path component sequence.
+///
+/// The mention must not be glued to other filename characters: `dir_1` matches in `dir_1/file` and `"dir_1"`
+/// but not in `dir_11` or `my-dir_1`.  Returns the new text and the number of replacements made.
+fn replace_path_mentions(text: &str, old: &str, new: &str) -> (String, usize) {
+        if old.is_empty() {
+                return (text.to_string(), 0);
+        }
+        let is_name_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+        let mut out = String::with_capacity(text.len());
+        let mut count = 0;
+        let mut last = 0;
+        for (start, _) in text.match_indices(old) {
+                let end = start + old.len();
+                let glued_before = text[..start].chars().next_back().is_some_and(is_name_char);
+                let glued_after = text[end..].chars().next().is_some_and(is_name_char);
+                if glued_before || glued_after {
+                        continue;
+                }
+                out.push_str(&text[last..start]);
+                out.push_str(new);
+                last = end;
+                count += 1;
+        }
+        out.push_str(&text[last..]);
+        (out, count)
+}
+
+/// Strings a file could use to refer to a path: its working-directory relative form and its absolute form.
+fn reference_needles(path: &Path) -> Vec<String> {
+        let mut needles = Vec::new();
+        let relative = path.strip_prefix(".").unwrap_or(path);
+        needles.push(relative.to_string_lossy().into_owned());
+        if let Ok(absolute) = path.canonicalize() {
+                needles.push(absolute.to_string_lossy().into_owned());
+        }
+        needles.retain(|needle| !needle.is_empty());
+        needles
+}
+
+/// Combine globs into a single matcher.
+fn build_globset(globs: &[String]) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+                builder.add(Glob::new(glob)?);
+        }
+        Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_replace_path_mentions() {
+                let test_cases = vec![("ls dir_1/file", "ls changed-dir_1/file", 1),
+                                      ("\"dir_1\" and dir_1", "\"changed-dir_1\" and changed-dir_1", 2),
+                                      ("dir_11 my-dir_1 dir_1.bak", "dir_11 my-dir_1 dir_1.bak", 0)];
+                for (input, expected, expected_count) in test_cases {
+                        assert_eq!(replace_path_mentions(input, "dir_1", "changed-dir_1"),
+                                   (expected.to_string(), expected_count));
+                }
+        }
+}
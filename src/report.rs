@@ -0,0 +1,301 @@
+//! Run events, and where they go.
+//!
+//! Library code paths do not print: they report what they planned and did to a [`Reporter`].  The CLI installs the
+//! reporter of the [`OutputStyle`] its flags select, on stdout; embedders can install [`SilentReporter`], or their
+//! own reporter to collect events.
+
+use std::{collections::BTreeMap,
+          io::{self, Write},
+          path::Path};
+
+use owo_colors::OwoColorize;
+
+use crate::{i18n::tr,
+            plan::{Conflict, Plan, PlanEntry, PlanOrigin, PrintRoot},
+            references::ReferenceUpdate,
+            stats::Stats};
+
+/// Receives the events of a run, in order.  Every event defaults to doing nothing.
+pub trait Reporter {
+        /// The plan is complete, about to be carried out (or previewed).
+        fn plan(&mut self,
+                _plan: &Plan,
+                _conflicts: &[Conflict],
+                _reference_updates: &[ReferenceUpdate],
+                _origin: &PlanOrigin)
+                -> io::Result<()> {
+                Ok(())
+        }
+
+        /// A reference file is about to be rewritten (or, if `is_test_run`, would be).
+        fn reference_update(&mut self, _update: &ReferenceUpdate, _is_test_run: bool) -> io::Result<()> {
+                Ok(())
+        }
+
+        /// An entry is about to be renamed (or, if `is_test_run` or it has no new name, only listed).
+        fn entry(&mut self, _entry: &PlanEntry, _is_test_run: bool) -> io::Result<()> {
+                Ok(())
+        }
+
+        /// Every entry has been handled.
+        fn finished(&mut self, _plan: &Plan) -> io::Result<()> {
+                Ok(())
+        }
+
+        /// With `--count`: the plan is complete, and nothing else will be reported.
+        fn counts(&mut self, _plan: &Plan, _per_dir: bool) -> io::Result<()> {
+                Ok(())
+        }
+
+        /// With `--stats`: counters of the run.
+        fn stats(&mut self, _stats: &Stats) -> io::Result<()> {
+                Ok(())
+        }
+}
+
+/// Reports nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {}
+
+/// How the CLI reports a run, from the output flags.  Each style is a [`Reporter`]; adding a style is adding a variant
+/// and its reporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStyle {
+        /// A line per entry (`ls -l` style if `long`), then a summary.
+        Human { long: bool },
+        /// The plan as JSON (`--json`).
+        Json,
+        /// Only what was explicitly asked for: `--count` and `--stats` (`--quiet`).
+        Quiet,
+        /// A single progress line, updated in place, then a summary (`--tui`).
+        Tui,
+}
+
+impl OutputStyle {
+        /// Reporter of this style, writing to `out`.
+        pub fn reporter<'a>(self, out: impl Write+'a) -> Box<dyn Reporter+'a> {
+                match self {
+                        Self::Human { long } => Box::new(HumanReporter { out, long, root: None }),
+                        Self::Json => Box::new(JsonReporter { out }),
+                        Self::Quiet => Box::new(QuietReporter { out }),
+                        Self::Tui => Box::new(TuiReporter { out, done: 0, total: 0 }),
+                }
+        }
+}
+
+/// [`OutputStyle::Human`].  With a single root, paths are printed relative to it.
+#[derive(Debug)]
+pub struct HumanReporter<W: Write> {
+        out:  W,
+        long: bool,
+        root: Option<PrintRoot>,
+}
+
+impl<W: Write> Reporter for HumanReporter<W> {
+        fn plan(&mut self, plan: &Plan, _: &[Conflict], _: &[ReferenceUpdate], _: &PlanOrigin) -> io::Result<()> {
+                self.root = match plan.roots.as_slice() {
+                        [root] if root.is_dir() => Some(PrintRoot::new(root)),
+                        _ => None,
+                };
+                Ok(())
+        }
+
+        fn reference_update(&mut self, update: &ReferenceUpdate, is_test_run: bool) -> io::Result<()> {
+                print_reference_update(&mut self.out, update, is_test_run)
+        }
+
+        fn entry(&mut self, entry: &PlanEntry, is_test_run: bool) -> io::Result<()> {
+                match self.long {
+                        true => entry.print_long(&mut self.out, self.root.as_ref()),
+                        false => entry.print(&mut self.out, is_test_run, self.root.as_ref()),
+                }
+        }
+
+        fn finished(&mut self, plan: &Plan) -> io::Result<()> {
+                print_summary(&mut self.out, plan)
+        }
+
+        fn counts(&mut self, plan: &Plan, per_dir: bool) -> io::Result<()> {
+                print_counts(&mut self.out, plan, per_dir)
+        }
+
+        fn stats(&mut self, stats: &Stats) -> io::Result<()> {
+                stats.print(&mut self.out)
+        }
+}
+
+/// [`OutputStyle::Json`].  The summary is part of the plan's JSON.
+#[derive(Debug)]
+pub struct JsonReporter<W: Write> {
+        out: W,
+}
+
+impl<W: Write> Reporter for JsonReporter<W> {
+        fn plan(&mut self,
+                plan: &Plan,
+                conflicts: &[Conflict],
+                reference_updates: &[ReferenceUpdate],
+                origin: &PlanOrigin)
+                -> io::Result<()> {
+                writeln!(self.out, "{}", plan.to_json(origin, conflicts, reference_updates)?)
+        }
+
+        fn counts(&mut self, plan: &Plan, per_dir: bool) -> io::Result<()> {
+                print_counts(&mut self.out, plan, per_dir)
+        }
+
+        fn stats(&mut self, stats: &Stats) -> io::Result<()> {
+                stats.print(&mut self.out)
+        }
+}
+
+/// [`OutputStyle::Quiet`].
+#[derive(Debug)]
+pub struct QuietReporter<W: Write> {
+        out: W,
+}
+
+impl<W: Write> Reporter for QuietReporter<W> {
+        fn counts(&mut self, plan: &Plan, per_dir: bool) -> io::Result<()> {
+                print_counts(&mut self.out, plan, per_dir)
+        }
+
+        fn stats(&mut self, stats: &Stats) -> io::Result<()> {
+                stats.print(&mut self.out)
+        }
+}
+
+/// [`OutputStyle::Tui`]: `[done/total] name` (`~~> new`, if renamed), redrawn in place for every entry.
+#[derive(Debug)]
+pub struct TuiReporter<W: Write> {
+        out:   W,
+        done:  usize,
+        total: usize,
+}
+
+impl<W: Write> Reporter for TuiReporter<W> {
+        fn plan(&mut self, plan: &Plan, _: &[Conflict], _: &[ReferenceUpdate], _: &PlanOrigin) -> io::Result<()> {
+                self.total = plan.entries.len();
+                Ok(())
+        }
+
+        fn entry(&mut self, entry: &PlanEntry, _is_test_run: bool) -> io::Result<()> {
+                self.done += 1;
+                // `\r` and clear line (ANSI), so the line is redrawn rather than appended
+                write!(self.out, "\r\x1b[2K[{}/{}] {}", self.done.cyan(), self.total, entry.filename())?;
+                if let Some(new_filename) = &entry.new_filename {
+                        write!(self.out, " ~~> {}", new_filename.blue())?;
+                }
+                self.out.flush()
+        }
+
+        fn finished(&mut self, plan: &Plan) -> io::Result<()> {
+                if self.done > 0 {
+                        writeln!(self.out)?;
+                }
+                print_summary(&mut self.out, plan)
+        }
+
+        fn counts(&mut self, plan: &Plan, per_dir: bool) -> io::Result<()> {
+                print_counts(&mut self.out, plan, per_dir)
+        }
+
+        fn stats(&mut self, stats: &Stats) -> io::Result<()> {
+                stats.print(&mut self.out)
+        }
+}
+
+/// A reference file update line.
+fn print_reference_update(out: &mut impl Write, update: &ReferenceUpdate, is_test_run: bool) -> io::Result<()> {
+        let id = if is_test_run { "reference-preview" } else { "reference-update" };
+        writeln!(out, "{}", tr(id, &[("file", &update.file.to_string_lossy().blue()), ("count", &update.replacements)]))
+}
+
+/// Match counts: per root (directory roots only, if more than one), then in total; and, for a `--fast-preview` plan,
+/// that it was not validated.
+fn print_summary(out: &mut impl Write, plan: &Plan) -> io::Result<()> {
+        // A file root is its own single match
+        let mut counts = plan.counts_by_root();
+        counts.retain(|(root, _)| root.is_dir());
+        if counts.len() > 1 {
+                for (root, count) in counts {
+                        writeln!(out,
+                                 "{}",
+                                 tr("matches-under-root", &[("root", &root.to_string_lossy().blue()),
+                                                            ("count", &count.cyan())]))?;
+                }
+        }
+        writeln!(out, "{}", tr("total-matches", &[("count", &(plan.entries.len() as u64).cyan())]))?;
+        if plan.unvalidated {
+                writeln!(out, "{}", tr("fast-preview-unchecked", &[]).yellow())?;
+        }
+        Ok(())
+}
+
+/// `--count` output: the number of matches, or (`per_dir`) one `<count> <dir>` line per directory.
+fn print_counts(out: &mut impl Write, plan: &Plan, per_dir: bool) -> io::Result<()> {
+        if !per_dir {
+                return writeln!(out, "{}", plan.entries.len());
+        }
+        let mut counts: BTreeMap<&Path, u64> = BTreeMap::new();
+        for entry in &plan.entries {
+                *counts.entry(entry.path.parent().unwrap_or(Path::new(""))).or_default() += 1;
+        }
+        for (dir, count) in counts {
+                writeln!(out, "{} {}", count, dir.to_string_lossy())?;
+        }
+        Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+        use crate::error::Result;
+
+        /// `--count` totals, overall and per directory.
+        #[test]
+        fn test_print_counts() -> Result<()> {
+                let mut plan = Plan::default();
+                for path in ["./a", "./b", "./sub/c"] {
+                        plan.push(PlanEntry::new_unresolved(Path::new("."), Path::new(path), false, None));
+                }
+                let mut out = Vec::new();
+                print_counts(&mut out, &plan, false)?;
+                assert_eq!(String::from_utf8(out)?, "3\n");
+                let mut out = Vec::new();
+                print_counts(&mut out, &plan, true)?;
+                assert_eq!(String::from_utf8(out)?, "2 .\n1 ./sub\n");
+                Ok(())
+        }
+
+        /// Each style reports the same run its own way.
+        #[test]
+        fn test_output_styles() -> Result<()> {
+                let mut plan = Plan::default();
+                for path in ["./a", "./b"] {
+                        plan.push(PlanEntry::new_unresolved(Path::new("."), Path::new(path), false, Some("c".into())));
+                }
+                let report = |style: OutputStyle| -> Result<String> {
+                        let mut out = Vec::new();
+                        let mut reporter = style.reporter(&mut out);
+                        reporter.plan(&plan, &[], &[], &PlanOrigin::new("", None))?;
+                        for entry in &plan.entries {
+                                reporter.entry(entry, true)?;
+                        }
+                        reporter.finished(&plan)?;
+                        drop(reporter);
+                        Ok(String::from_utf8(out)?)
+                };
+                assert_eq!(report(OutputStyle::Human { long: false })?.lines().count(), 3);
+                assert_eq!(report(OutputStyle::Quiet)?, "");
+                let tui = report(OutputStyle::Tui)?;
+                assert_eq!(tui.matches('\r').count(), 2);
+                assert_eq!(tui.lines().count(), 2, "{:?}", tui);
+                assert!(report(OutputStyle::Json)?.trim_start().starts_with('{'));
+                Ok(())
+        }
+}
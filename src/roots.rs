@@ -0,0 +1,238 @@
+//! Root directory handling: normalizing the set of roots handed to the walker.
+
+use std::{fmt::Display,
+          path::{Path, PathBuf},
+          thread,
+          time::{Duration, Instant}};
+
+use crate::{config, engine::Engine, i18n::tr};
+
+/// Removes duplicate roots, warning about each one dropped.
+///
+/// Roots are compared by canonical path, so `./photos`, `photos/`, and a symlink to it are all the same root.
+/// When recursing, a root nested inside another root is kept, with a warning: the outer walk may not reach it
+/// (`--max-depth`, hidden or excluded directories), and entries it does reach twice are planned once (see
+/// [`crate::plan::Plan::push`]).
+///
+/// Order of the remaining roots is preserved.
+pub fn dedupe(roots: Vec<PathBuf>, does_recurse: bool) -> Vec<PathBuf> {
+        let canonical: Vec<PathBuf> =
+                roots.iter().map(|root| root.canonicalize().unwrap_or_else(|_| root.clone())).collect();
+
+        let mut kept = Vec::with_capacity(roots.len());
+        'roots: for (i, root) in roots.iter().enumerate() {
+                for (j, other) in roots.iter().enumerate() {
+                        if i == j {
+                                continue;
+                        }
+                        let is_duplicate = canonical[i] == canonical[j] && j < i;
+                        let is_nested =
+                                does_recurse && canonical[i] != canonical[j] && canonical[i].starts_with(&canonical[j]);
+                        let (root_arg, other_arg) = (format!("{:?}", root), format!("{:?}", other));
+                        let message_args: &[(&str, &dyn Display)] = &[("root", &root_arg), ("other", &other_arg)];
+                        if is_duplicate {
+                                tracing::warn!("{}", tr("warn-duplicate-root", message_args));
+                                continue 'roots;
+                        }
+                        if is_nested {
+                                tracing::warn!("{}", tr("warn-nested-root", message_args));
+                        }
+                }
+                kept.push(root.clone());
+        }
+        kept
+}
+
+/// Whether a root is a filesystem root (`/`, `C:\`) or the home directory: the classic footgun roots.
+pub fn is_sweeping(root: &Path) -> bool {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let home = config::home_dir().map(|home| home.canonicalize().unwrap_or(home));
+        root.parent().is_none() || Some(&root) == home.as_ref()
+}
+
+/// Filesystem types whose directories are usually stubs for other mounts (`/run/media/...`), not data.
+const STUB_FS_TYPES: &[&str] = &["tmpfs", "devtmpfs", "ramfs"];
+
+/// Whether a root is on a mounted filesystem: `/` itself, a mount point, or inside a mount below `/`.
+///
+/// An unmounted mountpoint is just an (often empty) directory on the parent filesystem, so a run against it would
+/// "succeed" having renamed nothing.  So a directory on the `/` filesystem, or on a [stub](STUB_FS_TYPES) one like
+/// the tmpfs at `/run`, does not count.  A missing root is not mounted.
+///
+/// Mounts are read from `/proc/self/mountinfo`.  Without it (off Linux), a directory whose device differs from its
+/// parent's is taken for a mount point.  Off unix every existing root counts as mounted.
+pub fn is_mounted(root: &Path) -> bool {
+        let Ok(root) = root.canonicalize() else {
+                return false;
+        };
+        match std::fs::read_to_string("/proc/self/mountinfo") {
+                Ok(mountinfo) => {
+                        is_mounted_in(&root, &mountinfo.lines().filter_map(parse_mountinfo).collect::<Vec<_>>())
+                }
+                Err(_) => crosses_device(&root),
+        }
+}
+
+/// [`is_mounted`], against a mount table of (mount point, filesystem type) pairs.  `root` must be canonical.
+fn is_mounted_in(root: &Path, mounts: &[(PathBuf, String)]) -> bool {
+        // Later mounts over the same point hide earlier ones
+        let fs_type = |dir: &Path| mounts.iter().rev().find(|(point, _)| point == dir).map(|(_, fs_type)| fs_type);
+        if fs_type(root).is_some() {
+                return true;
+        }
+        match root.ancestors().skip(1).find_map(|dir| fs_type(dir).map(|fs_type| (dir, fs_type))) {
+                Some((dir, fs_type)) => dir.parent().is_some() && !STUB_FS_TYPES.contains(&fs_type.as_str()),
+                None => false,
+        }
+}
+
+/// Mount point and filesystem type from a `/proc/self/mountinfo` line (fields 5 and the one after ` - `).
+fn parse_mountinfo(line: &str) -> Option<(PathBuf, String)> {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mount_point = mount.split(' ').nth(4)?;
+        let fs_type = filesystem.split(' ').next()?;
+        Some((PathBuf::from(unescape_octal(mount_point)), fs_type.to_string()))
+}
+
+/// Undoes mountinfo's `\ooo` escapes (of space, tab, newline, and backslash).
+fn unescape_octal(field: &str) -> String {
+        let mut unescaped = String::with_capacity(field.len());
+        let mut rest = field;
+        while let Some(i) = rest.find('\\') {
+                unescaped.push_str(&rest[..i]);
+                match rest.get(i + 1..i + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+                        Some(byte) => {
+                                unescaped.push(char::from(byte));
+                                rest = &rest[i + 4..];
+                        }
+                        None => {
+                                unescaped.push('\\');
+                                rest = &rest[i + 1..];
+                        }
+                }
+        }
+        unescaped.push_str(rest);
+        unescaped
+}
+
+/// Whether `root`, or an ancestor below `/`, is on a different device from its parent.  `root` must be canonical.
+#[cfg(unix)]
+fn crosses_device(root: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = |dir: &Path| dir.metadata().map(|meta| meta.dev()).ok();
+        root.parent().is_none()
+        || root.ancestors().zip(root.ancestors().skip(1)).any(|(dir, parent)| {
+                                                                 dev(dir).is_some_and(|dev_of_dir| {
+                                                                                 Some(dev_of_dir) != dev(parent)
+                                                                         })
+                                                         })
+}
+
+#[cfg(not(unix))]
+fn crosses_device(_root: &Path) -> bool {
+        true
+}
+
+/// `--wait-for-mount`: polls until every root [`is_mounted`], for up to `wait`.  The first root still unmounted
+/// when time runs out, if any.
+pub fn wait_for_mounts(roots: &[PathBuf], wait: Duration) -> Option<PathBuf> {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+        let deadline = Instant::now() + wait;
+        loop {
+                let unmounted = roots.iter().find(|root| !is_mounted(root))?;
+                let now = Instant::now();
+                if now >= deadline {
+                        return Some(unmounted.clone());
+                }
+                tracing::info!("waiting for {:?} to be mounted", unmounted);
+                thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+}
+
+/// Whether a pattern would match (nearly) any filename: it matches the empty string, or every one of a handful
+/// of unrelated names.
+pub fn is_broad_pattern(re: &Engine) -> bool {
+        const SAMPLE_NAMES: &[&str] = &["a", "README.md", "photo_0001.JPG", ".config", "Cargo.toml", "x y z"];
+        re.is_match("") || SAMPLE_NAMES.iter().all(|name| re.is_match(name))
+}
+
+#[cfg(test)]
+mod tests {
+        use std::fs;
+
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn test_is_mounted() {
+                let temp_dir = TempDir::new().unwrap();
+                let missing = temp_dir.path().join("nas");
+
+                assert!(is_mounted(Path::new("/")));
+                assert!(is_mounted(Path::new("/proc")));
+                assert!(is_mounted(Path::new("/proc/self")));
+                assert!(!is_mounted(&missing));
+                assert_eq!(parse_mountinfo("36 35 98:0 /mnt1 /mnt/my\\040nas rw,noatime master:1 - nfs4 nas:/x rw"),
+                           Some((PathBuf::from("/mnt/my nas"), "nfs4".to_string())));
+                assert_eq!(wait_for_mounts(&[PathBuf::from("/proc")], Duration::ZERO), None);
+                assert_eq!(wait_for_mounts(&[PathBuf::from("/proc"), missing.clone()], Duration::ZERO), Some(missing));
+        }
+
+        /// Only mounts below `/` count, and not stub filesystems holding mount points.
+        #[test]
+        fn test_is_mounted_in() {
+                let mounts: Vec<(PathBuf, String)> =
+                        [("/", "ext4"), ("/run", "tmpfs"), ("/mnt/nas", "nfs4")].iter()
+                                                                                .map(|(point, fs_type)| {
+                                                                                        (PathBuf::from(point),
+                                                                                         fs_type.to_string())
+                                                                                })
+                                                                                .collect();
+                let test_cases = vec![("/", true),
+                                      ("/home/me/photos", false),
+                                      ("/mnt/nas", true),
+                                      ("/mnt/nas/photos/2023", true),
+                                      ("/mnt/usb", false),
+                                      ("/run/media/me/disk", false)];
+                for (root, expected) in test_cases {
+                        assert_eq!(is_mounted_in(Path::new(root), &mounts), expected, "root: {}", root);
+                }
+                let mounted_disk = [mounts, vec![(PathBuf::from("/run/media/me/disk"), "ext4".to_string())]].concat();
+                assert!(is_mounted_in(Path::new("/run/media/me/disk/photos"), &mounted_disk));
+        }
+
+        #[test]
+        fn test_dedupe() {
+                let temp_dir = TempDir::new().unwrap();
+                let photos = temp_dir.path().join("photos");
+                let year = photos.join("2023");
+                fs::create_dir_all(&year).unwrap();
+                let roots = vec![photos.clone(), year.clone(), photos.join("."), temp_dir.path().join("other")];
+
+                assert_eq!(dedupe(roots.clone(), true), vec![photos.clone(),
+                                                             year.clone(),
+                                                             temp_dir.path().join("other")]);
+                assert_eq!(dedupe(roots, false), vec![photos, year, temp_dir.path().join("other")]);
+        }
+
+        #[test]
+        fn test_is_broad_pattern() {
+                let test_cases = vec![(".*", true),
+                                      ("^", true),
+                                      ("[a-z]*", true),
+                                      (".", true),
+                                      (r"\.txt$", false),
+                                      ("^IMG_", false)];
+                for (pattern, expected) in test_cases {
+                        assert_eq!(is_broad_pattern(&Engine::new(pattern, false, false).unwrap()),
+                                   expected,
+                                   "pattern: {}",
+                                   pattern);
+                }
+        }
+}
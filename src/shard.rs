@@ -0,0 +1,153 @@
+//! `--shard i/n`: deterministic partition of a plan, so several processes or machines can each rename a disjoint
+//! part of one enormous tree.
+//!
+//! Every process walks and plans the whole tree, then keeps only the entries of its own shard.  An entry's shard is
+//! a stable hash of its path *relative to its root*, so machines that mount the tree at different places still agree.
+//!
+//! Planning the whole tree lets each shard warn about what it cannot guarantee alone:
+//! - entries of different shards that would be renamed to the same destination
+//! - directory renames with entries planned by another shard beneath them (whichever shard runs second will find
+//!   its paths moved)
+
+use std::{collections::HashMap,
+          fmt,
+          path::{Path, PathBuf},
+          str::FromStr};
+
+use crate::{plan::{Plan, PlanEntry},
+            state::Fnv};
+
+/// One of `count` shards (`index` is 1-based, as written on the command line).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+        index: u64,
+        count: u64,
+}
+
+impl FromStr for Shard {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let parsed =
+                        s.split_once('/').and_then(|(index, count)| Some((index.parse().ok()?, count.parse().ok()?)));
+                match parsed {
+                        Some((index, count)) if (1..=count).contains(&index) => Ok(Self { index, count }),
+                        _ => Err(format!("expected i/n with 1 <= i <= n (e.g. 2/4); got {:?}", s)),
+                }
+        }
+}
+
+impl fmt::Display for Shard {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}/{}", self.index, self.count)
+        }
+}
+
+impl Shard {
+        /// Whether `entry` belongs to this shard.
+        pub fn contains(&self, entry: &PlanEntry) -> bool {
+                let relative = match entry.path.strip_prefix(&entry.root) {
+                        Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                        _ => Path::new(entry.path.file_name().unwrap_or_default()),
+                };
+                let mut hash = Fnv::new();
+                hash.write(relative.as_os_str().as_encoded_bytes());
+                hash.finish() % self.count == self.index - 1
+        }
+
+        /// Keeps only this shard's entries, first warning about renames that interact with other shards.
+        ///
+        /// Returns the number of such cross-shard warnings.  (Each shard involved reports the same conflict.)
+        pub fn retain(&self, plan: &mut Plan) -> usize {
+                let is_mine: Vec<bool> = plan.entries.iter().map(|entry| self.contains(entry)).collect();
+                let mut warnings = 0;
+                let mut by_destination: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+                let mut renamed_dirs: HashMap<&Path, usize> = HashMap::new();
+                for (i, entry) in plan.entries.iter().enumerate() {
+                        if let Some(destination) = entry.canonical_destination() {
+                                by_destination.entry(destination).or_default().push(i);
+                                if entry.is_dir {
+                                        renamed_dirs.insert(entry.canonical(), i);
+                                }
+                        }
+                }
+                for (destination, sharers) in &by_destination {
+                        let Some(&mine) = sharers.iter().find(|&&i| is_mine[i]) else { continue };
+                        for &other in sharers.iter().filter(|&&i| !is_mine[i]) {
+                                tracing::warn!("Shard {}: {:?} and {:?} (another shard) would both be renamed to {:?}",
+                                               self,
+                                               plan.entries[mine].path,
+                                               plan.entries[other].path,
+                                               destination);
+                                warnings += 1;
+                        }
+                }
+                for (i, entry) in plan.entries.iter().enumerate() {
+                        for ancestor in entry.canonical().ancestors().skip(1) {
+                                let Some(&dir) = renamed_dirs.get(ancestor) else { continue };
+                                // Guard: only pairs across this shard's boundary
+                                if is_mine[i] == is_mine[dir] {
+                                        continue;
+                                }
+                                tracing::warn!("Shard {}: renaming directory {:?} moves {:?}, which another shard \
+                                                handles; run the shard renaming the directory last",
+                                               self,
+                                               plan.entries[dir].path,
+                                               entry.path);
+                                warnings += 1;
+                        }
+                }
+                let mut is_mine = is_mine.into_iter();
+                plan.entries.retain(|_| is_mine.next().expect("one flag per entry"));
+                warnings
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_shards_partition() {
+                let shards: Vec<Shard> = (1..=3).map(|i| format!("{}/3", i).parse().unwrap()).collect();
+                for name in ["a", "b", "c", "d", "e", "f", "g"] {
+                        let entry = PlanEntry::new_unresolved(Path::new("/root"),
+                                                              &Path::new("/root/sub").join(name),
+                                                              false,
+                                                              None);
+                        let moved = PlanEntry::new_unresolved(Path::new("/mnt/elsewhere"),
+                                                              &Path::new("/mnt/elsewhere/sub").join(name),
+                                                              false,
+                                                              None);
+                        assert_eq!(shards.iter().filter(|shard| shard.contains(&entry)).count(), 1);
+                        assert!(shards.iter().all(|shard| shard.contains(&entry) == shard.contains(&moved)));
+                }
+                assert!("0/3".parse::<Shard>().is_err());
+                assert!("4/3".parse::<Shard>().is_err());
+                assert_eq!("2/3".parse::<Shard>().unwrap().to_string(), "2/3");
+        }
+
+        /// Two shards' entries sharing a destination are reported, then only this shard's entry is kept.
+        #[test]
+        fn test_retain_warns_across_shards() {
+                let (one, two): (Shard, Shard) = ("1/2".parse().unwrap(), "2/2".parse().unwrap());
+                let entry = |name: &str| {
+                        PlanEntry::new_unresolved(Path::new("/root"),
+                                                  &Path::new("/root").join(name),
+                                                  false,
+                                                  Some("same".to_string()))
+                };
+                let names: Vec<String> = (0..100).map(|i| format!("file_{}", i)).collect();
+                let mine = names.iter().find(|name| one.contains(&entry(name))).unwrap();
+                let theirs = names.iter().find(|name| two.contains(&entry(name))).unwrap();
+
+                let mut plan = Plan::default();
+                plan.push(entry(mine));
+                plan.push(entry(theirs));
+                assert_eq!(one.retain(&mut plan), 1);
+                assert_eq!(plan.entries.len(), 1);
+                assert_eq!(plan.entries[0].filename(), mine);
+        }
+}
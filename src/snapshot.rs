@@ -0,0 +1,164 @@
+//! `--snapshot <DIR>`: canonical machine outputs of a run, for golden-file tests.
+//!
+//! Writes `plan.json` (every planned entry) and `summary.json` (counts and conflicts).  Both are canonical: entries
+//! are sorted by root and root-relative path, paths use `/`, and nothing depends on the tool version, the clock, or
+//! the absolute location of the roots.  Tools built on this crate can pin behavior across upgrades by comparing a
+//! fresh snapshot against a checked-in one with [`diff`].
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{error::Result,
+            plan::{Conflict, Plan, PlanEntry}};
+
+/// Files written to a snapshot directory.
+pub const SNAPSHOT_FILES: [&str; 2] = ["plan.json", "summary.json"];
+
+/// One planned entry.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct SnapshotEntry {
+        pub root:         String,
+        /// Path relative to the root (the root's own name for a file root).
+        pub path:         String,
+        pub is_dir:       bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub new_filename: Option<String>,
+        /// Why the new name was rejected, if it was (see `validate.rs`).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub rejected:     Option<String>,
+}
+
+/// Counts and conflicts of a plan.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Summary {
+        pub total_matches: usize,
+        pub renames:       usize,
+        pub rejected:      usize,
+        pub per_root:      BTreeMap<String, u64>,
+        pub conflicts:     Vec<String>,
+}
+
+/// A plan and its summary, in canonical form.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snapshot {
+        pub plan:    Vec<SnapshotEntry>,
+        pub summary: Summary,
+}
+
+impl SnapshotEntry {
+        fn of(entry: &PlanEntry) -> Self {
+                let rejected = entry.rejected.as_ref().map(|rejection| rejection.reason.to_string());
+                Self { root: slashed(&entry.root),
+                       path: relative(&entry.root, &entry.path),
+                       is_dir: entry.is_dir,
+                       new_filename: entry.new_filename.clone(),
+                       rejected }
+        }
+}
+
+impl Snapshot {
+        /// Canonical form of `plan`, with the conflicts found for it.
+        pub fn capture(plan: &Plan, conflicts: &[Conflict]) -> Self {
+                let mut entries: Vec<SnapshotEntry> = plan.entries.iter().map(SnapshotEntry::of).collect();
+                entries.sort();
+                let mut conflicts: Vec<String> = conflicts.iter().map(|conflict| conflict.to_string()).collect();
+                conflicts.sort();
+                let summary = Summary { total_matches: entries.len(),
+                                        renames: entries.iter().filter(|entry| entry.new_filename.is_some()).count(),
+                                        rejected: entries.iter().filter(|entry| entry.rejected.is_some()).count(),
+                                        per_root: plan.counts_by_root()
+                                                      .into_iter()
+                                                      .map(|(root, count)| (slashed(root), count))
+                                                      .collect(),
+                                        conflicts };
+                Self { plan: entries, summary }
+        }
+
+        /// Writes [`SNAPSHOT_FILES`] into `dir`, creating it if needed.
+        pub fn write(&self, dir: &Path) -> Result<()> {
+                fs::create_dir_all(dir).map_err(|e| format!("Could not create snapshot directory {:?}: {}", dir, e))?;
+                let [plan_file, summary_file] = SNAPSHOT_FILES;
+                fs::write(dir.join(plan_file), serde_json::to_string_pretty(&self.plan)? + "\n")?;
+                fs::write(dir.join(summary_file), serde_json::to_string_pretty(&self.summary)? + "\n")?;
+                Ok(())
+        }
+}
+
+/// Differences between a snapshot directory and a golden one, one line each (none if they match).
+///
+/// Files are compared line by line; each differing file reports its first differing line.
+pub fn diff(actual: &Path, golden: &Path) -> Result<Vec<String>> {
+        let mut differences = Vec::new();
+        for file in SNAPSHOT_FILES {
+                let golden_file = golden.join(file);
+                let expected = fs::read_to_string(&golden_file).map_err(|e| {
+                                                                       format!("Could not read golden {:?}: {}",
+                                                                               golden_file, e)
+                                                               })?;
+                let Ok(found) = fs::read_to_string(actual.join(file)) else {
+                        differences.push(format!("{}: missing", file));
+                        continue;
+                };
+                let (mut expected_lines, mut found_lines) = (expected.lines(), found.lines());
+                for line in 1.. {
+                        match (expected_lines.next(), found_lines.next()) {
+                                (None, None) => break,
+                                (expected, found) if expected == found => {}
+                                (expected, found) => {
+                                        differences.push(format!("{}:{}: expected {:?}, found {:?}",
+                                                                 file,
+                                                                 line,
+                                                                 expected.unwrap_or("<end of file>"),
+                                                                 found.unwrap_or("<end of file>")));
+                                        break;
+                                }
+                        }
+                }
+        }
+        Ok(differences)
+}
+
+/// `path` relative to `root` (a file root is its own name), with `/` separators.
+fn relative(root: &Path, path: &Path) -> String {
+        match path.strip_prefix(root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => slashed(relative),
+                _ => path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        }
+}
+
+/// `path` with `/` separators on every platform.
+fn slashed(path: &Path) -> String {
+        path.iter().map(|component| component.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_snapshot_diff() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let root = Path::new("photos");
+                let mut plan = Plan::default();
+                plan.push(PlanEntry::new_unresolved(root, &root.join("b/IMG_2.jpg"), false, Some("2.jpg".into())));
+                plan.push(PlanEntry::new_unresolved(root, &root.join("IMG_1.jpg"), false, None));
+                let (golden, actual) = (temp_dir.path().join("golden"), temp_dir.path().join("actual"));
+                let snapshot = Snapshot::capture(&plan, &[]);
+                assert_eq!(snapshot.plan[1].path, "b/IMG_2.jpg");
+                assert_eq!(snapshot.summary.renames, 1);
+                snapshot.write(&golden)?;
+                snapshot.write(&actual)?;
+                assert!(diff(&actual, &golden)?.is_empty());
+
+                plan.entries[0].new_filename = Some("two.jpg".into());
+                Snapshot::capture(&plan, &[]).write(&actual)?;
+                let differences = diff(&actual, &golden)?;
+                assert_eq!(differences.len(), 1);
+                assert!(differences[0].starts_with("plan.json:"), "{:?}", differences);
+                Ok(())
+        }
+}
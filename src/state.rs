@@ -0,0 +1,138 @@
+//! Per-root state for `--changed-only`: which entries a previous run already saw.
+//!
+//! Each candidate (a matched entry that passed the filters) is reduced to a hash of its path and modification time.
+//! After a completed (non-preview) run, the hashes of every candidate under a root are written to a state file for
+//! that root, pattern, and transform.
+//! A later `--changed-only` run with the same root, pattern, and transform skips entries whose hash is in it:
+//! anything new, renamed, or modified since is processed as usual.
+//!
+//! State files live under `$XDG_STATE_HOME/rename_files/changed-only/` (or `~/.local/state/...`).  Deleting them
+//! just makes the next run a full one.
+//!
+//! # Note:
+//! Entries renamed by a run are recorded under their old names, so they count as changed (once) on the next run.
+
+use std::{collections::HashSet,
+          fs::{self, Metadata},
+          path::{Path, PathBuf}};
+
+use crate::{config, error::Result, transform::Transform};
+
+/// Entries seen by the previous run under a root, and those seen by this one.
+#[derive(Debug)]
+pub struct RunState {
+        /// State file; `None` if no state directory could be determined (nothing is recorded).
+        file:     Option<PathBuf>,
+        previous: HashSet<u64>,
+        current:  Vec<u64>,
+}
+
+impl RunState {
+        /// State recorded for `root` by the last run with the same pattern and transform (empty if none).
+        pub fn load(root: &Path, pattern: &str, transform: Option<&Transform>) -> Self {
+                let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+                let mut key = Fnv::new();
+                key.write(canonical.as_os_str().as_encoded_bytes());
+                key.write(b"\0");
+                key.write(pattern.as_bytes());
+                if let Some((kind, template)) = transform.map(Transform::spec) {
+                        key.write(format!("\0{}\0{}", kind, template).as_bytes());
+                }
+                Self::from_file(state_dir().map(|dir| dir.join("changed-only").join(format!("{:016x}", key.finish()))))
+        }
+
+        /// State recorded in `file` (empty if none).
+        fn from_file(file: Option<PathBuf>) -> Self {
+                let previous: HashSet<u64> =
+                        file.as_ref()
+                            .and_then(|file| fs::read_to_string(file).ok())
+                            .map(|text| text.lines().filter_map(|line| u64::from_str_radix(line, 16).ok()).collect())
+                            .unwrap_or_default();
+                tracing::debug!("Loaded state {:?} ({} entries)", file, previous.len());
+                Self { file, previous, current: Vec::new() }
+        }
+
+        /// Records the entry at `path`, with `metadata`, as seen, and returns whether the previous run already saw it,
+        /// unchanged.
+        ///
+        /// Entries whose modification time could not be read always count as changed.
+        pub fn is_unchanged(&mut self, path: &Path, metadata: Option<&Metadata>) -> bool {
+                let Some(modified) = metadata.and_then(|metadata| metadata.modified().ok()) else {
+                        return false;
+                };
+                let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+                let mut hash = Fnv::new();
+                hash.write(path.as_os_str().as_encoded_bytes());
+                hash.write(&since_epoch.as_nanos().to_le_bytes());
+                let hash = hash.finish();
+                self.current.push(hash);
+                self.previous.contains(&hash)
+        }
+
+        /// Writes this run's entries as the state for the next run.
+        pub fn save(&self) -> Result<()> {
+                let Some(file) = &self.file else { return Ok(()) };
+                if let Some(dir) = file.parent() {
+                        fs::create_dir_all(dir)?;
+                }
+                let text: String = self.current.iter().map(|hash| format!("{:016x}\n", hash)).collect();
+                fs::write(file, text).map_err(|e| format!("Could not write state {:?}: {}", file, e))?;
+                Ok(())
+        }
+}
+
+/// This tool's directory under the XDG state dir (or `~/.local/state`).
+pub fn state_dir() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_STATE_HOME") {
+                Some(dir) => PathBuf::from(dir),
+                None => config::home_dir()?.join(".local/state"),
+        };
+        Some(base.join("rename_files"))
+}
+
+/// 64-bit FNV-1a.  Unlike `DefaultHasher`, stable across Rust releases (and machines), so state files stay valid
+/// after upgrades.
+pub(crate) struct Fnv(u64);
+
+impl Fnv {
+        pub(crate) fn new() -> Self {
+                Self(0xcbf2_9ce4_8422_2325)
+        }
+
+        pub(crate) fn write(&mut self, bytes: &[u8]) {
+                for byte in bytes {
+                        self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+                }
+        }
+
+        pub(crate) fn finish(&self) -> u64 {
+                self.0
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use tempfile::TempDir;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_run_state() -> Result<()> {
+                let temp_dir = TempDir::new()?;
+                let file = temp_dir.path().join("file.txt");
+                fs::write(&file, "a")?;
+                let state_file = Some(temp_dir.path().join("state").join("root"));
+                let metadata = || file.metadata().ok();
+
+                let mut state = RunState::from_file(state_file.clone());
+                assert!(!state.is_unchanged(&file, metadata().as_ref()));
+                state.save()?;
+                assert!(RunState::from_file(state_file.clone()).is_unchanged(&file, metadata().as_ref()));
+
+                let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+                fs::File::options().write(true).open(&file)?.set_modified(earlier)?;
+                assert!(!RunState::from_file(state_file).is_unchanged(&file, metadata().as_ref()));
+                Ok(())
+        }
+}
@@ -0,0 +1,66 @@
+//! Run statistics, printed with `--stats`.
+
+use std::io::{self, Write};
+
+use owo_colors::OwoColorize;
+
+/// Per-stage counters for a run.
+///
+/// `rejected_early` counts entries that left the hot path at the byte-level pre-check: no utf8 conversion, no
+/// captures, no replacement buffer.  Each of those would previously have paid for a utf8 conversion and a
+/// (failed) full regex search.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+        pub walked:             u64,
+        pub walk_errors:        u64,
+        pub symlink_loops:      u64,
+        pub timed_out:          u64,
+        pub excluded:           u64,
+        pub hidden:             u64,
+        pub unchanged:          u64,
+        pub marked:             u64,
+        pub filtered_by_type:   u64,
+        pub filtered_by_ext:    u64,
+        pub filtered_by_size:   u64,
+        pub filtered_by_mtime:  u64,
+        pub rejected_early:     u64,
+        pub rejected_literal:   u64,
+        pub non_utf8:           u64,
+        pub matched:            u64,
+        pub replacements_built: u64,
+        pub memo_hits:          u64,
+        pub replacement_bytes:  u64,
+        pub sanitized:          u64,
+        pub truncated:          u64,
+}
+
+impl Stats {
+        /// Prints counters, one per line.
+        pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+                let rows = [("entries walked", self.walked),
+                            ("walk errors", self.walk_errors),
+                            ("symlink loops skipped", self.symlink_loops),
+                            ("entries timed out", self.timed_out),
+                            ("entries excluded", self.excluded),
+                            ("hidden entries skipped", self.hidden),
+                            ("unchanged since last run", self.unchanged),
+                            ("marked by an earlier run", self.marked),
+                            ("filtered by entry type", self.filtered_by_type),
+                            ("filtered by extension", self.filtered_by_ext),
+                            ("filtered by size", self.filtered_by_size),
+                            ("filtered by mtime", self.filtered_by_mtime),
+                            ("rejected without allocating", self.rejected_early),
+                            ("  of which by literal check", self.rejected_literal),
+                            ("non-utf8 names skipped", self.non_utf8),
+                            ("matches", self.matched),
+                            ("replacement names built", self.replacements_built),
+                            ("memoized replacements reused", self.memo_hits),
+                            ("replacement bytes allocated", self.replacement_bytes),
+                            ("new names sanitized", self.sanitized),
+                            ("new names truncated", self.truncated)];
+                for (label, count) in rows {
+                        writeln!(out, "{:>28}: {}", label, count.cyan())?;
+                }
+                Ok(())
+        }
+}
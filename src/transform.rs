@@ -0,0 +1,508 @@
+//! How a matched filename becomes its new name.
+//!
+//! The regex always selects entries.  The new name comes from one of:
+//! - `--rep`: regex replacement of the match (see [`Matcher::replace`]), or with `--all` of every match, or with
+//!   `--occurrence` of one chosen match
+//! - `--insert TEXT --at POSITION`: fixed text inserted at a position of the name; no regex work beyond selection
+//! - `--rename-to TEMPLATE`: the whole new name built from a template; the match itself is not kept
+//! - `--map FILE`: the captured text replaced by its value in a lookup table (see [`crate::mapping`])
+
+use std::{cell::Cell,
+          ffi::OsStr,
+          fmt::{self, Write},
+          path::Path,
+          str::FromStr,
+          time::SystemTime};
+
+use chrono::{DateTime, Local};
+use deunicode::deunicode_with_tofu;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::{error,
+            mapping::Mapping,
+            matcher::{self, Matcher, Occurrence}};
+
+/// Where `--insert` puts its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertAt {
+        /// Before the name.
+        Start,
+        /// After the name (after any extension).
+        End,
+        /// Before the last extension (`photo.jpg` -> `photo<TEXT>.jpg`), or at the end if there is none.
+        #[default]
+        BeforeExt,
+        /// After this many characters; negative counts from the end.  Clamped to the name.
+        Offset(isize),
+}
+
+impl FromStr for InsertAt {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                        "start" => Ok(Self::Start),
+                        "end" => Ok(Self::End),
+                        "before-ext" => Ok(Self::BeforeExt),
+                        _ => s.strip_prefix("offset:")
+                              .and_then(|n| n.parse().ok())
+                              .map(Self::Offset)
+                              .ok_or_else(|| format!("expected start, end, before-ext, or offset:N; got {:?}", s)),
+                }
+        }
+}
+
+/// As parsed by [`InsertAt::from_str`].
+impl fmt::Display for InsertAt {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        Self::Start => write!(f, "start"),
+                        Self::End => write!(f, "end"),
+                        Self::BeforeExt => write!(f, "before-ext"),
+                        Self::Offset(n) => write!(f, "offset:{}", n),
+                }
+        }
+}
+
+impl InsertAt {
+        /// Byte index in `name` to insert at.
+        pub fn index(&self, name: &str) -> usize {
+                match *self {
+                        Self::Start => 0,
+                        Self::End => name.len(),
+                        // A leading dot marks a hidden file, not an extension
+                        Self::BeforeExt => matcher::split_ext(name).0.len(),
+                        Self::Offset(n) => {
+                                let chars = name.chars().count();
+                                let n = match n < 0 {
+                                        true => chars.saturating_sub(n.unsigned_abs()),
+                                        false => (n as usize).min(chars),
+                                };
+                                name.char_indices().nth(n).map_or(name.len(), |(i, _)| i)
+                        }
+                }
+        }
+}
+
+/// `{n}` (or zero-padded, `{n:03}`) in a template: the entry's number among matches (in filename order within each
+/// directory), counting from `start` by `step`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter {
+        start:    i64,
+        step:     i64,
+        /// Entries numbered so far.
+        numbered: Cell<i64>,
+}
+
+impl Default for Counter {
+        fn default() -> Self {
+                Self::new(1, 1)
+        }
+}
+
+impl Counter {
+        /// A counter at `start`, advancing by `step` (`--counter-start`, `--counter-step`).
+        pub fn new(start: i64, step: i64) -> Self {
+                Self { start, step, numbered: Cell::new(0) }
+        }
+
+        /// Builds a name with the next number, which is used up only if a name was built.
+        fn number(&self, build: impl FnOnce(&str) -> Option<String>) -> Option<String> {
+                let n = self.start + self.step * self.numbered.get();
+                let new_name = build(&n.to_string())?;
+                self.numbered.set(self.numbered.get() + 1);
+                Some(new_name)
+        }
+}
+
+/// Whether `template` uses the `{n}` counter.
+fn is_counted(template: &str) -> bool {
+        template.contains("{n}") || template.contains("{n:")
+}
+
+/// Date/time tokens, each followed by a chrono strftime format and `}`: `{now:%Y-%m-%d}` for when the run started,
+/// `{mtime:%Y%m%d_%H%M}` for the entry's modification time.  Both are in local time.
+pub const TIME_TOKENS: [&str; 2] = ["{now:", "{mtime:"];
+
+/// Date/time tokens in `template`: each whole token, its kind (`now`, `mtime`), and its format.
+fn time_tokens(template: &str) -> impl Iterator<Item=(&str, &str, &str)> {
+        let starts = TIME_TOKENS.into_iter().flat_map(move |opening| template.match_indices(opening));
+        starts.filter_map(move |(start, _)| {
+                      let token = &template[start..=start + template[start..].find('}')?];
+                      let (kind, format) = token[1..token.len() - 1].split_once(':')?;
+                      Some((token, kind, format))
+              })
+}
+
+/// `{parent}`, `{parent.parent}`, ...: the name of the directory containing the entry, of the one containing that, and
+/// so on.  Empty past the filesystem root.
+const PARENT_TOKEN: &str = "{parent";
+
+/// Parent tokens in `template`: each whole token, and how many levels up it names.
+fn parent_tokens(template: &str) -> impl Iterator<Item=(&str, usize)> {
+        let starts = template.match_indices(PARENT_TOKEN);
+        starts.filter_map(move |(start, _)| {
+                      let token = &template[start..=start + template[start..].find('}')?];
+                      let parts = token[1..token.len() - 1].split('.');
+                      let levels = parts.map(|part| (part == "parent").then_some(1)).sum::<Option<usize>>()?;
+                      Some((token, levels))
+              })
+}
+
+/// Name of the directory `levels` up from `path` (1: the one containing it).
+fn ancestor_name(path: &Path, levels: usize) -> String {
+        let Ok(path) = std::path::absolute(path) else { return String::new() };
+        let Some(ancestor) = path.ancestors().nth(levels) else { return String::new() };
+        // `..` components have no name of their own
+        let name = match ancestor.file_name() {
+                Some(name) => Some(name.to_os_string()),
+                None => ancestor.canonicalize().ok().and_then(|ancestor| ancestor.file_name().map(OsStr::to_os_string)),
+        };
+        name.map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// `time`, formatted; `None` if `format` is not a valid strftime format.
+fn format_time(time: DateTime<Local>, format: &str) -> Option<String> {
+        let mut formatted = String::new();
+        write!(formatted, "{}", time.format(format)).ok()?;
+        Some(formatted)
+}
+
+/// A way of building new names for matched entries.
+///
+/// Templates (`rep`, `template`) may use the [`Counter`] and [`TIME_TOKENS`]; `now` is the time `{now:...}` stands
+/// for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+        /// Regex replacement of the match.
+        Replace { rep: String, counter: Counter, now: DateTime<Local> },
+        /// Regex replacement of every (non-empty) match; names with more than `max_matches` are left alone.
+        ReplaceAll { rep: String, max_matches: usize, counter: Counter, now: DateTime<Local> },
+        /// Regex replacement of one (non-empty) match.
+        ReplaceNth { rep: String, occurrence: Occurrence, counter: Counter, now: DateTime<Local> },
+        /// Fixed text inserted at a position.
+        Insert { text: String, at: InsertAt },
+        /// Whole new name from a template: capture references and [`matcher::TOKENS`] as well.
+        RenameTo { template: String, counter: Counter, now: DateTime<Local> },
+        /// The captured text replaced by its value in `mapping`, or by `default` if it has none (without a default,
+        /// such names are left alone).
+        Map { mapping: Mapping, default: Option<String> },
+}
+
+impl Transform {
+        /// A `--rep` transform, counting from 1.
+        pub fn replace(rep: &str) -> Self {
+                Self::Replace { rep: rep.to_string(), counter: Counter::default(), now: Local::now() }
+        }
+
+        /// A `--rename-to` transform, counting from 1.
+        pub fn rename_to(template: &str) -> Self {
+                Self::RenameTo { template: template.to_string(), counter: Counter::default(), now: Local::now() }
+        }
+
+        /// Flag that selects this transform (`rep`, `rep-all` for `--rep` with `--all`, `rep-nth` for `--rep` with
+        /// `--occurrence`, `insert`, `rename-to`, `map`) and its text (for `map`, the table's file), as given.
+        pub fn spec(&self) -> (&'static str, &str) {
+                match self {
+                        Self::Replace { rep, .. } => ("rep", rep),
+                        Self::ReplaceAll { rep, .. } => ("rep-all", rep),
+                        Self::ReplaceNth { rep, .. } => ("rep-nth", rep),
+                        Self::Insert { text, .. } => ("insert", text),
+                        Self::RenameTo { template, .. } => ("rename-to", template),
+                        Self::Map { mapping, .. } => ("map", mapping.source()),
+                }
+        }
+
+        /// Template new names are built from, if any.
+        fn template(&self) -> Option<&str> {
+                match self {
+                        Self::Insert { .. } | Self::Map { .. } => None,
+                        _ => Some(self.spec().1),
+                }
+        }
+
+        /// Whether every entry must be numbered: walk in a stable order and don't memoize by name.
+        pub fn is_numbered(&self) -> bool {
+                self.template().is_some_and(is_counted)
+        }
+
+        /// Whether new names depend on the entry's modification time (`{mtime:...}`).
+        pub fn uses_mtime(&self) -> bool {
+                self.template().is_some_and(|template| time_tokens(template).any(|(_, kind, _)| kind == "mtime"))
+        }
+
+        /// Whether new names depend on more of the entry than its name (`{mtime:...}`, `{parent}`): don't memoize by
+        /// name.
+        pub fn uses_entry(&self) -> bool {
+                self.uses_mtime() || self.template().is_some_and(|template| parent_tokens(template).next().is_some())
+        }
+
+        /// Checks the formats of [`TIME_TOKENS`] in the template.
+        pub fn check(&self) -> error::Result<()> {
+                let now = Local::now();
+                for (token, _, format) in self.template().map(time_tokens).into_iter().flatten() {
+                        if format_time(now, format).is_none() {
+                                return Err(format!("Invalid date/time format in `{}`", token).into());
+                        }
+                }
+                Ok(())
+        }
+
+        /// New name for a (matching) `name`, using `scratch` as a reusable buffer.  `None` if the regex does not match.
+        ///
+        /// `{mtime:...}` and `{parent}` tokens are left as is; see [`Transform::apply_with`].
+        pub fn apply(&self, matcher: &Matcher, name: &str, scratch: &mut String) -> Option<String> {
+                self.apply_with(matcher, name, None, None, scratch)
+        }
+
+        /// As [`Transform::apply`], for the entry at `path`, modified at `mtime`.
+        ///
+        /// For an entry (`path` given) whose `mtime` could not be read, a `{mtime:...}` template gives `None`: the
+        /// entry is left as is, with a warning, rather than named after the literal token.
+        pub fn apply_with(&self,
+                          matcher: &Matcher,
+                          name: &str,
+                          path: Option<&Path>,
+                          mtime: Option<SystemTime>,
+                          scratch: &mut String)
+                          -> Option<String> {
+                let (template, counter, now) = match self {
+                        Self::Insert { text, at } => {
+                                let index = at.index(name);
+                                let mut new_name = String::with_capacity(name.len() + text.len());
+                                new_name.push_str(&name[..index]);
+                                new_name.push_str(text);
+                                new_name.push_str(&name[index..]);
+                                return Some(new_name);
+                        }
+                        Self::Map { mapping, default } => {
+                                return matcher.replace_captured(name, |key| mapping.get(key).or(default.as_deref()));
+                        }
+                        Self::Replace { rep, counter, now }
+                        | Self::ReplaceAll { rep, counter, now, .. }
+                        | Self::ReplaceNth { rep, counter, now, .. } => (rep, counter, now),
+                        Self::RenameTo { template, counter, now } => (template, counter, now),
+                };
+                let mut extra: Vec<(&str, String)> = Vec::new();
+                for (token, kind, format) in time_tokens(template) {
+                        let time = match kind {
+                                "mtime" => match (mtime, path) {
+                                        (Some(mtime), _) => DateTime::from(mtime),
+                                        (None, Some(path)) => {
+                                                tracing::warn!("Modification time unreadable, not renamed: {:?}", path);
+                                                return None;
+                                        }
+                                        (None, None) => continue,
+                                },
+                                _ => *now,
+                        };
+                        extra.push((token, format_time(time, format)?));
+                }
+                if let Some(path) = path {
+                        extra.extend(parent_tokens(template).map(|(token, levels)| {
+                                                                    (token, ancestor_name(path, levels))
+                                                            }));
+                }
+                // Plain templates build without any token bookkeeping
+                if extra.is_empty() && !is_counted(template) {
+                        return self.build(matcher, name, &[], scratch);
+                }
+                counter.number(|n| {
+                               let mut extra: Vec<(&str, &str)> =
+                                       extra.iter().map(|(token, value)| (*token, value.as_str())).collect();
+                               extra.push(("{n}", n));
+                               self.build(matcher, name, &extra, scratch)
+                       })
+        }
+
+        /// New name from the template, with `extra` (token, value) pairs.
+        fn build(&self, matcher: &Matcher, name: &str, extra: &[(&str, &str)], scratch: &mut String) -> Option<String> {
+                match self {
+                        Self::Replace { rep, .. } => matcher.replace_with(name, rep, extra, scratch),
+                        Self::ReplaceNth { rep, occurrence, .. } => {
+                                matcher.replace_nth_with(name, rep, *occurrence, extra, scratch)
+                        }
+                        Self::ReplaceAll { rep, max_matches, .. } => {
+                                matcher.replace_all_with(name, rep, *max_matches, extra, scratch)
+                        }
+                        Self::RenameTo { template, .. } => matcher.render(name, template, extra, scratch),
+                        Self::Insert { .. } | Self::Map { .. } => unreachable!("inserts and maps have no template"),
+                }
+        }
+}
+
+/// `--slug`: a web-safe form of a filename.  Lowercased ASCII letters, digits, and `_`; accents are dropped
+/// (`é` -> `e`) and every other run of characters becomes one `-`.  The extension is kept (lowercased), as is a
+/// leading dot.
+pub fn slugify(name: &str) -> String {
+        let (stem, ext) = matcher::split_ext(name);
+        let (dot, stem) = match stem.strip_prefix('.') {
+                Some(stem) => (".", stem),
+                None => ("", stem),
+        };
+        let slug = |text: &str| {
+                let mut slug = String::with_capacity(text.len());
+                // Decomposed, accents are separate (non-ascii) marks, dropped along with other unsafe characters
+                for c in text.nfd().filter(|c| !is_combining_mark(*c)) {
+                        match c.to_ascii_lowercase() {
+                                c @ ('a'..='z' | '0'..='9' | '_') => slug.push(c),
+                                _ if slug.ends_with('-') || slug.is_empty() => {}
+                                _ => slug.push('-'),
+                        }
+                }
+                slug.truncate(slug.trim_end_matches('-').len());
+                slug
+        };
+        let ext = match ext {
+                "" => String::new(),
+                ext => format!(".{}", slug(&ext[1..])),
+        };
+        format!("{}{}{}", dot, slug(stem), ext)
+}
+
+/// `--ascii`: a filename transliterated to ASCII, e.g. `Über Café.txt` -> `Uber Cafe.txt`, `Ελλάδα` -> `Ellada`.
+/// Characters with no transliteration become `_`, as do path separators some transliterations contain (`½` ->
+/// `1/2`).
+pub fn transliterate(name: &str) -> String {
+        deunicode_with_tofu(name, "_").replace(['/', '\\'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+        use chrono::TimeZone;
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_insert() {
+                let matcher = Matcher::new("").unwrap();
+                let test_cases = vec![("start", "photo.jpg", "_xphoto.jpg"),
+                                      ("end", "photo.jpg", "photo.jpg_x"),
+                                      ("before-ext", "photo.jpg", "photo_x.jpg"),
+                                      ("before-ext", "a.tar.gz", "a.tar_x.gz"),
+                                      ("before-ext", ".bashrc", ".bashrc_x"),
+                                      ("before-ext", "README", "README_x"),
+                                      ("offset:2", "äöüß", "äö_xüß"),
+                                      ("offset:-1", "abc", "ab_xc"),
+                                      ("offset:99", "abc", "abc_x"),
+                                      ("offset:-99", "abc", "_xabc")];
+                for (at, name, expected) in test_cases {
+                        let transform = Transform::Insert { text: "_x".to_string(), at: at.parse().unwrap() };
+                        assert_eq!(transform.apply(&matcher, name, &mut String::new()).as_deref(),
+                                   Some(expected),
+                                   "at: {}",
+                                   at);
+                        assert_eq!(at.parse::<InsertAt>().unwrap().to_string(), at);
+                }
+                assert!("middle".parse::<InsertAt>().is_err());
+        }
+
+        #[test]
+        fn test_rename_to() {
+                let matcher = Matcher::new(r"\.jpg$").unwrap();
+                let transform = Transform::rename_to("photo_{n}{ext}");
+                assert!(transform.is_numbered());
+                let mut scratch = String::new();
+                assert_eq!(transform.apply(&matcher, "a.jpg", &mut scratch).as_deref(), Some("photo_1.jpg"));
+                assert_eq!(transform.apply(&matcher, "b.png", &mut scratch), None);
+                assert_eq!(transform.apply(&matcher, "c.jpg", &mut scratch).as_deref(), Some("photo_2.jpg"));
+        }
+
+        #[test]
+        fn test_time_tokens() {
+                let matcher = Matcher::new(r"^").unwrap();
+                let now = Local.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+                let transform = Transform::Replace { rep: "{now:%Y-%m-%d}_{mtime:%H%M}_".to_string(),
+                                                     counter: Counter::default(),
+                                                     now };
+                assert!(transform.uses_mtime());
+                assert!(transform.check().is_ok());
+                let mtime = SystemTime::from(Local.with_ymd_and_hms(2023, 1, 2, 13, 14, 0).unwrap());
+                assert_eq!(transform.apply_with(&matcher, "a.jpg", None, Some(mtime), &mut String::new()).as_deref(),
+                           Some("2024-05-06_1314_a.jpg"));
+                // An entry whose modification time is unreadable is left as is
+                let path = Path::new("dir/a.jpg");
+                assert_eq!(transform.apply_with(&matcher, "a.jpg", Some(path), None, &mut String::new()), None);
+                assert!(Transform::rename_to("{now:%Q}").check().is_err());
+                assert!(!Transform::rename_to("{now:%Y}").uses_mtime());
+        }
+
+        #[test]
+        fn test_parent_tokens() {
+                let matcher = Matcher::new(r"^(\d+)").unwrap();
+                let transform = Transform::replace("{parent.parent}-{parent}_${1}");
+                assert!(transform.uses_entry());
+                let path = Path::new("/music/Album/01.mp3");
+                assert_eq!(transform.apply_with(&matcher, "01.mp3", Some(path), None, &mut String::new()).as_deref(),
+                           Some("music-Album_01.mp3"));
+                // Past the root
+                let transform = Transform::rename_to("{parent.parent.parent}x");
+                assert_eq!(transform.apply_with(&matcher, "01.mp3", Some(path), None, &mut String::new()).as_deref(),
+                           Some("x"));
+                assert!(!Transform::rename_to("{parents}").uses_entry());
+        }
+
+        #[test]
+        fn test_slugify() {
+                let test_cases = vec![("My Photo (1).JPG", "my-photo-1.jpg"),
+                                      ("Café Déjà Vu!.md", "cafe-deja-vu.md"),
+                                      ("  spaced   out  ", "spaced-out"),
+                                      ("snake_case&more", "snake_case-more"),
+                                      (".Hidden File", ".hidden-file"),
+                                      ("a.tar.gz", "a-tar.gz")];
+                for (name, expected) in test_cases {
+                        assert_eq!(slugify(name), expected, "name: {:?}", name);
+                }
+        }
+
+        #[test]
+        fn test_transliterate() {
+                let test_cases = vec![("Über Café.txt", "Uber Cafe.txt"),
+                                      ("Ελλάδα.jpg", "Ellada.jpg"),
+                                      ("plain_ascii.md", "plain_ascii.md"),
+                                      ("½ cup", "1_2 cup")];
+                for (name, expected) in test_cases {
+                        assert_eq!(transliterate(name), expected, "name: {:?}", name);
+                }
+        }
+
+        #[test]
+        fn test_map() {
+                let mapping = Mapping::new("people.csv", [("jdoe".to_string(), "Jane Doe".to_string())]);
+                let map = Transform::Map { mapping: mapping.clone(), default: None };
+                let matcher = Matcher::new(r"^(\w+)_report").unwrap();
+                let mut scratch = String::new();
+                assert_eq!(map.apply(&matcher, "jdoe_report.pdf", &mut scratch),
+                           Some("Jane Doe_report.pdf".to_string()));
+                assert_eq!(map.apply(&matcher, "xyz_report.pdf", &mut scratch), None);
+                assert_eq!(map.apply(&Matcher::new("jdoe").unwrap(), "by jdoe.txt", &mut scratch),
+                           Some("by Jane Doe.txt".to_string()));
+
+                let map = Transform::Map { mapping, default: Some("unknown".to_string()) };
+                assert_eq!(map.apply(&matcher, "xyz_report.pdf", &mut scratch), Some("unknown_report.pdf".to_string()));
+                assert_eq!(map.spec(), ("map", "people.csv"));
+        }
+
+        /// `{n}` in `--rep`, padded, from a given start and step.
+        #[test]
+        fn test_counter() {
+                let matcher = Matcher::new(r"^IMG_\d+").unwrap();
+                let transform = Transform::Replace { rep:     "photo_{n:03}".to_string(),
+                                                     counter: Counter::new(9, 2),
+                                                     now:     Local::now(), };
+                assert!(transform.is_numbered());
+                assert!(!Transform::replace("photo").is_numbered());
+                let mut scratch = String::new();
+                let names = ["IMG_7.jpg", "notes.txt", "IMG_8.jpg", "IMG_9.jpg"];
+                let new_names: Vec<_> =
+                        names.iter().filter_map(|name| transform.apply(&matcher, name, &mut scratch)).collect();
+                assert_eq!(new_names, ["photo_009.jpg", "photo_011.jpg", "photo_013.jpg"]);
+                let transform = Transform::RenameTo { template: "{n:02}{ext}".to_string(),
+                                                      counter:  Counter::new(1, -1),
+                                                      now:      Local::now(), };
+                assert_eq!(transform.apply(&matcher, "IMG_1.jpg", &mut scratch).as_deref(), Some("01.jpg"));
+                assert_eq!(transform.apply(&matcher, "IMG_2.jpg", &mut scratch).as_deref(), Some("00.jpg"));
+                assert_eq!(transform.apply(&matcher, "IMG_3.jpg", &mut scratch).as_deref(), Some("-1.jpg"));
+        }
+}
@@ -0,0 +1,186 @@
+//! Checks on new names, before anything is renamed.
+//!
+//! An entry whose new name fails a check is rejected: it stays in the plan (shown with the reason, in previews
+//! too) but is not renamed.  Rejected:
+//! - empty names, `.`, `..`, and names containing a path separator or NUL
+//! - a bare extension (`photo.txt` -> `.txt`), unless `--allow-bare-ext`
+//! - leading or trailing whitespace, or a trailing `.` (invalid on Windows, confusing everywhere), unless the old
+//!   name already had it.  With `--auto-trim` it is trimmed off instead
+//! - turning a visible name into a dotfile or back, unless `--allow-dotfile-change`: a leading `.` hides a file from
+//!   most listings, so it seemingly disappears
+//!
+//! With `--sanitize`, names are first made portable ([`sanitize`]) rather than rejected for what it fixes.
+
+use std::borrow::Cow;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{matcher, plan::Plan};
+
+/// Which otherwise rejected names to allow.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NameRules {
+        pub allow_bare_ext:       bool,
+        pub allow_dotfile_change: bool,
+        /// Trim whitespace and trailing dots off new names instead of rejecting them.
+        pub auto_trim:            bool,
+}
+
+impl NameRules {
+        /// Why `new` is not an acceptable new name for `old`, if it is not.
+        pub fn problem(&self, old: &str, new: &str) -> Option<&'static str> {
+                if new.is_empty() {
+                        return Some("empty name");
+                }
+                if new == "." || new == ".." {
+                        return Some("reserved name");
+                }
+                if new.contains(['/', '\0']) || (cfg!(windows) && new.contains('\\')) {
+                        return Some("contains a path separator; only the last path component can be renamed");
+                }
+                if has_untrimmed_ends(new) && !has_untrimmed_ends(old) {
+                        return Some("leading/trailing whitespace or trailing dot (see --auto-trim)");
+                }
+                let (old_stem, old_ext) = matcher::split_ext(old);
+                if !self.allow_bare_ext && !old_stem.is_empty() && !old.starts_with('.') && new == old_ext {
+                        return Some("only the extension is left (see --allow-bare-ext)");
+                }
+                if !self.allow_dotfile_change && old.starts_with('.') != new.starts_with('.') {
+                        return Some(match new.starts_with('.') {
+                                true => "would become a hidden dotfile (see --allow-dotfile-change)",
+                                false => "would no longer be a hidden dotfile (see --allow-dotfile-change)",
+                        });
+                }
+                None
+        }
+
+        /// Rejects every planned rename with a [`Self::problem`]; returns how many were.
+        pub fn reject_invalid(&self, plan: &mut Plan) -> usize {
+                let mut rejected = 0;
+                for entry in &mut plan.entries {
+                        if self.auto_trim && !has_untrimmed_ends(entry.filename()) {
+                                if let Some(new_filename) = &mut entry.new_filename {
+                                        let trimmed = trim_ends(new_filename);
+                                        if trimmed.len() != new_filename.len() {
+                                                *new_filename = trimmed.to_string();
+                                        }
+                                }
+                        }
+                        let Some(new_filename) = &entry.new_filename else { continue };
+                        if let Some(reason) = self.problem(entry.filename(), new_filename) {
+                                tracing::debug!("Rejected {:?} ~~> {:?}: {}", entry.path, new_filename, reason);
+                                entry.reject(reason);
+                                rejected += 1;
+                        }
+                }
+                rejected
+        }
+}
+
+/// Characters not allowed in a filename on Windows (and so in a portable one); control characters aside.
+const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_STEMS: &[&str] = &["CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+                                  "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8",
+                                  "LPT9"];
+
+/// `--sanitize`: `name`, made valid on every platform (Windows rules being the strictest).
+///
+/// [`ILLEGAL_CHARS`] and control characters become `_`, trailing dots and spaces are trimmed, and a reserved device
+/// name (`CON`, `nul.txt`, ...) gets a `_` after its stem.
+pub fn sanitize(name: &str) -> String {
+        let mut sanitized: String =
+                name.chars().map(|c| if ILLEGAL_CHARS.contains(&c) || c.is_control() { '_' } else { c }).collect();
+        sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+        let (stem, ext) = matcher::split_ext(&sanitized);
+        if RESERVED_STEMS.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+                sanitized = format!("{}_{}", stem, ext);
+        }
+        sanitized
+}
+
+/// `--max-len`: `name` cut to at most `max_len` bytes, keeping its extension.
+///
+/// The stem is cut on a grapheme boundary (so `é` or an emoji sequence is never split), then trimmed of trailing
+/// whitespace and dots.  If the extension alone is too long, the whole name is cut instead.
+pub fn truncate(name: &str, max_len: usize) -> Cow<'_, str> {
+        if name.len() <= max_len {
+                return Cow::Borrowed(name);
+        }
+        let (stem, ext) = match matcher::split_ext(name) {
+                (stem, ext) if ext.len() < max_len => (stem, ext),
+                _ => (name, ""),
+        };
+        let room = max_len - ext.len();
+        let cut = stem.grapheme_indices(true)
+                      .map(|(start, grapheme)| start + grapheme.len())
+                      .take_while(|&end| end <= room)
+                      .last();
+        let stem = trim_ends(&stem[..cut.unwrap_or(0)]);
+        Cow::Owned(format!("{}{}", stem, ext))
+}
+
+/// Whether `name` has whitespace at either end or a trailing dot (`.` and `..` themselves aside).
+fn has_untrimmed_ends(name: &str) -> bool {
+        name != "." && name != ".." && trim_ends(name).len() != name.len()
+}
+
+/// `name` without whitespace at either end or trailing dots.
+fn trim_ends(name: &str) -> &str {
+        name.trim().trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+        use test_log::test;
+
+        use super::*;
+
+        #[test]
+        fn test_problem() {
+                let rules = NameRules::default();
+                assert_eq!(rules.problem("a.txt", ""), Some("empty name"));
+                assert_eq!(rules.problem("a.txt", ".."), Some("reserved name"));
+                assert!(rules.problem("a.txt", "b/a.txt").is_some_and(|reason| reason.contains("path separator")));
+                assert!(rules.problem("photo.txt", ".txt").is_some());
+                assert_eq!(rules.problem("photo.txt", "image.txt"), None);
+                assert_eq!(rules.problem(".bashrc", ".zshrc"), None);
+                assert!(rules.problem("photo.txt", ".photo.txt")
+                             .is_some_and(|reason| reason.contains("become a hidden")));
+                assert!(rules.problem(".env", "env").is_some_and(|reason| reason.contains("no longer")));
+                assert!(rules.problem("a.txt", " a.txt").is_some_and(|reason| reason.contains("whitespace")));
+                assert!(rules.problem("a.txt", "a.").is_some_and(|reason| reason.contains("trailing dot")));
+                assert_eq!(rules.problem("a. ", "b. "), None);
+                assert_eq!(trim_ends(" a .txt. \t"), "a .txt");
+                let permissive = NameRules { allow_bare_ext: true, allow_dotfile_change: true, ..rules };
+                assert_eq!(permissive.problem("photo.txt", ".txt"), None);
+                assert_eq!(permissive.problem(".env", "env"), None);
+        }
+
+        #[test]
+        fn test_truncate() {
+                assert_eq!(truncate("short.txt", 20), "short.txt");
+                assert_eq!(truncate("a_rather_long_name.txt", 10), "a_rath.txt");
+                // `é` is two bytes, and not split
+                assert_eq!(truncate("caféé.txt", 8), "caf.txt");
+                assert_eq!(truncate("cafe\u{301}x.txt", 9), "caf.txt");
+                assert_eq!(truncate("word. more.txt", 10), "word.txt");
+                assert_eq!(truncate("a.verylongextension", 6), "a.very");
+        }
+
+        #[test]
+        fn test_sanitize() {
+                let test_cases = vec![("a<b>c:d.txt", "a_b_c_d.txt"),
+                                      ("what?*.md", "what__.md"),
+                                      ("tab\there.txt", "tab_here.txt"),
+                                      ("dots... ", "dots"),
+                                      ("con.txt", "con_.txt"),
+                                      ("LPT1", "LPT1_"),
+                                      ("console.txt", "console.txt"),
+                                      ("fine name.txt", "fine name.txt")];
+                for (name, expected) in test_cases {
+                        assert_eq!(sanitize(name), expected, "name: {:?}", name);
+                }
+        }
+}
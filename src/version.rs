@@ -0,0 +1,25 @@
+//! `--version` output, optionally (`--verbose`) with build provenance for bug reports.
+//!
+//! Provenance values are captured at compile time by `build.rs`.
+
+/// Target triple the binary was built for.
+pub const TARGET: &str = env!("RENAME_FILES_BUILD_TARGET");
+/// Enabled cargo features, comma separated (`none` if none).
+pub const FEATURES: &str = env!("RENAME_FILES_BUILD_FEATURES");
+/// Git commit built from (`-dirty` if there were uncommitted changes), or `unknown`.
+pub const COMMIT: &str = env!("RENAME_FILES_BUILD_COMMIT");
+/// Cargo profile (`debug` or `release`).
+pub const PROFILE: &str = env!("RENAME_FILES_BUILD_PROFILE");
+
+/// `rename_files <version>`, plus one `key: value` line per provenance item if `is_verbose`.
+pub fn version_text(is_verbose: bool) -> String {
+        let mut text = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        if is_verbose {
+                for (key, value) in
+                        [("target", TARGET), ("features", FEATURES), ("commit", COMMIT), ("profile", PROFILE)]
+                {
+                        text.push_str(&format!("\n{}: {}", key, value));
+                }
+        }
+        text
+}